@@ -1,18 +1,79 @@
 use std::collections::HashMap;
 
+use wh02_lexer::position::Position;
 use wh02_parser::expressions::Expressions;
 use wh02_parser::keyword::Keyword;
+use wh02_parser::operand::Operand;
+use rom_builder::opcodes;
 
 use crate::assembler_error::AssemblerError;
+use crate::memory_map::{MemoryMap, RegionKind, MAX_SIZE};
+
+// Code before the first SECTION belongs here
+pub const DEFAULT_SECTION: &str = "code";
+
+// A label encoded into the program, which a linker has to fill in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reference {
+    pub expression: usize,
+    pub address: usize,
+    pub label: String,
+}
+
+// An opcode as the hex word it is assembled to
+fn opcode(code: u8) -> String {
+    format!("{:02X}", code)
+}
+
+// Logisim's image format, 16 hex words to a line
+pub fn image_text(words: &[String]) -> String {
+    let mut output = String::new();
+    output += "v3.0 hex words addressed\n00: ";
+    let mut counter = 0;
+    let mut address = 0;
+    let len = words.len();
+    for byte in words {
+        output += format!("{} ", byte).as_str();
+        counter += 1;
+        address += 1;
+        if counter % 16 == 0 && address < len {
+            output += format!("\n{:02x}: ", address).as_str();
+        }
+    }
+
+    output
+}
 
 #[derive(Debug, Clone)]
 pub struct Assembler {
     pub expressions: Vec<Expressions>,
     start_index: usize,
-    size: usize,
+    pub memory_map: MemoryMap,
     pub words: HashMap<String, usize>,
     index: usize,
     pub assembled: Vec<String>,
+    // Where each expression starts in the source, if known. Errors from
+    // an expression are reported at its position.
+    pub positions: Vec<Position>,
+    // Address and byte count of each expression once assembled
+    pub addresses: Vec<usize>,
+    pub sizes: Vec<usize>,
+    // Section each expression belongs to
+    pub sections: Vec<String>,
+    // The section being assembled, and the address each other section
+    // left off at
+    section: String,
+    section_ends: HashMap<String, usize>,
+    // Expression that assembled each byte, to catch code placed over
+    // other code
+    owners: Vec<Option<usize>>,
+    // Label references, from the last pass
+    pub references: Vec<Reference>,
+    // When set, labels that are not defined are left for a linker to
+    // fill in rather than being errors
+    pub relocatable: bool,
+    // Set during the first pass, where labels may not be defined yet
+    resolving: bool,
 }
 
 impl Assembler {
@@ -21,44 +82,227 @@ impl Assembler {
         Assembler {
             expressions,
             start_index: 0,
-            size: 256,
+            memory_map: MemoryMap::default(),
             words: HashMap::new(),
             index: 0,
             assembled: Vec::new(),
+            positions: Vec::new(),
+            addresses: Vec::new(),
+            sizes: Vec::new(),
+            sections: Vec::new(),
+            section: DEFAULT_SECTION.to_string(),
+            section_ends: HashMap::new(),
+            owners: Vec::new(),
+            references: Vec::new(),
+            relocatable: false,
+            resolving: false,
         }
     }
 
     pub fn assemble(&mut self) -> Result<String, AssemblerError> {
-        self.assembled = vec!["00".to_string(); self.size];
-        self.index = self.start_index;
+        // The first pass only lays out the program, so that every label
+        // has an address before the second pass encodes references to it
+        self.words.clear();
+        self.resolving = true;
+        self.lay_out()?;
+        self.resolving = false;
+        self.lay_out()?;
+
+        Ok(image_text(&self.assembled))
+    }
+
+    fn lay_out(&mut self) -> Result<(), AssemblerError> {
+        self.assembled = vec!["00".to_string(); self.memory_map.size];
+        self.owners = vec![None; self.memory_map.size];
+        self.section = DEFAULT_SECTION.to_string();
+        self.section_ends.clear();
+        self.index = self.memory_map.region(DEFAULT_SECTION)
+            .map(|region| region.start)
+            .unwrap_or(self.start_index);
+        self.addresses.clear();
+        self.sizes.clear();
+        self.sections.clear();
+        self.references.clear();
+
+        for (i, expr) in self.expressions.clone().into_iter().enumerate() {
+            let position = self.positions.get(i).copied();
+            for (offset, label) in Self::encoded_labels(&expr) {
+                self.references.push(Reference {
+                    expression: i,
+                    address: self.index + offset,
+                    label: label.to_string(),
+                });
+            }
+
+            let result = self.check_write(&expr)
+                .and_then(|_| self.assemble_expression(expr))
+                .map_err(|mut error| {
+                    error.position = position;
+                    error
+                })?;
 
-        for expr in self.expressions.clone() {
-            let result = self.assemble_expression(expr.clone())?;
+            self.addresses.push(self.index);
+            self.sizes.push(if result.is_empty() { 0 } else { result.split(' ').count() });
+            self.sections.push(self.section.clone());
 
-            if result != "" {
+            if !result.is_empty() {
                 let splits = result.split(' ').collect::<Vec<&str>>();
                 for entry in splits {
+                    self.check_code_address(self.index).map_err(|mut error| {
+                        error.position = position;
+                        error
+                    })?;
                     self.assembled[self.index] = entry.to_string();
+                    self.owners[self.index] = Some(i);
                     self.index += 1;
                 }
             }
         }
 
-        let mut output = String::new();
-        output += "v3.0 hex words addressed\n00: ";
-        let mut counter = 0;
-        let mut address = 0;
-        let len = self.assembled.len();
-        for byte in self.assembled.clone() {
-            output += format!("{} ", byte).as_str();
-            counter += 1;
-            address += 1;
-            if counter % 16 == 0 && address < len {
-                output += format!("\n{:02x}: ", address).as_str();
+        Ok(())
+    }
+
+    // Code can go anywhere in memory except reserved regions and code
+    // that is already there. A section with a region of the same name
+    // stays inside it.
+    fn check_code_address(&self, address: usize) -> Result<(), AssemblerError> {
+        if address >= self.memory_map.size {
+            return Err(AssemblerError {
+                message: format!(
+                    "Program does not fit in memory. It needs ${:02X} but memory ends at ${:02X}",
+                    address,
+                    self.memory_map.size.saturating_sub(1),
+                ),
+                position: None,
+            });
+        }
+
+        if let Some(region) = self.memory_map.region(&self.section) {
+            if !region.contains(address) {
+                return Err(AssemblerError {
+                    message: format!("Code at ${:02X} in section {} is outside region {}", address, self.section, region),
+                    position: None,
+                });
             }
         }
 
-        Ok(output)
+        if let Some(owner) = self.owners[address] {
+            return Err(AssemblerError {
+                message: format!(
+                    "Code at ${:02X} overlaps {} in section {} at {}",
+                    address,
+                    self.expressions[owner],
+                    self.sections[owner],
+                    self.positions.get(owner).copied().unwrap_or_default(),
+                ),
+                position: None,
+            });
+        }
+
+        match self.memory_map.region_at(address) {
+            Some(region) if !region.kind.holds_code() => Err(AssemblerError {
+                message: format!("Code at ${:02X} overlaps region {}", address, region),
+                position: None,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // MOV may not write to ROM
+    fn check_write(&self, expr: &Expressions) -> Result<(), AssemblerError> {
+        let destination = match expr {
+            Expressions::BinaryExpression { keyword: Keyword::MOV, operand2, .. } => self.resolve_label(operand2.clone())?,
+            _ => return Ok(()),
+        };
+
+        if destination.indicator != '$' {
+            return Ok(());
+        }
+
+        let address = usize::from_str_radix(&destination.value, 16).unwrap_or(0);
+        match self.memory_map.region_at(address) {
+            Some(region) if region.kind == RegionKind::Rom => Err(AssemblerError {
+                message: format!("MOV writes to ${:02X}, which is in region {}", address, region),
+                position: None,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // The labels an instruction encodes, with the offset of the byte each
+    // is encoded in. Instructions that take a label go here as they are
+    // added.
+    pub fn encoded_labels(expr: &Expressions) -> Vec<(usize, &str)> {
+        match expr {
+            Expressions::UnaryExpression { keyword: Keyword::JMP | Keyword::CALL, operand } if operand.is_label() => vec![(1, &operand.value)],
+            Expressions::BinaryExpression { keyword: Keyword::MOV, operand1, operand2, .. } => {
+                let mut labels = Vec::new();
+                if operand1.is_label() {
+                    labels.push((1, operand1.value.as_str()));
+                }
+                // A memory destination follows the source operand
+                if operand2.is_label() {
+                    labels.push((2, operand2.value.as_str()));
+                }
+                labels
+            },
+            _ => vec![],
+        }
+    }
+
+    // Whether each address holds part of an instruction
+    pub fn used(&self) -> Vec<bool> {
+        let mut used = vec![false; self.memory_map.size];
+        for (address, size) in self.addresses.iter().zip(&self.sizes) {
+            for flag in used.iter_mut().skip(*address).take(*size) {
+                *flag = true;
+            }
+        }
+
+        used
+    }
+
+    // The assembled bytes of one expression, as hex words
+    pub fn expression_bytes(&self, index: usize) -> &[String] {
+        let address = self.addresses[index];
+        &self.assembled[address..address + self.sizes[index]]
+    }
+
+    fn resolve_label(&self, operand: Operand) -> Result<Operand, AssemblerError> {
+        if !operand.is_label() {
+            return Ok(operand);
+        }
+
+        let address = match self.words.get(&operand.value) {
+            Some(address) => *address,
+            // Labels defined further down are filled in on the second
+            // pass, and labels from other files by the linker
+            None if self.resolving || self.relocatable => 0,
+            None => {
+                return Err(
+                    AssemblerError {
+                        message: format!("Undefined label: {}", operand.value),
+                        position: None,
+                    }
+                )
+            }
+        };
+
+        // An address is encoded in one byte. A label after the last byte
+        // of a full memory has no address that fits.
+        if address >= MAX_SIZE {
+            return Err(
+                AssemblerError {
+                    message: format!("Label {} is at ${:02X}, which does not fit in a byte", operand.value, address),
+                    position: None,
+                }
+            )
+        }
+
+        Ok(Operand {
+            indicator: '$',
+            value: format!("{:02X}", address),
+        })
     }
 
     pub fn assemble_expression(&mut self, expr: Expressions) -> Result<String, AssemblerError> {
@@ -78,33 +322,37 @@ impl Assembler {
     fn assemble_binary_expression(&mut self, expr: Expressions) -> Result<String, AssemblerError> {
         match expr {
             Expressions::BinaryExpression { keyword, operand1, operand2, .. } => {
+                let operand1 = self.resolve_label(operand1)?;
+                let operand2 = self.resolve_label(operand2)?;
+
                 match keyword {
                     Keyword::MOV => {
                         let mut result = "".to_string();
                         if operand1.indicator == '#' {
                             if operand2.indicator == '$' {
-                                result += "26";
+                                result += &opcode(opcodes::MOV_BUS_RAM);
                             } else {
                                 match operand2.value.as_str() {
                                     "A" => {
-                                        result += "21";
+                                        result += &opcode(opcodes::MOV_BUS_A);
                                     }
                                     "B" => {
-                                        result += "22";
+                                        result += &opcode(opcodes::MOV_BUS_B);
                                     }
                                     "C" => {
-                                        result += "23";
+                                        result += &opcode(opcodes::MOV_BUS_C);
                                     }
                                     "O1" => {
-                                        result += "24";
+                                        result += &opcode(opcodes::MOV_BUS_O1);
                                     }
                                     "O2" => {
-                                        result += "25";
+                                        result += &opcode(opcodes::MOV_BUS_O2);
                                     }
                                     _ => {
                                         return Err(
                                             AssemblerError {
-                                                message: format!("Found unexpected operand {}. How did we get here?", operand2.value)
+                                                message: format!("Found unexpected operand {}. How did we get here?", operand2.value),
+                                                position: None,
                                             }
                                         )
                                     }
@@ -112,117 +360,125 @@ impl Assembler {
                             }
 
                             result += format!(" {}", operand1.value).as_str();
+                            if operand2.indicator == '$' {
+                                result += format!(" {}", operand2.value).as_str();
+                            }
                         } else if operand1.indicator == '$' {
                             if operand2.indicator == '$' {
-                                result += "1F";
+                                result += &opcode(opcodes::MOV_RAM_RAM);
                             } else {
                                 match operand2.value.as_str() {
                                     "A" => {
-                                        result += "1A";
+                                        result += &opcode(opcodes::MOV_RAM_A);
                                     }
                                     "B" => {
-                                        result += "1B";
+                                        result += &opcode(opcodes::MOV_RAM_B);
                                     }
                                     "C" => {
-                                        result += "1C";
+                                        result += &opcode(opcodes::MOV_RAM_C);
                                     }
                                     "O1" => {
-                                        result += "1D";
+                                        result += &opcode(opcodes::MOV_RAM_O1);
                                     }
                                     "O2" => {
-                                        result += "1E";
+                                        result += &opcode(opcodes::MOV_RAM_O2);
                                     }
                                     _ => {
                                         return Err(
                                             AssemblerError {
-                                                message: format!("Found unexpected operand {}. How did we get here?", operand2.value)
+                                                message: format!("Found unexpected operand {}. How did we get here?", operand2.value),
+                                                position: None,
                                             }
                                         )
                                     }
                                 }
                             }
                         result += format!(" {}", operand1.value).as_str();
+                        if operand2.indicator == '$' {
+                            result += format!(" {}", operand2.value).as_str();
+                        }
                         } else {
                             match (operand1.value.as_str(), operand2.value.as_str()) {
                                 ("A", "B") => {
-                                    result += "01";
+                                    result += &opcode(opcodes::MOV_A_B);
                                 },
                                 ("A", "C") => {
-                                    result += "02";
+                                    result += &opcode(opcodes::MOV_A_C);
                                 },
                                 ("A", "O1") => {
-                                    result += "03";
+                                    result += &opcode(opcodes::MOV_A_O1);
                                 }
                                 ("A", "O2") => {
-                                    result += "04";
+                                    result += &opcode(opcodes::MOV_A_O2);
                                 },
                                 ("B", "A") => {
-                                    result += "06";
+                                    result += &opcode(opcodes::MOV_B_A);
                                 },
                                 ("B", "C") => {
-                                    result += "07";
+                                    result += &opcode(opcodes::MOV_B_C);
                                 },
                                 ("B", "O1") => {
-                                    result += "08";
+                                    result += &opcode(opcodes::MOV_B_O1);
                                 },
                                 ("B", "O2") => {
-                                    result += "09";
+                                    result += &opcode(opcodes::MOV_B_O2);
                                 },
                                 ("C", "A") => {
-                                    result += "0B";
+                                    result += &opcode(opcodes::MOV_C_A);
                                 }
                                 ("C", "B") => {
-                                    result += "0C"
+                                    result += &opcode(opcodes::MOV_C_B)
                                 },
                                 ("C", "O1") => {
-                                    result += "0D";
+                                    result += &opcode(opcodes::MOV_C_O1);
                                 },
                                 ("C", "O2") => {
-                                    result += "0E";
+                                    result += &opcode(opcodes::MOV_C_O2);
                                 },
                                 ("O1", "A") => {
-                                    result += "10";
+                                    result += &opcode(opcodes::MOV_O1_A);
                                 },
                                 ("O1", "B") => {
-                                    result += "11";
+                                    result += &opcode(opcodes::MOV_O1_B);
                                 },
                                 ("O1", "C") => {
-                                    result += "12";
+                                    result += &opcode(opcodes::MOV_O1_C);
                                 },
                                 ("O1", "O2") => {
-                                    result += "13";
+                                    result += &opcode(opcodes::MOV_O1_O2);
                                 },
                                 ("O2", "A") => {
-                                    result += "15";
+                                    result += &opcode(opcodes::MOV_O2_A);
                                 },
                                 ("O2", "B") => {
-                                    result += "16";
+                                    result += &opcode(opcodes::MOV_O2_B);
                                 },
                                 ("O2", "C") => {
-                                    result += "17";
+                                    result += &opcode(opcodes::MOV_O2_C);
                                 },
                                 ("O2", "O1") => {
-                                    result += "18";
+                                    result += &opcode(opcodes::MOV_O2_O1);
                                 },
                                 ("ACC", "A") => {
-                                    result += "27";
+                                    result += &opcode(opcodes::MOV_ACC_A);
                                 },
                                 ("ACC", "B") => {
-                                    result += "28";
+                                    result += &opcode(opcodes::MOV_ACC_B);
                                 },
                                 ("ACC", "C") => {
-                                    result += "29";
+                                    result += &opcode(opcodes::MOV_ACC_C);
                                 },
                                 ("ACC", "O1") => {
-                                    result += "2A";
+                                    result += &opcode(opcodes::MOV_ACC_O1);
                                 },
                                 ("ACC", "O2") => {
-                                    result += "2B";
+                                    result += &opcode(opcodes::MOV_ACC_O2);
                                 },
                                 _ => {
                                     return Err(
                                         AssemblerError {
-                                            message: format!("Found unexpected operand combination {} and {}. How did we get here?", operand1.value, operand2.value)
+                                            message: format!("Found unexpected operand combination {} and {}. How did we get here?", operand1.value, operand2.value),
+                                            position: None,
                                         }
                                     )
                                 }
@@ -238,7 +494,8 @@ impl Assembler {
             _ => {
                 Err(
                     AssemblerError {
-                        message: "Found unexpected expression type. How did we get here?".to_string()
+                        message: "Found unexpected expression type. How did we get here?".to_string(),
+                        position: None,
                     }
                 )
             }
@@ -252,27 +509,153 @@ impl Assembler {
                     Keyword::DEF =>  {
                         // Not actual code for the processor, but sets
                         // a label to a memory address
-                        self.words.insert(operand.value, self.index);
+                        if self.resolving {
+                            if self.words.contains_key(&operand.value) {
+                                return Err(
+                                    AssemblerError {
+                                        message: format!("Label {} is defined more than once", operand.value),
+                                        position: None,
+                                    }
+                                )
+                            }
+
+                            self.words.insert(operand.value, self.index);
+                        }
                         Ok("".to_string())
                     },
                     Keyword::START => {
                         // Not actual code for the processor, but sets
                         // where we start in memory
-                        self.start_index = usize::from_str_radix(&operand.value, 16)
-                            .expect(&format!("Expected valid hex value in 32bit range; found {}", operand.value));
+                        self.start_index = match usize::from_str_radix(&operand.value, 16) {
+                            Ok(address) if address < self.memory_map.size && address < MAX_SIZE => address,
+                            _ => {
+                                return Err(
+                                    AssemblerError {
+                                        message: format!(
+                                            "START expects an address from $00 to ${:02X}; found {}",
+                                            self.memory_map.size.saturating_sub(1),
+                                            operand,
+                                        ),
+                                        position: None,
+                                    }
+                                )
+                            }
+                        };
                         self.index = self.start_index;
                         Ok("".to_string())
                     },
+                    Keyword::ORG => {
+                        // Not actual code either, but moves to another
+                        // address within the current section
+                        self.index = match usize::from_str_radix(&operand.value, 16) {
+                            Ok(address) if address < self.memory_map.size && address < MAX_SIZE => address,
+                            _ => {
+                                return Err(
+                                    AssemblerError {
+                                        message: format!(
+                                            "ORG expects an address from $00 to ${:02X}; found {}",
+                                            self.memory_map.size.saturating_sub(1),
+                                            operand,
+                                        ),
+                                        position: None,
+                                    }
+                                )
+                            }
+                        };
+                        Ok("".to_string())
+                    },
+                    Keyword::SECTION => {
+                        // Switches to another section, which carries on
+                        // where it left off. A new section starts at the
+                        // region with its name, or else right here.
+                        let previous = std::mem::replace(&mut self.section, operand.value.clone());
+                        self.section_ends.insert(previous, self.index);
+                        self.index = match self.section_ends.get(&operand.value) {
+                            Some(end) => *end,
+                            None => self.memory_map.region(&operand.value)
+                                .map(|region| region.start)
+                                .unwrap_or(self.index),
+                        };
+                        Ok("".to_string())
+                    },
+                    // Jumps to the address held in a register
+                    Keyword::JMP if operand.indicator == '@' => {
+                        match operand.value.as_str() {
+                            "A" => Ok(opcode(opcodes::JMP_A)),
+                            "B" => Ok(opcode(opcodes::JMP_B)),
+                            "C" => Ok(opcode(opcodes::JMP_C)),
+                            "ACC" => Ok(opcode(opcodes::JMP_ACC)),
+                            _ => Err(
+                                AssemblerError {
+                                    message: format!("Found unexpected operand {}. How did we get here?", operand.value),
+                                    position: None,
+                                }
+                            ),
+                        }
+                    },
                     Keyword::JMP => {
-                        let mut result = "2D ".to_string();
+                        let operand = self.resolve_label(operand)?;
+                        let mut result = format!("{} ", opcode(opcodes::JMP));
                         result += operand.value.as_str();
 
                         Ok(result)
-                    }
+                    },
+                    Keyword::CALL => {
+                        let operand = self.resolve_label(operand)?;
+                        let mut result = format!("{} ", opcode(opcodes::CALL));
+                        result += operand.value.as_str();
+
+                        Ok(result)
+                    },
+                    // One opcode for each number of places
+                    Keyword::SHL | Keyword::SHR => {
+                        let first = if keyword == Keyword::SHL { opcodes::SHL_1 } else { opcodes::SHR_1 };
+                        match usize::from_str_radix(&operand.value, 16) {
+                            Ok(places) if (1..=7).contains(&places) => Ok(opcode(first + places as u8 - 1)),
+                            _ => Err(
+                                AssemblerError {
+                                    message: format!("Found unexpected operand {}. How did we get here?", operand.value),
+                                    position: None,
+                                }
+                            ),
+                        }
+                    },
+                    Keyword::PUSH => {
+                        match operand.value.as_str() {
+                            "A" => Ok(opcode(opcodes::PUSH_A)),
+                            "B" => Ok(opcode(opcodes::PUSH_B)),
+                            "C" => Ok(opcode(opcodes::PUSH_C)),
+                            "O1" => Ok(opcode(opcodes::PUSH_O1)),
+                            "O2" => Ok(opcode(opcodes::PUSH_O2)),
+                            "ACC" => Ok(opcode(opcodes::PUSH_ACC)),
+                            _ => Err(
+                                AssemblerError {
+                                    message: format!("Found unexpected operand {}. How did we get here?", operand.value),
+                                    position: None,
+                                }
+                            ),
+                        }
+                    },
+                    Keyword::POP => {
+                        match operand.value.as_str() {
+                            "A" => Ok(opcode(opcodes::POP_A)),
+                            "B" => Ok(opcode(opcodes::POP_B)),
+                            "C" => Ok(opcode(opcodes::POP_C)),
+                            "O1" => Ok(opcode(opcodes::POP_O1)),
+                            "O2" => Ok(opcode(opcodes::POP_O2)),
+                            _ => Err(
+                                AssemblerError {
+                                    message: format!("Found unexpected operand {}. How did we get here?", operand.value),
+                                    position: None,
+                                }
+                            ),
+                        }
+                    },
                     _ => {
                         Err(
                             AssemblerError {
-                                message: format!("Found unexpected keyword {}. How did we get here?", keyword)
+                                message: format!("Found unexpected keyword {}. How did we get here?", keyword),
+                                position: None,
                             }
                         )
                     }
@@ -281,7 +664,8 @@ impl Assembler {
             _ => {
                 Err(
                     AssemblerError {
-                        message: "Found unexpected expression type. How did we get here?".to_string()
+                        message: "Found unexpected expression type. How did we get here?".to_string(),
+                        position: None,
                     }
                 )
             }
@@ -292,11 +676,15 @@ impl Assembler {
         match expr {
             Expressions::NoOperandExpression { keyword } => {
                 match keyword {
-                    Keyword::HLT => Ok("20".to_string()),
-                    Keyword::NOP => Ok("00".to_string()),
+                    Keyword::HLT => Ok(opcode(opcodes::HLT)),
+                    Keyword::NOP => Ok(opcode(opcodes::NOP)),
+                    Keyword::RET => Ok(opcode(opcodes::RET)),
+                    Keyword::ADD => Ok(opcode(opcodes::ADD)),
+                    Keyword::SUB => Ok(opcode(opcodes::SUB)),
                     _ => Err(
                         AssemblerError {
-                            message: format!("Found unexpected keyword {}. How did we get here?", keyword)
+                            message: format!("Found unexpected keyword {}. How did we get here?", keyword),
+                            position: None,
                         }
                     )
 
@@ -305,10 +693,91 @@ impl Assembler {
             _ => {
                 Err(
                     AssemblerError {
-                        message: "Found unexpected expression type. How did we get here?".to_string()
+                        message: "Found unexpected expression type. How did we get here?".to_string(),
+                        position: None,
                     }
                 )
             }
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use wh02_lexer::Lexer;
+    use wh02_parser::Parser;
+
+    use super::*;
+
+    fn assembler(source: &str) -> Assembler {
+        let mut parser = Parser::new(Lexer::new(source));
+        parser.parse_all();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        let mut assembler = Assembler::new(parser.expressions);
+        assembler.assemble().unwrap();
+        assembler
+    }
+
+    #[test]
+    fn value_to_memory_encodes_destination() {
+        let assembler = assembler("MOV #05, $F0\nHLT\n");
+        assert_eq!(assembler.assembled[..4], ["26", "05", "F0", "20"]);
+        assert_eq!(assembler.sizes, vec![3, 1]);
+    }
+
+    #[test]
+    fn memory_to_memory_encodes_destination() {
+        let assembler = assembler("MOV $F0, $F1\nHLT\n");
+        assert_eq!(assembler.assembled[..4], ["1F", "F0", "F1", "20"]);
+    }
+
+    #[test]
+    fn destination_label_is_encoded_after_source() {
+        let assembler = assembler("MOV #07, count\nMOV count, total\nHLT\nORG $F0\nDEF count\nNOP\nDEF total\nNOP\n");
+        assert_eq!(assembler.assembled[..7], ["26", "07", "F0", "1F", "F0", "F1", "20"]);
+
+        let references: Vec<(usize, &str)> = assembler.references
+            .iter()
+            .map(|reference| (reference.address, reference.label.as_str()))
+            .collect();
+        assert_eq!(references, vec![(2, "count"), (4, "count"), (5, "total")]);
+    }
+
+    fn assemble_error(source: &str) -> String {
+        let mut parser = Parser::new(Lexer::new(source));
+        parser.parse_all();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        Assembler::new(parser.expressions).assemble().unwrap_err().message
+    }
+
+    #[test]
+    fn rejects_label_past_the_last_address() {
+        assert_eq!(
+            assemble_error("JMP end\nORG $FF\nHLT\nDEF end\n"),
+            "Label end is at $100, which does not fit in a byte",
+        );
+        assert_eq!(
+            assemble_error("MOV #01, end\nORG $FF\nHLT\nDEF end\n"),
+            "Label end is at $100, which does not fit in a byte",
+        );
+    }
+
+    #[test]
+    fn rejects_origin_past_the_last_address() {
+        for keyword in [Keyword::START, Keyword::ORG] {
+            let origin = Expressions::UnaryExpression {
+                keyword: keyword.clone(),
+                operand: Operand {
+                    indicator: '$',
+                    value: "100".to_string(),
+                },
+            };
+
+            // Even when the memory map has been made larger directly
+            let mut assembler = Assembler::new(vec![origin, Expressions::NoOperandExpression { keyword: Keyword::HLT }]);
+            assembler.memory_map = MemoryMap::new(0x200);
+            let error = assembler.assemble().unwrap_err();
+            assert!(error.message.starts_with(&format!("{} expects an address", keyword)), "{}", error);
+        }
+    }
 }
\ No newline at end of file
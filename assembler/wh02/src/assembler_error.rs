@@ -1,12 +1,19 @@
 use core::fmt;
 
+use wh02_lexer::position::Position;
+
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct AssemblerError {
     pub message: String,
+    // Where the expression that failed starts, when the source is known
+    pub position: Option<Position>,
 }
 
 impl fmt::Display for AssemblerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "AssemblerError: {}", self.message)
+        match self.position {
+            Some(position) => write!(f, "AssemblerError at {}: {}", position, self.message),
+            None => write!(f, "AssemblerError: {}", self.message),
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,306 @@
+/*
+    Control-flow graph of an assembled program.
+
+    Instructions are grouped into basic blocks: runs of instructions that
+    always execute together, from the top. A block starts at the first
+    instruction, at every jump or call target, after every instruction
+    that does not fall through and wherever ORG or SECTION moves the
+    code. A call leads both to the subroutine and to the block it returns
+    to. A jump to the address in a register could go to any label, so it
+    leads to every labelled block. Each block knows the source lines it
+    came from and how many clock cycles one pass through it takes,
+    counted from the microcode in `RomBuilder`.
+
+    The graph can be written as Graphviz DOT or as JSON.
+*/
+
+use std::collections::HashMap;
+
+use rom_builder::RomBuilder;
+use wh02_parser::expressions::Expressions;
+use wh02_parser::keyword::Keyword;
+use wh02_parser::operand::Operand;
+
+use crate::assembler::Assembler;
+use crate::json::Json;
+
+// Where execution goes after an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    // On to the next instruction
+    Continue,
+    // To an address, if it could be worked out
+    Jump(Option<usize>),
+    // To the address in a register, which could be any label
+    Indirect,
+    // To a subroutine, which comes back to the next instruction
+    Call(Option<usize>),
+    // Back to wherever the subroutine was called from
+    Return,
+    // Nowhere; the processor stops
+    Halt,
+}
+
+impl Flow {
+    // Branch instructions go here as they are added
+    pub fn of(assembler: &Assembler, expression: usize) -> Flow {
+        match &assembler.expressions[expression] {
+            Expressions::NoOperandExpression { keyword: Keyword::HLT } => Flow::Halt,
+            Expressions::NoOperandExpression { keyword: Keyword::RET } => Flow::Return,
+            Expressions::UnaryExpression { keyword: Keyword::JMP, operand } if operand.indicator == '@' => Flow::Indirect,
+            Expressions::UnaryExpression { keyword: Keyword::JMP, operand } => Flow::Jump(Self::target(assembler, operand)),
+            Expressions::UnaryExpression { keyword: Keyword::CALL, operand } => Flow::Call(Self::target(assembler, operand)),
+            _ => Flow::Continue,
+        }
+    }
+
+    fn target(assembler: &Assembler, operand: &Operand) -> Option<usize> {
+        if operand.is_label() {
+            assembler.words.get(&operand.value).copied()
+        } else {
+            usize::from_str_radix(&operand.value, 16).ok()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CfgInstruction {
+    pub expression: usize,
+    pub address: usize,
+    // Source line, counting from zero
+    pub line: u32,
+    // The instruction as assembled, which differs from the source line
+    // once the optimizer has rewritten it
+    pub source: String,
+    pub bytes: Vec<String>,
+    pub cycles: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    pub id: usize,
+    pub labels: Vec<String>,
+    pub instructions: Vec<CfgInstruction>,
+    pub successors: Vec<usize>,
+    pub cycles: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl BasicBlock {
+    pub fn start(&self) -> usize {
+        self.instructions[0].address
+    }
+
+    // Address of the last byte in the block
+    pub fn end(&self) -> usize {
+        let last = &self.instructions[self.instructions.len() - 1];
+        last.address + last.bytes.len() - 1
+    }
+
+    // Whether execution can stop or leave the program from this block
+    pub fn is_exit(&self) -> bool {
+        self.successors.is_empty()
+    }
+}
+
+impl ControlFlowGraph {
+    // Builds the graph of a program that has been assembled without errors
+    pub fn build(assembler: &Assembler, rom: &RomBuilder) -> ControlFlowGraph {
+        let instructions: Vec<CfgInstruction> = (0..assembler.expressions.len())
+            .filter(|i| assembler.sizes.get(*i).copied().unwrap_or(0) > 0)
+            .map(|i| {
+                let bytes = assembler.expression_bytes(i).to_vec();
+                let opcode = u16::from_str_radix(&bytes[0], 16).unwrap_or(0);
+                let line = assembler.positions.get(i).map(|position| position.line).unwrap_or(0);
+
+                CfgInstruction {
+                    expression: i,
+                    address: assembler.addresses[i],
+                    line,
+                    source: assembler.expressions[i].to_string(),
+                    bytes,
+                    cycles: rom.cycles(opcode),
+                }
+            })
+            .collect();
+
+        let flows: Vec<Flow> = instructions
+            .iter()
+            .map(|instruction| Flow::of(assembler, instruction.expression))
+            .collect();
+
+        let mut leaders = vec![false; instructions.len()];
+        if !leaders.is_empty() {
+            leaders[0] = true;
+        }
+
+        for (i, flow) in flows.iter().enumerate() {
+            // A block also ends where ORG or SECTION moves the next
+            // instruction away from this one
+            let next = instructions.get(i + 1);
+            let adjacent = next.map(|next| next.address == instructions[i].address + instructions[i].bytes.len());
+            if (*flow != Flow::Continue || adjacent == Some(false)) && next.is_some() {
+                leaders[i + 1] = true;
+            }
+
+            if let Flow::Jump(Some(target)) | Flow::Call(Some(target)) = flow {
+                if let Some(j) = instructions.iter().position(|instruction| instruction.address == *target) {
+                    leaders[j] = true;
+                }
+            }
+
+            if *flow == Flow::Indirect {
+                for target in assembler.words.values() {
+                    if let Some(j) = instructions.iter().position(|instruction| instruction.address == *target) {
+                        leaders[j] = true;
+                    }
+                }
+            }
+        }
+
+        let mut blocks: Vec<BasicBlock> = Vec::new();
+        let mut block_flows: Vec<Flow> = Vec::new();
+        for (i, instruction) in instructions.into_iter().enumerate() {
+            if leaders[i] {
+                blocks.push(BasicBlock {
+                    id: blocks.len(),
+                    labels: Vec::new(),
+                    instructions: Vec::new(),
+                    successors: Vec::new(),
+                    cycles: 0,
+                });
+                block_flows.push(Flow::Continue);
+            }
+
+            let block = blocks.last_mut().expect("The first instruction always starts a block");
+            block.cycles += instruction.cycles;
+            block.instructions.push(instruction);
+            *block_flows.last_mut().expect("Every block has a flow") = flows[i];
+        }
+
+        let blocks_by_address: HashMap<usize, usize> = blocks
+            .iter()
+            .map(|block| (block.start(), block.id))
+            .collect();
+
+        for (block, flow) in blocks.iter_mut().zip(block_flows) {
+            match flow {
+                Flow::Continue => {
+                    if let Some(next) = blocks_by_address.get(&(block.end() + 1)) {
+                        block.successors.push(*next);
+                    }
+                },
+                Flow::Jump(Some(target)) => {
+                    if let Some(target) = blocks_by_address.get(&target) {
+                        block.successors.push(*target);
+                    }
+                },
+                Flow::Indirect => {
+                    let mut targets: Vec<usize> = assembler.words
+                        .values()
+                        .filter_map(|target| blocks_by_address.get(target).copied())
+                        .collect();
+                    targets.sort();
+                    targets.dedup();
+                    block.successors.extend(targets);
+                },
+                // The subroutine, then where it returns to
+                Flow::Call(target) => {
+                    if let Some(target) = target.and_then(|target| blocks_by_address.get(&target)) {
+                        block.successors.push(*target);
+                    }
+                    if let Some(next) = blocks_by_address.get(&(block.end() + 1)) {
+                        block.successors.push(*next);
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        let mut labels: Vec<(&String, &usize)> = assembler.words.iter().collect();
+        labels.sort();
+        for (label, address) in labels {
+            if let Some(block) = blocks_by_address.get(address) {
+                blocks[*block].labels.push(label.clone());
+            }
+        }
+
+        ControlFlowGraph {
+            blocks,
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut output = String::new();
+        output += "digraph program {\n";
+        output += "    node [shape=box, fontname=\"monospace\"];\n";
+
+        for block in &self.blocks {
+            let mut label = String::new();
+            for name in &block.labels {
+                label += &format!("{}:\\l", escape_dot(name));
+            }
+            for instruction in &block.instructions {
+                label += &format!(
+                    "{:>3}  ${:02X}  {}\\l",
+                    instruction.line + 1,
+                    instruction.address,
+                    escape_dot(&instruction.source),
+                );
+            }
+            label += &format!("{} cycles\\l", block.cycles);
+
+            let style = if block.is_exit() { ", peripheries=2" } else { "" };
+            output += &format!("    b{} [label=\"{}\"{}];\n", block.id, label, style);
+        }
+
+        for block in &self.blocks {
+            for successor in &block.successors {
+                output += &format!("    b{} -> b{};\n", block.id, successor);
+            }
+        }
+
+        output += "}\n";
+        output
+    }
+
+    pub fn to_json(&self) -> Json {
+        let blocks = self.blocks
+            .iter()
+            .map(|block| {
+                let instructions = block.instructions
+                    .iter()
+                    .map(|instruction| Json::object(vec![
+                        ("address", instruction.address.into()),
+                        ("line", (instruction.line as usize + 1).into()),
+                        ("source", instruction.source.as_str().into()),
+                        ("bytes", instruction.bytes.join(" ").into()),
+                        ("cycles", instruction.cycles.into()),
+                    ]))
+                    .collect::<Vec<Json>>();
+
+                Json::object(vec![
+                    ("id", block.id.into()),
+                    ("labels", block.labels.iter().map(|label| Json::from(label.as_str())).collect::<Vec<Json>>().into()),
+                    ("start", block.start().into()),
+                    ("end", block.end().into()),
+                    ("cycles", block.cycles.into()),
+                    ("instructions", instructions.into()),
+                    ("successors", block.successors.iter().map(|id| Json::from(*id)).collect::<Vec<Json>>().into()),
+                ])
+            })
+            .collect::<Vec<Json>>();
+
+        Json::object(vec![
+            ("blocks", blocks.into()),
+        ])
+    }
+}
\ No newline at end of file
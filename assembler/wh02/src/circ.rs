@@ -0,0 +1,368 @@
+/*
+    Reads and edits Logisim circuit files (.circ).
+
+    A .circ file is XML: each `<circuit>` holds `<comp>` and `<wire>`
+    elements, and each component keeps its settings as
+    `<a name=".." val=".."/>` attributes. Memory contents are the text of a `contents` attribute:
+
+        addr/data: 8 8
+        21 0 22 1 7 28 b 3
+        2d 4 20
+
+    Words are lowercase hex, eight to a line. A run of four or more equal
+    words is written as `count*word`, and zeros at the end are left out.
+
+    Only the parts of XML Logisim writes are understood. Edits replace
+    the text of one component and leave the rest of the file as it was.
+*/
+
+use std::ops::Range;
+
+use crate::circ_error::CircError;
+
+// Kinds of component that hold memory contents
+pub const MEMORIES: [&str; 2] = ["RAM", "ROM"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Component {
+    // The circuit the component is placed in
+    pub circuit: String,
+    // The kind of component, such as RAM or Tunnel
+    pub name: String,
+    pub location: (i64, i64),
+    pub attributes: Vec<(String, String)>,
+    // Where the `<comp>` element is in the file
+    pub span: Range<usize>,
+}
+
+impl Component {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn label(&self) -> Option<&str> {
+        self.attribute("label").filter(|label| !label.is_empty())
+    }
+
+    // Like `RamUnit/RAM at (210,110)`, with the label if it has one
+    pub fn describe(&self) -> String {
+        let name = match self.label() {
+            Some(label) => format!("{} \"{}\"", self.name, label),
+            None => self.name.clone(),
+        };
+        format!("{}/{} at ({},{})", self.circuit, name, self.location.0, self.location.1)
+    }
+
+    // A width attribute of a memory, with Logisim's default of 8 bits
+    pub fn width(&self, name: &str) -> Result<u32, CircError> {
+        match self.attribute(name) {
+            None => Ok(8),
+            Some(value) => value.parse().map_err(|_| CircError {
+                message: format!("{} has an invalid {}: {}", self.describe(), name, value),
+            }),
+        }
+    }
+}
+
+// A straight wire between two points
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Wire {
+    pub from: (i64, i64),
+    pub to: (i64, i64),
+}
+
+#[derive(Debug, Clone)]
+pub struct Circ {
+    pub text: String,
+    pub components: Vec<Component>,
+    // The wires of each circuit, by circuit name
+    pub wires: Vec<(String, Wire)>,
+}
+
+impl Circ {
+    pub fn parse(text: &str) -> Result<Circ, CircError> {
+        let mut components = Vec::new();
+        let mut wires = Vec::new();
+        let mut circuit = None;
+        let mut position = 0;
+
+        while let Some(offset) = text[position..].find('<') {
+            let start = position + offset;
+            let end = match text[start..].find('>') {
+                Some(end) => start + end + 1,
+                None => return Err(error_at(text, start, "Unclosed tag")),
+            };
+            let tag = &text[start..end];
+
+            if tag.starts_with("<circuit ") {
+                circuit = attribute_value(tag, "name");
+            } else if tag.starts_with("</circuit") {
+                circuit = None;
+            } else if tag.starts_with("<wire ") {
+                let point = |key: &str| attribute_value(tag, key).and_then(|point| parse_point(&point));
+                match (&circuit, point("from"), point("to")) {
+                    (Some(circuit), Some(from), Some(to)) => wires.push((circuit.clone(), Wire { from, to })),
+                    _ => return Err(error_at(text, start, "Wire without two ends in a circuit")),
+                }
+            } else if tag.starts_with("<comp ") {
+                let circuit = match &circuit {
+                    Some(circuit) => circuit.clone(),
+                    None => return Err(error_at(text, start, "Component outside a circuit")),
+                };
+
+                let (body, element_end) = if tag.ends_with("/>") {
+                    ("", end)
+                } else {
+                    match text[end..].find("</comp>") {
+                        Some(close) => (&text[end..end + close], end + close + "</comp>".len()),
+                        None => return Err(error_at(text, start, "Unclosed component")),
+                    }
+                };
+
+                components.push(Component {
+                    circuit,
+                    name: attribute_value(tag, "name").unwrap_or_default(),
+                    location: attribute_value(tag, "loc")
+                        .and_then(|loc| parse_point(&loc))
+                        .ok_or_else(|| error_at(text, start, "Component without a location"))?,
+                    attributes: component_attributes(body),
+                    span: start..element_end,
+                });
+
+                position = element_end;
+                continue;
+            }
+
+            position = end;
+        }
+
+        Ok(Circ {
+            text: text.to_string(),
+            components,
+            wires,
+        })
+    }
+
+    // Finds one component from `[circuit/]name`. The name is matched
+    // against labels first, then against the kind of component.
+    pub fn find(&self, spec: &str) -> Result<&Component, CircError> {
+        self.find_among(spec, |_| true)
+    }
+
+    // Finds one RAM or ROM, like `find`. Subcircuits that hold a memory
+    // are often labelled after it, so only memories are looked at.
+    pub fn find_memory(&self, spec: &str) -> Result<&Component, CircError> {
+        self.find_among(spec, |component| MEMORIES.contains(&component.name.as_str()))
+    }
+
+    fn find_among(&self, spec: &str, filter: impl Fn(&Component) -> bool) -> Result<&Component, CircError> {
+        let (circuit, name) = match spec.split_once('/') {
+            Some((circuit, name)) => (Some(circuit), name),
+            None => (None, spec),
+        };
+
+        let in_circuit: Vec<&Component> = self.components
+            .iter()
+            .filter(|component| filter(component))
+            .filter(|component| circuit.map(|circuit| component.circuit == circuit).unwrap_or(true))
+            .collect();
+
+        let mut matches: Vec<&Component> = in_circuit
+            .iter()
+            .filter(|component| component.label() == Some(name))
+            .copied()
+            .collect();
+        if matches.is_empty() {
+            matches = in_circuit
+                .iter()
+                .filter(|component| component.name == name)
+                .copied()
+                .collect();
+        }
+
+        match matches.len() {
+            0 => Err(CircError {
+                message: format!("No component is labelled or named {}", spec),
+            }),
+            1 => Ok(matches[0]),
+            _ => Err(CircError {
+                message: format!(
+                    "{} matches {} components: {}. Give a label, or the circuit as Circuit/{}",
+                    spec,
+                    matches.len(),
+                    matches.iter().map(|component| component.describe()).collect::<Vec<String>>().join(", "),
+                    name,
+                ),
+            }),
+        }
+    }
+
+    // The file with a component's memory contents replaced
+    pub fn with_contents(&self, component: &Component, contents: &str) -> String {
+        let element = &self.text[component.span.clone()];
+        let attribute = format!("<a name=\"contents\">{}</a>", contents);
+
+        let replaced = match element.find("<a name=\"contents\">") {
+            Some(start) => {
+                let end = element[start..].find("</a>").map(|end| start + end + "</a>".len()).unwrap_or(element.len());
+                format!("{}{}{}", &element[..start], attribute, &element[end..])
+            },
+            None => {
+                // Attributes go on their own lines, indented past the tag
+                let indent = self.text[..component.span.start]
+                    .rsplit('\n')
+                    .next()
+                    .unwrap_or_default();
+                match element.strip_suffix("/>") {
+                    Some(tag) => format!("{}>\n{}  {}\n{}</comp>", tag, indent, attribute, indent),
+                    None => {
+                        let close = element.len() - "</comp>".len();
+                        format!("{}  {}\n{}</comp>", &element[..close], attribute, indent)
+                    },
+                }
+            },
+        };
+
+        format!("{}{}{}", &self.text[..component.span.start], replaced, &self.text[component.span.end..])
+    }
+}
+
+// Memory contents in Logisim's encoding
+pub fn encode_contents(address_width: u32, data_width: u32, words: &[u32]) -> String {
+    let mut words = words.to_vec();
+    while words.last() == Some(&0) {
+        words.pop();
+    }
+
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < words.len() {
+        let run = words[index..].iter().take_while(|word| **word == words[index]).count();
+        if run >= 4 {
+            tokens.push(format!("{}*{:x}", run, words[index]));
+            index += run;
+        } else {
+            tokens.push(format!("{:x}", words[index]));
+            index += 1;
+        }
+    }
+
+    let mut output = format!("addr/data: {} {}\n", address_width, data_width);
+    for line in tokens.chunks(8) {
+        output += &line.join(" ");
+        output += "\n";
+    }
+
+    output
+}
+
+// Memory contents as an image file, in the format rom_builder writes
+// rom.bin, so the two can be compared directly
+pub fn memory_image(address_width: u32, data_width: u32, words: &[u32]) -> String {
+    let address_digits = address_width.div_ceil(4) as usize;
+    let data_digits = data_width.div_ceil(4) as usize;
+
+    let mut output = String::new();
+    output += "v3.0 hex words addressed";
+    for (address, word) in words.iter().enumerate() {
+        if address % 16 == 0 {
+            output += &format!("\n{:0width$x}: ", address, width = address_digits);
+        }
+        output += &format!("{:0width$x} ", word, width = data_digits);
+    }
+
+    output
+}
+
+// Memory contents from Logisim's encoding, as the address and data
+// widths and every word
+pub fn decode_contents(text: &str) -> Result<(u32, u32, Vec<u32>), CircError> {
+    let mut lines = text.lines();
+    let header = lines.next().unwrap_or_default();
+    let widths: Vec<u32> = header
+        .strip_prefix("addr/data:")
+        .map(|widths| widths.split_whitespace().filter_map(|width| width.parse().ok()).collect())
+        .unwrap_or_default();
+    if widths.len() != 2 {
+        return Err(CircError {
+            message: format!("Expected memory contents to start with \"addr/data:\", found {}", header),
+        });
+    }
+
+    let invalid = |token: &str| CircError {
+        message: format!("Invalid word in memory contents: {}", token),
+    };
+
+    let mut words = Vec::new();
+    for line in lines {
+        for token in line.split('#').next().unwrap_or_default().split_whitespace() {
+            let (count, word) = match token.split_once('*') {
+                Some((count, word)) => (count.parse::<usize>().map_err(|_| invalid(token))?, word),
+                None => (1, token),
+            };
+            let word = u32::from_str_radix(word, 16).map_err(|_| invalid(token))?;
+            words.extend(std::iter::repeat_n(word, count));
+        }
+    }
+
+    words.resize(1 << widths[0], 0);
+    Ok((widths[0], widths[1], words))
+}
+
+// The attributes in the body of a `<comp>` element
+fn component_attributes(body: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut position = 0;
+    while let Some(offset) = body[position..].find("<a ") {
+        let start = position + offset;
+        let end = match body[start..].find('>') {
+            Some(end) => start + end + 1,
+            None => break,
+        };
+        let tag = &body[start..end];
+        let name = attribute_value(tag, "name").unwrap_or_default();
+
+        // Long values, such as memory contents, are the element's text
+        if tag.ends_with("/>") {
+            attributes.push((name, attribute_value(tag, "val").unwrap_or_default()));
+            position = end;
+        } else {
+            let close = body[end..].find("</a>").map(|close| end + close).unwrap_or(body.len());
+            attributes.push((name, unescape(&body[end..close])));
+            position = close;
+        }
+    }
+
+    attributes
+}
+
+// The value of `key="value"` in a tag
+fn attribute_value(tag: &str, key: &str) -> Option<String> {
+    let pattern = format!(" {}=\"", key);
+    let start = tag.find(&pattern)? + pattern.len();
+    let end = tag[start..].find('"')? + start;
+    Some(unescape(&tag[start..end]))
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+// A location such as `(990,840)`
+fn parse_point(text: &str) -> Option<(i64, i64)> {
+    let (x, y) = text.strip_prefix('(')?.strip_suffix(')')?.split_once(',')?;
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+fn error_at(text: &str, offset: usize, message: &str) -> CircError {
+    CircError {
+        message: format!("{} on line {}", message, text[..offset].matches('\n').count() + 1),
+    }
+}
\ No newline at end of file
@@ -0,0 +1,12 @@
+use core::fmt;
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct CircError {
+    pub message: String,
+}
+
+impl fmt::Display for CircError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CircError: {}", self.message)
+    }
+}
\ No newline at end of file
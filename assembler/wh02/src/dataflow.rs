@@ -0,0 +1,266 @@
+/*
+    What an assembled program does with its registers.
+
+    Each instruction becomes a node with the instructions that can run
+    after it, following JMP targets and falling through otherwise. Over
+    those nodes this works out which instructions can run at all, which
+    registers are set on every path to an instruction, and which
+    registers may still be read after it.
+*/
+
+use std::collections::HashMap;
+
+use wh02_parser::expressions::Expressions;
+use wh02_parser::keyword::Keyword;
+use wh02_parser::operand::Operand;
+
+use crate::assembler::Assembler;
+use crate::cfg::Flow;
+
+// Registers as bits of a set
+pub const REGISTERS: [(&str, u8); 5] = [("A", 1), ("B", 2), ("C", 4), ("O1", 8), ("O2", 16)];
+pub const ALL_REGISTERS: u8 = 0b11111;
+// The registers the ALU adds to produce @ACC
+pub const ALU_INPUTS: u8 = 0b00011;
+// The register the ALU shifts
+pub const SHIFT_INPUT: u8 = 0b00001;
+// The output registers are shown on the board, so writing them is never
+// wasted even if nothing reads them back
+pub const WORKING_REGISTERS: u8 = 0b00111;
+
+// An instruction, and what it does to control flow and registers
+#[derive(Debug, Clone, Default)]
+pub struct Node {
+    pub expression: usize,
+    pub successors: Vec<usize>,
+    // Execution continues past the last instruction
+    pub falls_off: bool,
+    // Stops here with HLT or jumps somewhere that is not an instruction
+    pub exits: bool,
+    // Jumps to an address that is not the start of an instruction
+    pub misaligned: bool,
+    pub reads: u8,
+    pub writes: u8,
+    pub reads_acc: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct DataFlow {
+    pub nodes: Vec<Node>,
+    pub reachable: Vec<bool>,
+    // Registers written on every path to each node, before it runs
+    pub set: Vec<u8>,
+    // Registers that may be read after each node runs, before being
+    // written again. Everything counts as read once the program stops.
+    pub live: Vec<u8>,
+}
+
+pub fn register(operand: &Operand) -> u8 {
+    if operand.indicator != '@' {
+        return 0;
+    }
+
+    REGISTERS.iter()
+        .find(|(name, _)| *name == operand.value)
+        .map(|(_, bit)| *bit)
+        .unwrap_or(0)
+}
+
+pub fn register_names(registers: u8) -> String {
+    REGISTERS.iter()
+        .filter(|(_, bit)| registers & bit != 0)
+        .map(|(name, _)| format!("@{}", name))
+        .collect::<Vec<String>>()
+        .join(" and ")
+}
+
+impl DataFlow {
+    // Works on a program that has been assembled without errors
+    pub fn new(assembler: &Assembler) -> DataFlow {
+        let nodes = Self::build_nodes(assembler);
+        let reachable = Self::reachable(&nodes);
+        let set = Self::registers_set(&nodes, &reachable);
+        let live = Self::registers_live(&nodes, &reachable);
+
+        DataFlow {
+            nodes,
+            reachable,
+            set,
+            live,
+        }
+    }
+
+    // The node for an expression, if it is an instruction
+    pub fn node(&self, expression: usize) -> Option<usize> {
+        self.nodes.iter().position(|node| node.expression == expression)
+    }
+
+    fn build_nodes(assembler: &Assembler) -> Vec<Node> {
+        // Expressions that take up space are instructions
+        let instructions: Vec<usize> = (0..assembler.expressions.len())
+            .filter(|i| assembler.sizes.get(*i).copied().unwrap_or(0) > 0)
+            .collect();
+
+        let nodes_by_address: HashMap<usize, usize> = instructions
+            .iter()
+            .enumerate()
+            .map(|(node, expression)| (assembler.addresses[*expression], node))
+            .collect();
+
+        let mut nodes = Vec::new();
+        for expression in &instructions {
+            let mut node = Node {
+                expression: *expression,
+                ..Default::default()
+            };
+
+            let next_address = assembler.addresses[*expression] + assembler.sizes[*expression];
+            match Flow::of(assembler, *expression) {
+                // The next instruction is the one at the next address,
+                // which ORG and SECTION can move away
+                Flow::Continue => match nodes_by_address.get(&next_address) {
+                    Some(next) => node.successors.push(*next),
+                    None => node.falls_off = true,
+                },
+                Flow::Jump(target) => match target.and_then(|target| nodes_by_address.get(&target)) {
+                    Some(target) => node.successors.push(*target),
+                    None => {
+                        node.exits = true;
+                        node.misaligned = true;
+                    }
+                },
+                // Any label could be in the register
+                Flow::Indirect => {
+                    let mut targets: Vec<usize> = assembler.words
+                        .values()
+                        .filter_map(|target| nodes_by_address.get(target).copied())
+                        .collect();
+                    targets.sort();
+                    targets.dedup();
+                    node.successors.extend(targets);
+                },
+                // Registers the subroutine reads count as read by the
+                // call, but registers it writes are not counted as set
+                Flow::Call(target) => {
+                    match target.and_then(|target| nodes_by_address.get(&target)) {
+                        Some(target) => node.successors.push(*target),
+                        None => node.misaligned = true,
+                    }
+                    match nodes_by_address.get(&next_address) {
+                        Some(next) => node.successors.push(*next),
+                        None => node.falls_off = true,
+                    }
+                },
+                // Where a subroutine returns to is not followed, so
+                // everything counts as read afterwards
+                Flow::Return | Flow::Halt => node.exits = true,
+            }
+
+            let (read, written) = match &assembler.expressions[*expression] {
+                Expressions::BinaryExpression { keyword: Keyword::MOV, operand1, operand2, .. } => (Some(operand1), Some(operand2)),
+                Expressions::UnaryExpression { keyword: Keyword::PUSH, operand } => (Some(operand), None),
+                Expressions::UnaryExpression { keyword: Keyword::JMP, operand } if operand.indicator == '@' => (Some(operand), None),
+                Expressions::UnaryExpression { keyword: Keyword::POP, operand } => (None, Some(operand)),
+                _ => (None, None),
+            };
+
+            if let Some(operand) = read {
+                node.reads = register(operand);
+                if operand.indicator == '@' && operand.value == "ACC" {
+                    node.reads_acc = true;
+                    node.reads |= ALU_INPUTS;
+                }
+            }
+
+            if let Some(operand) = written {
+                node.writes = register(operand);
+            }
+
+            // ALU operations latch ACC from A and B, or shift A alone
+            match &assembler.expressions[*expression] {
+                Expressions::NoOperandExpression { keyword: Keyword::ADD | Keyword::SUB } => node.reads |= ALU_INPUTS,
+                Expressions::UnaryExpression { keyword: Keyword::SHL | Keyword::SHR, .. } => node.reads |= SHIFT_INPUT,
+                _ => {},
+            }
+
+            nodes.push(node);
+        }
+
+        nodes
+    }
+
+    fn reachable(nodes: &[Node]) -> Vec<bool> {
+        let mut reachable = vec![false; nodes.len()];
+        let mut pending = Vec::new();
+        if !nodes.is_empty() {
+            pending.push(0);
+        }
+
+        while let Some(node) = pending.pop() {
+            if reachable[node] {
+                continue;
+            }
+            reachable[node] = true;
+            pending.extend(nodes[node].successors.iter().copied());
+        }
+
+        reachable
+    }
+
+    fn registers_set(nodes: &[Node], reachable: &[bool]) -> Vec<u8> {
+        let mut set = vec![ALL_REGISTERS; nodes.len()];
+        if !nodes.is_empty() {
+            set[0] = 0;
+        }
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, node) in nodes.iter().enumerate() {
+                if !reachable[i] {
+                    continue;
+                }
+
+                let after = set[i] | node.writes;
+                for successor in &node.successors {
+                    let before = if *successor == 0 { 0 } else { set[*successor] & after };
+                    if before != set[*successor] {
+                        set[*successor] = before;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        set
+    }
+
+    fn registers_live(nodes: &[Node], reachable: &[bool]) -> Vec<u8> {
+        let mut live_in = vec![0u8; nodes.len()];
+        let mut live_out = vec![0u8; nodes.len()];
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, node) in nodes.iter().enumerate().rev() {
+                if !reachable[i] {
+                    continue;
+                }
+
+                let mut out = node.successors.iter().fold(0, |live, successor| live | live_in[*successor]);
+                if node.exits || node.falls_off {
+                    out = ALL_REGISTERS;
+                }
+
+                let before = node.reads | (out & !node.writes);
+                if out != live_out[i] || before != live_in[i] {
+                    live_out[i] = out;
+                    live_in[i] = before;
+                    changed = true;
+                }
+            }
+        }
+
+        live_out
+    }
+}
\ No newline at end of file
@@ -0,0 +1,266 @@
+use wh02_lexer::enumerations::TokenType;
+use wh02_parser::ast::{Instruction, Line, Program};
+use wh02_parser::cst;
+use wh02_parser::keyword::Keyword;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    Upper,
+    Lower,
+}
+
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    // Casing for mnemonics, directives and register names
+    pub case: Case,
+    // Column that trailing comments start at, counting from zero
+    pub comment_column: usize,
+    // Spaces to indent instructions under a label
+    pub indent: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            case: Case::Upper,
+            comment_column: 24,
+            indent: 4,
+        }
+    }
+}
+
+// One source line after formatting, before indentation is decided for
+// lines that hold only a comment
+struct FormattedLine {
+    code: Option<String>,
+    comment: Option<String>,
+    indent: usize,
+    verbatim: Option<String>,
+    blank_lines: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Formatter {
+    pub options: FormatOptions,
+}
+
+impl Formatter {
+    pub fn new(options: FormatOptions) -> Self {
+        Formatter {
+            options,
+        }
+    }
+
+    pub fn format(&self, source: &str) -> String {
+        let tree = cst::parse(source);
+        let program = Program::cast(&tree).expect("cst::parse always returns a program");
+
+        let mut lines: Vec<FormattedLine> = Vec::new();
+        let mut under_label = false;
+
+        for line in program.lines() {
+            let newlines = line.newline()
+                .map(|newline| newline.value.matches('\n').count())
+                .unwrap_or(0);
+            let blank_lines = newlines.saturating_sub(1);
+
+            if line.is_blank() && line.unknown().is_empty() {
+                // Whitespace-only lines count as blank lines of the line before
+                if let Some(previous) = lines.last_mut() {
+                    previous.blank_lines += newlines;
+                }
+                continue;
+            }
+
+            if !line.unknown().is_empty() || !Self::has_well_formed_operands(&line) {
+                // Leave lines we cannot make sense of alone rather than guess
+                let text = line.node.text();
+                lines.push(FormattedLine {
+                    code: None,
+                    comment: None,
+                    indent: 0,
+                    verbatim: Some(text.trim_end().to_string()),
+                    blank_lines,
+                });
+                continue;
+            }
+
+            let keyword = line.instruction().and_then(|instruction| instruction.keyword());
+            let starts_block = line.label().is_some() || keyword == Some(Keyword::DEF);
+            let is_directive = matches!(keyword, Some(Keyword::START | Keyword::ORG | Keyword::SECTION));
+
+            let indent = if starts_block || is_directive || !under_label {
+                0
+            } else {
+                self.options.indent
+            };
+
+            if starts_block {
+                under_label = true;
+            } else if keyword == Some(Keyword::SECTION) {
+                // Labels do not carry over into a new section
+                under_label = false;
+            }
+
+            lines.push(FormattedLine {
+                code: self.format_code(&line),
+                comment: line.comment().map(|comment| comment.value.clone()),
+                indent,
+                verbatim: None,
+                blank_lines,
+            });
+        }
+
+        // A line holding only a comment describes the code after it, so
+        // it takes that line's indentation
+        let mut next_indent = 0;
+        for line in lines.iter_mut().rev() {
+            if line.code.is_some() {
+                next_indent = line.indent;
+            } else if line.verbatim.is_none() {
+                line.indent = next_indent;
+            }
+        }
+
+        let mut output = String::new();
+        for line in &lines {
+            if let Some(verbatim) = &line.verbatim {
+                output += verbatim;
+            } else {
+                let mut text = " ".repeat(line.indent);
+                if let Some(code) = &line.code {
+                    text += code;
+                }
+
+                if let Some(comment) = &line.comment {
+                    if line.code.is_some() {
+                        let padding = self.options.comment_column.saturating_sub(text.len()).max(1);
+                        text += &" ".repeat(padding);
+                    }
+                    text += comment;
+                }
+
+                output += &text;
+            }
+
+            output += "\n";
+            // Runs of blank lines collapse to one
+            if line.blank_lines > 0 {
+                output += "\n";
+            }
+        }
+
+        // Blank lines at the end of the file are dropped
+        while output.ends_with("\n\n") {
+            output.pop();
+        }
+
+        output
+    }
+
+    fn has_well_formed_operands(line: &Line) -> bool {
+        match line.instruction() {
+            Some(instruction) => {
+                let operands = instruction.operands().len();
+                operands == 0 || instruction.commas().len() + 1 == operands
+            },
+            None => true,
+        }
+    }
+
+    fn format_code(&self, line: &Line) -> Option<String> {
+        let label = line.label().map(|label| format!("{}:", label.name().value));
+        let instruction = line.instruction().map(|instruction| self.format_instruction(&instruction));
+
+        match (label, instruction) {
+            (Some(label), Some(instruction)) => {
+                let padding = self.options.indent.saturating_sub(label.len()).max(1);
+                Some(format!("{}{}{}", label, " ".repeat(padding), instruction))
+            },
+            (Some(label), None) => Some(label),
+            (None, Some(instruction)) => Some(instruction),
+            (None, None) => None,
+        }
+    }
+
+    fn format_instruction(&self, instruction: &Instruction) -> String {
+        let mut text = self.apply_case(&instruction.mnemonic().value);
+
+        let operands: Vec<String> = instruction.operands()
+            .iter()
+            .map(|operand| {
+                let token = operand.token();
+                match token.token_type {
+                    TokenType::Location => self.apply_case(&token.value),
+                    TokenType::Hex | TokenType::Address => token.value.to_ascii_uppercase(),
+                    _ => token.value.clone(),
+                }
+            })
+            .collect();
+
+        if !operands.is_empty() {
+            text += " ";
+            text += &operands.join(", ");
+        }
+
+        text
+    }
+
+    fn apply_case(&self, text: &str) -> String {
+        match self.options.case {
+            Case::Upper => text.to_ascii_uppercase(),
+            Case::Lower => text.to_ascii_lowercase(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(source: &str) -> String {
+        Formatter::new(FormatOptions::default()).format(source)
+    }
+
+    #[test]
+    fn formats_in_the_standard_style() {
+        let source = "start $00\nmain:\nmov #01,@a   ; one\n\n\n\njmp   main\n";
+        assert_eq!(format(source), "START $00\nmain:\n    MOV #01, @A         ; one\n\n    JMP main\n");
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let sources = [
+            include_str!("../../../programs/fib.asm"),
+            "main:\nmov #01,@a   ; one\n\n\n\njmp   main\n",
+            "; header\r\n\r\nloop: MOV @B,@C\r\n   ; body\r\n  JMP loop",
+            ".local:   NOP\nDEF table\nSECTION data\n   ORG $F0\nMOV $F0,   $F1;store\n",
+            "MOV #1G, @A\nMOV @A,, @B\n    HLT\n",
+        ];
+
+        for source in sources {
+            let once = format(source);
+            assert_eq!(format(&once), once, "formatting {:?}", source);
+        }
+    }
+
+    #[test]
+    fn keeps_comments_and_labels() {
+        let source = "; Counts down\nmain:\n.loop: MOV @B, @C ; body\n  ; before the jump\nJMP .loop\ndone: HLT\n";
+        let formatted = format(source);
+
+        for kept in ["; Counts down", "main:", ".loop:", "; body", "; before the jump", "JMP .loop", "done:"] {
+            assert!(formatted.contains(kept), "{:?} lost {:?}", formatted, kept);
+        }
+
+        // Both the label and the instruction sharing its line survive
+        assert!(formatted.contains(".loop: MOV @B, @C"), "{:?}", formatted);
+        assert_eq!(formatted.lines().count(), source.lines().count());
+    }
+
+    #[test]
+    fn leaves_lines_it_cannot_parse() {
+        let formatted = format("MOV #1G,@A\nmov @a,@b\n");
+        assert_eq!(formatted, "MOV #1G,@A\nMOV @A, @B\n");
+    }
+}
\ No newline at end of file
@@ -0,0 +1,363 @@
+/*
+    A small JSON value type with a parser and a compact printer.
+
+    This covers what the tools in this repo exchange with editors and
+    other programs (language server messages, graph exports) without
+    pulling in a dependency. Objects keep their keys in insertion order
+    so that output is stable.
+*/
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use crate::json_error::JsonError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(entries: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    pub fn parse(text: &str) -> Result<Json, JsonError> {
+        let mut reader = Reader {
+            text,
+            characters: text.char_indices().peekable(),
+        };
+
+        let value = reader.value()?;
+        reader.skip_whitespace();
+        if let Some((offset, c)) = reader.characters.peek() {
+            return Err(JsonError {
+                message: format!("Unexpected {} after the value", c),
+                offset: *offset,
+            });
+        }
+
+        Ok(value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(value) if *value >= 0.0 && value.fract() == 0.0 => Some(*value as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == Json::Null
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<u32> for Json {
+    fn from(value: u32) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(values: Vec<Json>) -> Self {
+        Json::Array(values)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(value) => {
+                if value.fract() == 0.0 && value.abs() < 1e15 {
+                    write!(f, "{}", *value as i64)
+                } else {
+                    write!(f, "{}", value)
+                }
+            },
+            Json::String(value) => write_string(f, value),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+struct Reader<'a> {
+    text: &'a str,
+    characters: Peekable<CharIndices<'a>>,
+}
+
+impl<'a> Reader<'a> {
+    fn offset(&mut self) -> usize {
+        match self.characters.peek() {
+            Some((offset, _)) => *offset,
+            None => self.text.len(),
+        }
+    }
+
+    fn error(&mut self, message: String) -> JsonError {
+        JsonError {
+            message,
+            offset: self.offset(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some((_, c)) = self.characters.peek() {
+            if !c.is_ascii_whitespace() {
+                break;
+            }
+            self.characters.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), JsonError> {
+        match self.characters.next() {
+            Some((_, c)) if c == expected => Ok(()),
+            Some((offset, c)) => Err(JsonError {
+                message: format!("Expected {}, found {}", expected, c),
+                offset,
+            }),
+            None => Err(self.error(format!("Expected {}, found the end of the text", expected))),
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, JsonError> {
+        for expected in word.chars() {
+            self.expect(expected)?;
+        }
+
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.skip_whitespace();
+        match self.characters.peek().map(|(_, c)| *c) {
+            Some('n') => self.keyword("null", Json::Null),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) => Err(self.error(format!("Unexpected {}", c))),
+            None => Err(self.error("Expected a value, found the end of the text".to_string())),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.offset();
+        while let Some((_, c)) = self.characters.peek() {
+            if !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+                break;
+            }
+            self.characters.next();
+        }
+
+        let end = self.offset();
+        match self.text[start..end].parse::<f64>() {
+            Ok(number) => Ok(Json::Number(number)),
+            Err(_) => Err(JsonError {
+                message: format!("Invalid number: {}", &self.text[start..end]),
+                offset: start,
+            }),
+        }
+    }
+
+    fn hex_escape(&mut self) -> Result<u32, JsonError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            match self.characters.next().and_then(|(_, c)| c.to_digit(16)) {
+                Some(digit) => code = code * 16 + digit,
+                None => return Err(self.error("Invalid \\u escape".to_string())),
+            }
+        }
+
+        Ok(code)
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            match self.characters.next() {
+                Some((_, '"')) => return Ok(value),
+                Some((_, '\\')) => {
+                    let escaped = match self.characters.next() {
+                        Some((_, 'n')) => '\n',
+                        Some((_, 'r')) => '\r',
+                        Some((_, 't')) => '\t',
+                        Some((_, 'b')) => '\u{8}',
+                        Some((_, 'f')) => '\u{c}',
+                        Some((_, 'u')) => {
+                            let mut code = self.hex_escape()?;
+                            // Characters outside the BMP come as a surrogate pair
+                            if (0xD800..0xDC00).contains(&code) {
+                                self.expect('\\')?;
+                                self.expect('u')?;
+                                let low = self.hex_escape()?;
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                            }
+                            char::from_u32(code).unwrap_or('\u{FFFD}')
+                        },
+                        Some((_, c)) => c,
+                        None => return Err(self.error("Unterminated string".to_string())),
+                    };
+                    value.push(escaped);
+                },
+                Some((_, c)) => value.push(c),
+                None => return Err(self.error("Unterminated string".to_string())),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, JsonError> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+
+        self.skip_whitespace();
+        if let Some((_, ']')) = self.characters.peek() {
+            self.characters.next();
+            return Ok(Json::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.characters.next() {
+                Some((_, ',')) => {},
+                Some((_, ']')) => return Ok(Json::Array(values)),
+                _ => return Err(self.error("Expected , or ] in array".to_string())),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, JsonError> {
+        self.expect('{')?;
+        let mut entries = Vec::new();
+
+        self.skip_whitespace();
+        if let Some((_, '}')) = self.characters.peek() {
+            self.characters.next();
+            return Ok(Json::Object(entries));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.value()?;
+            entries.push((key, value));
+
+            self.skip_whitespace();
+            match self.characters.next() {
+                Some((_, ',')) => {},
+                Some((_, '}')) => return Ok(Json::Object(entries)),
+                _ => return Err(self.error("Expected , or } in object".to_string())),
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,14 @@
+use core::fmt;
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct JsonError {
+    pub message: String,
+    // Byte offset into the text being parsed
+    pub offset: usize,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "JsonError at byte {}: {}", self.offset, self.message)
+    }
+}
\ No newline at end of file
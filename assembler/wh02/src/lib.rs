@@ -0,0 +1,19 @@
+pub mod assembler;
+pub mod assembler_error;
+pub mod cfg;
+pub mod circ;
+pub mod circ_error;
+pub mod dataflow;
+pub mod formatter;
+pub mod json;
+pub mod json_error;
+pub mod lint;
+pub mod lint_warning;
+pub mod linter;
+pub mod memory_map;
+pub mod memory_map_error;
+pub mod object;
+pub mod object_error;
+pub mod optimizer;
+pub mod stats;
+pub mod wiring;
\ No newline at end of file
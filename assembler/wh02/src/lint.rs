@@ -0,0 +1,71 @@
+use std::fmt;
+
+// A kind of suspicious code that `wh02 lint` reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    UnreachableCode,
+    UninitializedAcc,
+    DeadStore,
+    UnusedLabel,
+    MissingHlt,
+    MisalignedJump,
+    UnknownLint,
+}
+
+// What to do when a lint fires
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Lint {
+    pub fn all() -> Vec<Lint> {
+        vec![
+            Lint::UnreachableCode,
+            Lint::UninitializedAcc,
+            Lint::DeadStore,
+            Lint::UnusedLabel,
+            Lint::MissingHlt,
+            Lint::MisalignedJump,
+            Lint::UnknownLint,
+        ]
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::all().into_iter().find(|lint| lint.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Lint::UnreachableCode => "unreachable_code",
+            Lint::UninitializedAcc => "uninitialized_acc",
+            Lint::DeadStore => "dead_store",
+            Lint::UnusedLabel => "unused_label",
+            Lint::MissingHlt => "missing_hlt",
+            Lint::MisalignedJump => "misaligned_jump",
+            Lint::UnknownLint => "unknown_lint",
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for lint in Lint::all() {
+            assert_eq!(Lint::from_name(lint.name()), Some(lint));
+        }
+        assert_eq!(Lint::from_name("dead-store"), None);
+        assert_eq!(Lint::from_name("DEAD_STORE"), None);
+    }
+}
\ No newline at end of file
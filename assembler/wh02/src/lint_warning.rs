@@ -0,0 +1,20 @@
+use core::fmt;
+
+use wh02_lexer::position::Position;
+
+use crate::lint::{Level, Lint};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintWarning {
+    pub lint: Lint,
+    // Warn or Deny; allowed lints are not reported
+    pub level: Level,
+    pub position: Position,
+    pub message: String,
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}: {}", self.lint, self.position, self.message)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,361 @@
+/*
+    Finds code that assembles but is probably not what was meant.
+
+    The linter works on an assembled program, using `DataFlow` to find
+    code that cannot run and what happens to each register.
+
+    Lints can be turned off or made fatal in the source:
+
+        ; wh02: allow(unused_label)
+        MOV #00, @A     ; wh02: deny(dead_store)
+
+    A comment on a line with code applies to that line, and a comment on
+    its own line applies to the next line with code. Comments before the
+    first line of code apply to the whole file.
+*/
+
+use std::collections::HashMap;
+
+use wh02_lexer::position::Position;
+use wh02_parser::ast::Program;
+use wh02_parser::cst;
+use wh02_parser::expressions::Expressions;
+use wh02_parser::keyword::Keyword;
+
+use crate::assembler::Assembler;
+use crate::dataflow::{register_names, DataFlow, ALU_INPUTS, WORKING_REGISTERS};
+use crate::lint::{Level, Lint};
+use crate::lint_warning::LintWarning;
+
+// Levels set by `wh02:` comments
+#[derive(Debug, Clone, Default)]
+struct Directives {
+    file: HashMap<Lint, Level>,
+    lines: HashMap<u32, HashMap<Lint, Level>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Linter {
+    // Levels from the command line. Comments in the source take precedence.
+    pub levels: HashMap<Lint, Level>,
+    directives: Directives,
+    warnings: Vec<LintWarning>,
+}
+
+impl Linter {
+    pub fn new(levels: HashMap<Lint, Level>) -> Self {
+        Linter {
+            levels,
+            ..Default::default()
+        }
+    }
+
+    // Lints a program that has been assembled without errors
+    pub fn lint(&mut self, source: &str, assembler: &Assembler) -> Vec<LintWarning> {
+        self.warnings.clear();
+        self.read_directives(source);
+
+        let position = |expression: usize| assembler.positions.get(expression).copied().unwrap_or_default();
+        let flow = DataFlow::new(assembler);
+
+        let mut previous_reachable = true;
+        for (i, node) in flow.nodes.iter().enumerate() {
+            if !flow.reachable[i] && previous_reachable {
+                self.report(Lint::UnreachableCode, position(node.expression), "This code can never run".to_string());
+            }
+            previous_reachable = flow.reachable[i];
+
+            if node.misaligned {
+                let (keyword, target) = match &assembler.expressions[node.expression] {
+                    Expressions::UnaryExpression { keyword, operand } => (keyword.to_string(), operand.to_string()),
+                    _ => ("JMP".to_string(), "address".to_string()),
+                };
+                self.report(
+                    Lint::MisalignedJump,
+                    position(node.expression),
+                    format!("{} target {} is not the start of an instruction", keyword, target),
+                );
+            }
+
+            if !flow.reachable[i] {
+                continue;
+            }
+
+            if node.falls_off {
+                self.report(
+                    Lint::MissingHlt,
+                    position(node.expression),
+                    "Execution runs past the end of the program. End it with HLT".to_string(),
+                );
+            }
+
+            let unset = ALU_INPUTS & !flow.set[i];
+            if node.reads_acc && unset != 0 {
+                self.report(
+                    Lint::UninitializedAcc,
+                    position(node.expression),
+                    format!("@ACC is read before {} {} set", register_names(unset), if unset.count_ones() == 1 { "is" } else { "are" }),
+                );
+            }
+
+            let dead = node.writes & WORKING_REGISTERS & !flow.live[i];
+            if dead != 0 {
+                self.report(
+                    Lint::DeadStore,
+                    position(node.expression),
+                    format!("The value written to {} is never read", register_names(dead)),
+                );
+            }
+        }
+
+        self.check_labels(assembler);
+
+        self.warnings.sort_by_key(|warning| warning.position.offset);
+        self.warnings.clone()
+    }
+
+    fn check_labels(&mut self, assembler: &Assembler) {
+        let referenced: Vec<&String> = assembler.expressions
+            .iter()
+            .flat_map(|expression| match expression {
+                Expressions::UnaryExpression { keyword: Keyword::DEF | Keyword::SECTION, .. } => vec![],
+                Expressions::UnaryExpression { operand, .. } => vec![operand],
+                Expressions::BinaryExpression { operand1, operand2, .. } => vec![operand1, operand2],
+                Expressions::NoOperandExpression { .. } => vec![],
+            })
+            .filter(|operand| operand.is_label())
+            .map(|operand| &operand.value)
+            .collect();
+
+        for (i, expression) in assembler.expressions.iter().enumerate() {
+            if let Expressions::UnaryExpression { keyword: Keyword::DEF, operand } = expression {
+                if !referenced.contains(&&operand.value) {
+                    self.report(
+                        Lint::UnusedLabel,
+                        assembler.positions.get(i).copied().unwrap_or_default(),
+                        format!("Label {} is never referenced", operand.value),
+                    );
+                }
+            }
+        }
+    }
+
+    fn read_directives(&mut self, source: &str) {
+        self.directives = Directives::default();
+
+        let tree = cst::parse(source);
+        let program = Program::cast(&tree).expect("cst::parse always returns a program");
+
+        let mut seen_code = false;
+        let mut pending: Vec<(Lint, Level)> = Vec::new();
+
+        for line in program.lines() {
+            let has_code = line.label().is_some() || line.instruction().is_some();
+            let number = line.node.span().start.line;
+
+            if let Some(comment) = line.comment() {
+                let levels = self.parse_directive(&comment.value, comment.span.start);
+                if has_code {
+                    self.directives.lines.entry(number).or_default().extend(levels);
+                } else if !seen_code {
+                    self.directives.file.extend(levels);
+                } else {
+                    pending.extend(levels);
+                }
+            }
+
+            if has_code {
+                seen_code = true;
+                self.directives.lines.entry(number).or_default().extend(pending.drain(..));
+            }
+        }
+    }
+
+    // Reads `; wh02: allow(a, b) deny(c)`. Other comments set nothing.
+    fn parse_directive(&mut self, comment: &str, position: Position) -> Vec<(Lint, Level)> {
+        let text = comment.trim_start_matches(';').trim();
+        let text = match text.strip_prefix("wh02:") {
+            Some(text) => text,
+            None => return Vec::new(),
+        };
+
+        let mut levels = Vec::new();
+        for group in text.split(')') {
+            let group = group.trim();
+            if group.is_empty() {
+                continue;
+            }
+
+            let (level, names) = match group.split_once('(') {
+                Some((level, names)) => (level.trim(), names),
+                None => (group, ""),
+            };
+
+            let level = match level {
+                "allow" => Level::Allow,
+                "warn" => Level::Warn,
+                "deny" => Level::Deny,
+                _ => {
+                    self.report(
+                        Lint::UnknownLint,
+                        position,
+                        format!("Unknown lint level: {}. Expected allow, warn or deny", level),
+                    );
+                    continue;
+                }
+            };
+
+            for name in names.split(',').map(|name| name.trim()).filter(|name| !name.is_empty()) {
+                match Lint::from_name(name) {
+                    Some(lint) => levels.push((lint, level)),
+                    None => self.report(Lint::UnknownLint, position, format!("Unknown lint: {}", name)),
+                }
+            }
+        }
+
+        levels
+    }
+
+    fn level(&self, lint: Lint, line: u32) -> Level {
+        self.directives.lines
+            .get(&line)
+            .and_then(|levels| levels.get(&lint))
+            .or_else(|| self.directives.file.get(&lint))
+            .or_else(|| self.levels.get(&lint))
+            .copied()
+            .unwrap_or(Level::Warn)
+    }
+
+    fn report(&mut self, lint: Lint, position: Position, message: String) {
+        let level = self.level(lint, position.line);
+        if level == Level::Allow {
+            return;
+        }
+
+        self.warnings.push(LintWarning {
+            lint,
+            level,
+            position,
+            message,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wh02_lexer::Lexer;
+    use wh02_parser::Parser;
+
+    use super::*;
+
+    fn lint_with(source: &str, levels: HashMap<Lint, Level>) -> Vec<(Lint, Level, u32)> {
+        let mut parser = Parser::new(Lexer::new(source));
+        parser.parse_all();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+
+        let mut assembler = Assembler::new(parser.expressions);
+        assembler.positions = parser.positions;
+        assembler.assemble().unwrap();
+
+        Linter::new(levels)
+            .lint(source, &assembler)
+            .into_iter()
+            .map(|warning| (warning.lint, warning.level, warning.position.line))
+            .collect()
+    }
+
+    fn lint(source: &str) -> Vec<(Lint, Level, u32)> {
+        lint_with(source, HashMap::new())
+    }
+
+    #[test]
+    fn clean_program_has_no_warnings() {
+        assert_eq!(lint("MOV #01, @A\nMOV @A, @O1\nHLT\n"), vec![]);
+    }
+
+    #[test]
+    fn unreachable_code() {
+        let source = "main: JMP main\nMOV #01, @O1\nHLT\n";
+        assert_eq!(lint(source), vec![(Lint::UnreachableCode, Level::Warn, 1)]);
+
+        let source = "main: JMP main\nMOV #01, @O1 ; wh02: allow(unreachable_code)\nHLT\n";
+        assert_eq!(lint(source), vec![]);
+    }
+
+    #[test]
+    fn uninitialized_acc() {
+        assert_eq!(lint("MOV #01, @A\nMOV @ACC, @O1\nHLT\n"), vec![(Lint::UninitializedAcc, Level::Warn, 1)]);
+        assert_eq!(lint("MOV #01, @A\nMOV #02, @B\nMOV @ACC, @O1\nHLT\n"), vec![]);
+        assert_eq!(lint("MOV #01, @A\nMOV @ACC, @O1 ; wh02: allow(uninitialized_acc)\nHLT\n"), vec![]);
+    }
+
+    #[test]
+    fn dead_store() {
+        assert_eq!(lint("MOV #01, @A\nMOV #02, @A\nHLT\n"), vec![(Lint::DeadStore, Level::Warn, 0)]);
+        assert_eq!(lint("MOV #01, @A\nMOV @A, @O1\nMOV #02, @A\nHLT\n"), vec![]);
+        assert_eq!(lint("MOV #01, @A ; wh02: allow(dead_store)\nMOV #02, @A\nHLT\n"), vec![]);
+    }
+
+    #[test]
+    fn unused_label() {
+        assert_eq!(lint("main: MOV #01, @O1\nHLT\n"), vec![(Lint::UnusedLabel, Level::Warn, 0)]);
+        assert_eq!(lint("main: MOV #01, @O1\nJMP main\n"), vec![]);
+        assert_eq!(lint("main: MOV #01, @O1 ; wh02: allow(unused_label)\nHLT\n"), vec![]);
+    }
+
+    #[test]
+    fn missing_hlt() {
+        assert_eq!(lint("MOV #01, @O1\n"), vec![(Lint::MissingHlt, Level::Warn, 0)]);
+        assert_eq!(lint("MOV #01, @O1\nHLT\n"), vec![]);
+        assert_eq!(lint("MOV #01, @O1 ; wh02: allow(missing_hlt)\n"), vec![]);
+    }
+
+    #[test]
+    fn misaligned_jump() {
+        assert_eq!(lint("MOV #01, @O1\nJMP $01\n"), vec![(Lint::MisalignedJump, Level::Warn, 1)]);
+        assert_eq!(lint("MOV #01, @O1\nJMP $00\n"), vec![]);
+        assert_eq!(lint("MOV #01, @O1\nJMP $01 ; wh02: allow(misaligned_jump)\n"), vec![]);
+    }
+
+    #[test]
+    fn unknown_lint() {
+        let source = "; wh02: allow(no_such_lint) forbid(dead_store)\nMOV #01, @O1\nHLT\n";
+        assert_eq!(lint(source), vec![(Lint::UnknownLint, Level::Warn, 0), (Lint::UnknownLint, Level::Warn, 0)]);
+
+        let source = "; wh02: allow(unknown_lint)\nMOV #01, @O1 ; wh02: allow(no_such_lint)\nHLT\n";
+        assert_eq!(lint(source), vec![]);
+    }
+
+    #[test]
+    fn directive_on_its_own_line_applies_to_the_next_code() {
+        let source = "MOV #01, @A\n; wh02: allow(dead_store)\n\nMOV #02, @A\nMOV #03, @A\nHLT\n";
+        assert_eq!(lint(source), vec![(Lint::DeadStore, Level::Warn, 0)]);
+    }
+
+    #[test]
+    fn directive_before_code_applies_to_the_file() {
+        let source = "; wh02: allow(dead_store)\nMOV #01, @A\nMOV #02, @A\nMOV #03, @A\nHLT\n";
+        assert_eq!(lint(source), vec![]);
+    }
+
+    #[test]
+    fn deny_raises_the_level() {
+        let source = "; wh02: deny(missing_hlt)\nMOV #01, @O1\n";
+        assert_eq!(lint(source), vec![(Lint::MissingHlt, Level::Deny, 1)]);
+
+        let source = "MOV #01, @A ; wh02: deny(dead_store)\nMOV #02, @A\nMOV #03, @A\nHLT\n";
+        assert_eq!(lint(source), vec![(Lint::DeadStore, Level::Deny, 0), (Lint::DeadStore, Level::Warn, 1)]);
+    }
+
+    #[test]
+    fn directives_override_the_command_line() {
+        let levels = HashMap::from([(Lint::MissingHlt, Level::Deny), (Lint::UnusedLabel, Level::Allow)]);
+        assert_eq!(lint_with("main: MOV #01, @O1\n", levels.clone()), vec![(Lint::MissingHlt, Level::Deny, 0)]);
+
+        let source = "; wh02: warn(missing_hlt, unused_label)\nmain: MOV #01, @O1\n";
+        assert_eq!(lint_with(source, levels), vec![
+            (Lint::UnusedLabel, Level::Warn, 1),
+            (Lint::MissingHlt, Level::Warn, 1),
+        ]);
+    }
+}
\ No newline at end of file
@@ -1,48 +1,223 @@
-use std::{env, fs};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
 use std::fs::File;
 use std::io::Write;
 use std::time::Instant;
 
 use wh02_lexer::Lexer;
-use wh02_lexer::position::Position;
 
 use wh02_parser::Parser;
+use wh02_parser::expressions::Expressions;
 
-pub mod assembler;
-pub mod assembler_error;
+use rom_builder::control_word::ControlWord;
+use rom_builder::opcodes::INSTRUCTIONS;
+use rom_builder::{RomBuilder, STEPS};
+
+use wh02_emu::diff::{self, Outcome};
+use wh02_emu::disassembler::listing;
+use wh02_emu::emulator::Emulator;
+use wh02_emu::image::parse_image;
+use wh02_emu::microcode::MicrocodeEmulator;
+use wh02_emu::trace::Trace;
+use wh02_emu::tui;
+
+use wh02::assembler;
+use wh02::cfg::ControlFlowGraph;
+use wh02::circ::{decode_contents, encode_contents, memory_image, Circ, MEMORIES};
+use wh02::circ_error::CircError;
+use wh02::formatter::{Case, FormatOptions, Formatter};
+use wh02::lint::{Level, Lint};
+use wh02::linter::Linter;
+use wh02::memory_map::{check_size, parse_number, project_file, MemoryMap, Region, RegionKind};
+use wh02::memory_map_error::MemoryMapError;
+use wh02::object::ObjectFile;
+use wh02::optimizer::Optimizer;
+use wh02::stats::Stats;
+use wh02::wiring::check_wiring as check_control_wiring;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    let input_path = &args[1];
-    let output_path = &args[2];
+    let code = match args.get(1).map(|arg| arg.as_str()) {
+        Some("fmt") => format_files(&args[2..]),
+        Some("lint") => lint_files(&args[2..]),
+        Some("cfg") => export_cfg(&args[2..]),
+        Some("obj") => write_object(&args[2..]),
+        Some("diff") => diff_emulators(&args[2..]),
+        Some("trace") => trace_program(&args[2..]),
+        Some("run") => run_program(&args[2..]),
+        Some("tui") => front_panel(&args[2..]),
+        Some("inject") => inject_program(&args[2..]),
+        Some("extract") => extract_memory(&args[2..]),
+        Some("wiring") => check_wiring(&args[2..]),
+        _ => assemble_file(&args[1..]),
+    };
+
+    process::exit(code);
+}
+
+fn number_argument(flag: &str, value: Option<&String>) -> Result<usize, String> {
+    match value.map(|value| value.parse::<usize>()) {
+        Some(Ok(number)) => Ok(number),
+        _ => Err(format!("{} expects a number", flag)),
+    }
+}
+
+fn assemble_file(args: &[String]) -> i32 {
+    let mut case_sensitive = false;
+    let mut stats = false;
+    let mut optimize = false;
+    let mut verbose = false;
+    let mut map_path = None;
+    let mut memory_size = None;
+    let mut regions = Vec::new();
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "--case-sensitive" => {
+                case_sensitive = true;
+                Ok(())
+            },
+            "--stats" => {
+                stats = true;
+                Ok(())
+            },
+            "-O" => {
+                optimize = true;
+                Ok(())
+            },
+            "-v" | "--verbose" => {
+                verbose = true;
+                Ok(())
+            },
+            "--memory-map" => match args.next() {
+                Some(path) => {
+                    map_path = Some(path.clone());
+                    Ok(())
+                },
+                None => Err(format!("{} expects a path", arg)),
+            },
+            "--memory-size" => match args.next().and_then(|size| parse_number(size)) {
+                Some(size) => match check_size(size) {
+                    Ok(()) => {
+                        memory_size = Some(size);
+                        Ok(())
+                    },
+                    Err(error) => Err(error.to_string()),
+                },
+                None => Err(format!("{} expects a number, such as 256 or 0x100", arg)),
+            },
+            "--region" => match args.next().map(|spec| Region::from_spec(spec)) {
+                Some(Ok(region)) => {
+                    regions.push(region);
+                    Ok(())
+                },
+                Some(Err(error)) => Err(error.to_string()),
+                None => Err(format!("{} expects name:kind:start-end", arg)),
+            },
+            _ => {
+                paths.push(arg);
+                Ok(())
+            }
+        };
+
+        if let Err(error) = result {
+            println!("ERROR: {}", error);
+            return 2;
+        }
+    }
+
+    if paths.len() != 2 {
+        println!("Usage: wh02 [--case-sensitive] [--stats] [-O [--verbose]] [--memory-map <wh02.toml>]");
+        println!("            [--memory-size N] [--region name:kind:start-end]... <program.asm> <program.bin>");
+        println!("       wh02 fmt [--check] [--lowercase] [--comment-column N] [--indent N] <program.asm>...");
+        println!("       wh02 lint [--allow LINT] [--warn LINT] [--deny LINT] <program.asm>...");
+        println!("       wh02 cfg [--json] <program.asm> [<output>]");
+        println!("       wh02 obj [--case-sensitive] <program.asm> <program.o>");
+        println!("       wh02 run [--max-cycles N] <program.asm|program.bin>");
+        println!("       wh02 tui <program.asm|program.bin>");
+        println!("       wh02 diff [--max-cycles N] <program.asm|program.bin>");
+        println!("       wh02 inject --circ <WH02.circ> --component NAME [--rom NAME] [--output <out.circ>] <program.asm>");
+        println!("       wh02 extract --circ <WH02.circ> [--component NAME [--format hex|bin|asm|microcode] [<output>]]");
+        println!("       wh02 wiring [--rom NAME] <WH02.circ>");
+        println!("       wh02 trace [--max-cycles N] [--vcd <trace.vcd>] <program.asm|program.bin> [<trace>]");
+        return 2;
+    }
+
+    let input_path = paths[0];
+    let output_path = paths[1];
 
     let start = Instant::now();
 
-    let contents = fs::read_to_string(&input_path).expect("Failed to load input file.");
-    let lexer = Lexer {
-        position: Position::default(),
-        characters: contents.chars().peekable(),
-    };
+    let contents = fs::read_to_string(input_path).expect("Failed to load input file.");
+    let lexer = Lexer::new(&contents);
 
     let mut parser = Parser::new(lexer);
+    parser.case_insensitive = !case_sensitive;
 
     parser.parse_all();
 
-    if parser.errors.len() > 0 {
-        return;
+    for warning in &parser.warnings {
+        println!("WARNING: {}", warning);
+    }
+
+    if !parser.errors.is_empty() {
+        for error in &parser.errors {
+            println!("ERROR: {}", error);
+        }
+        return 1;
     }
 
     let mut assembler = assembler::Assembler::new(parser.expressions);
+    assembler.positions = parser.positions;
+    assembler.memory_map = match memory_map(input_path, map_path, memory_size, regions) {
+        Ok(memory_map) => memory_map,
+        Err(error) => {
+            println!("ERROR: {}", error);
+            return 2;
+        }
+    };
 
-    let success = assembler.assemble();
+    let mut success = assembler.assemble();
+
+    if optimize && success.is_ok() {
+        match Optimizer::fixed_jump(&assembler) {
+            Some(jump) => println!(
+                "WARNING: Not optimizing, since the {} at {} uses a fixed address. Use a label instead",
+                match &assembler.expressions[jump] {
+                    Expressions::UnaryExpression { keyword, .. } => keyword.to_string(),
+                    _ => "JMP".to_string(),
+                },
+                assembler.positions.get(jump).copied().unwrap_or_default(),
+            ),
+            None => {
+                let size = assembler.sizes.iter().sum::<usize>();
+                let mut optimizer = Optimizer::new();
+                success = optimizer.optimize(&contents, &mut assembler).and_then(|_| assembler.assemble());
+
+                if verbose {
+                    for rewrite in &optimizer.rewrites {
+                        println!("OPTIMIZED: {}: {}", rewrite.position, rewrite.message);
+                    }
+                }
+                println!(
+                    "Made {} rewrites, saving {} bytes",
+                    optimizer.rewrites.len(),
+                    size - assembler.sizes.iter().sum::<usize>(),
+                );
+            }
+        }
+    }
 
     let duration = start.elapsed();
 
     match success {
         Err(error) => {
             println!("ERROR: {}", error);
-            return;
+            return 1;
         },
         Ok(success) => {
             let mut output = File::create(output_path).expect("Failed to create output file.");
@@ -51,5 +226,987 @@ fn main() {
         }
     }
 
+    print_memory_usage(&assembler);
+
     println!("Completed in {}ms ({}ns)", duration.as_millis(), duration.as_nanos());
+
+    if stats {
+        let mut rom = RomBuilder::default();
+        rom.build_rom();
+        println!();
+        println!("{}", Stats::build(&assembler, &rom));
+    }
+
+    0
+}
+
+fn format_files(args: &[String]) -> i32 {
+    let mut options = FormatOptions::default();
+    let mut check = false;
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "--check" => {
+                check = true;
+                Ok(())
+            },
+            "--lowercase" => {
+                options.case = Case::Lower;
+                Ok(())
+            },
+            "--comment-column" => number_argument(arg, args.next())
+                .map(|column| options.comment_column = column),
+            "--indent" => number_argument(arg, args.next())
+                .map(|indent| options.indent = indent),
+            _ => {
+                paths.push(arg);
+                Ok(())
+            }
+        };
+
+        if let Err(error) = result {
+            println!("ERROR: {}", error);
+            return 2;
+        }
+    }
+
+    let formatter = Formatter::new(options);
+    let mut unformatted = 0;
+
+    for path in paths {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(error) => {
+                println!("ERROR: Failed to read {}: {}", path, error);
+                return 2;
+            }
+        };
+
+        let formatted = formatter.format(&contents);
+        if formatted == contents {
+            continue;
+        }
+
+        unformatted += 1;
+        if check {
+            println!("Would reformat {}", path);
+        } else {
+            fs::write(path, formatted).expect("Failed to write formatted file.");
+            println!("Formatted {}", path);
+        }
+    }
+
+    if check && unformatted > 0 {
+        return 1;
+    }
+
+    0
+}
+
+// The memory map from the command line, or else from the project file
+// next to the program
+fn memory_map(
+    program: &str,
+    map_path: Option<String>,
+    size: Option<usize>,
+    regions: Vec<Region>,
+) -> Result<MemoryMap, MemoryMapError> {
+    let project = map_path.map(PathBuf::from).or_else(|| project_file(Path::new(program)));
+    MemoryMap::configure(project.as_deref(), size, regions)
+}
+
+fn print_memory_usage(assembler: &assembler::Assembler) {
+    let usage = assembler.memory_map.usage(&assembler.used());
+    let used: usize = usage.iter().map(|region| region.used).sum();
+    let size = assembler.memory_map.size;
+    println!("Used {} of {} bytes, {} free", used, size, size - used);
+
+    if assembler.memory_map.regions.is_empty() {
+        return;
+    }
+
+    for region in usage {
+        match region.kind {
+            Some(RegionKind::Reserved) => println!("  {:<12} reserved, {} bytes", region.name, region.total),
+            Some(kind) => println!(
+                "  {:<12} {}, {} of {} bytes used, {} free",
+                region.name,
+                kind,
+                region.used,
+                region.total,
+                region.total - region.used,
+            ),
+            None => println!(
+                "  {:<12} {} of {} bytes used, {} free",
+                region.name,
+                region.used,
+                region.total,
+                region.total - region.used,
+            ),
+        }
+    }
+}
+
+// Parses and assembles a program, printing any problems along with the
+// path they came from
+fn assemble_source(path: &str, contents: &str) -> Option<assembler::Assembler> {
+    let mut parser = Parser::new(Lexer::new(contents));
+    parser.parse_all();
+
+    for warning in &parser.warnings {
+        println!("WARNING: {}: {}", path, warning);
+    }
+
+    if !parser.errors.is_empty() {
+        for error in &parser.errors {
+            println!("ERROR: {}: {}", path, error);
+        }
+        return None;
+    }
+
+    let mut assembler = assembler::Assembler::new(parser.expressions);
+    assembler.positions = parser.positions;
+    assembler.memory_map = match memory_map(path, None, None, Vec::new()) {
+        Ok(memory_map) => memory_map,
+        Err(error) => {
+            println!("ERROR: {}", error);
+            return None;
+        }
+    };
+
+    match assembler.assemble() {
+        Ok(_) => Some(assembler),
+        Err(error) => {
+            println!("ERROR: {}: {}", path, error);
+            None
+        }
+    }
+}
+
+fn lint_argument(flag: &str, value: Option<&String>) -> Result<Lint, String> {
+    match value.map(|value| Lint::from_name(value)) {
+        Some(Some(lint)) => Ok(lint),
+        Some(None) => Err(format!("Unknown lint: {}", value.unwrap())),
+        None => Err(format!("{} expects a lint name", flag)),
+    }
+}
+
+fn lint_files(args: &[String]) -> i32 {
+    let mut levels = HashMap::new();
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "--allow" => lint_argument(arg, args.next()).map(|lint| levels.insert(lint, Level::Allow)),
+            "--warn" => lint_argument(arg, args.next()).map(|lint| levels.insert(lint, Level::Warn)),
+            "--deny" => lint_argument(arg, args.next()).map(|lint| levels.insert(lint, Level::Deny)),
+            _ => {
+                paths.push(arg);
+                Ok(None)
+            }
+        };
+
+        if let Err(error) = result {
+            println!("ERROR: {}", error);
+            return 2;
+        }
+    }
+
+    let mut code = 0;
+    for path in paths {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(error) => {
+                println!("ERROR: Failed to read {}: {}", path, error);
+                return 2;
+            }
+        };
+
+        let assembler = match assemble_source(path, &contents) {
+            Some(assembler) => assembler,
+            None => {
+                code = 1;
+                continue;
+            }
+        };
+
+        let mut linter = Linter::new(levels.clone());
+        for warning in linter.lint(&contents, &assembler) {
+            match warning.level {
+                Level::Deny => {
+                    println!("ERROR: {}: {}", path, warning);
+                    code = 1;
+                },
+                _ => println!("WARNING: {}: {}", path, warning),
+            }
+        }
+    }
+
+    code
+}
+
+fn export_cfg(args: &[String]) -> i32 {
+    let json = args.iter().any(|arg| arg == "--json");
+    let paths: Vec<&String> = args.iter()
+        .filter(|arg| !arg.starts_with("--"))
+        .collect();
+
+    if paths.is_empty() || paths.len() > 2 {
+        println!("Usage: wh02 cfg [--json] <program.asm> [<output>]");
+        return 2;
+    }
+
+    let contents = match fs::read_to_string(paths[0]) {
+        Ok(contents) => contents,
+        Err(error) => {
+            println!("ERROR: Failed to read {}: {}", paths[0], error);
+            return 2;
+        }
+    };
+
+    let assembler = match assemble_source(paths[0], &contents) {
+        Some(assembler) => assembler,
+        None => return 1,
+    };
+
+    let mut rom = RomBuilder::default();
+    rom.build_rom();
+
+    let graph = ControlFlowGraph::build(&assembler, &rom);
+    let output = if json {
+        format!("{}\n", graph.to_json())
+    } else {
+        graph.to_dot()
+    };
+
+    match paths.get(1) {
+        Some(path) => {
+            fs::write(path, output).expect("Failed to write to output file.");
+            println!("Wrote {} blocks to {}", graph.blocks.len(), path);
+        },
+        None => print!("{}", output),
+    }
+
+    0
+}
+
+fn write_object(args: &[String]) -> i32 {
+    let case_sensitive = args.iter().any(|arg| arg == "--case-sensitive");
+    let paths: Vec<&String> = args.iter()
+        .filter(|arg| !arg.starts_with("--"))
+        .collect();
+
+    if paths.len() != 2 {
+        println!("Usage: wh02 obj [--case-sensitive] <program.asm> <program.o>");
+        return 2;
+    }
+
+    let contents = match fs::read_to_string(paths[0]) {
+        Ok(contents) => contents,
+        Err(error) => {
+            println!("ERROR: Failed to read {}: {}", paths[0], error);
+            return 2;
+        }
+    };
+
+    let mut parser = Parser::new(Lexer::new(&contents));
+    parser.case_insensitive = !case_sensitive;
+    parser.parse_all();
+
+    for warning in &parser.warnings {
+        println!("WARNING: {}: {}", paths[0], warning);
+    }
+
+    if !parser.errors.is_empty() {
+        for error in &parser.errors {
+            println!("ERROR: {}: {}", paths[0], error);
+        }
+        return 1;
+    }
+
+    let name = Path::new(paths[0])
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| paths[0].to_string());
+
+    let object = match ObjectFile::assemble(&name, parser.expressions, parser.positions) {
+        Ok(object) => object,
+        Err(error) => {
+            println!("ERROR: {}: {}", paths[0], error);
+            return 1;
+        }
+    };
+
+    fs::write(paths[1], format!("{}\n", object.to_json())).expect("Failed to write to output file.");
+    println!(
+        "Wrote {} sections, {} symbols and {} relocations to {}",
+        object.sections.len(),
+        object.symbols.len(),
+        object.relocations.len(),
+        paths[1],
+    );
+
+    let undefined = object.undefined();
+    if !undefined.is_empty() {
+        println!("Left for the linker: {}", undefined.join(", "));
+    }
+
+    0
+}
+
+// Loads a program to run: an .asm file is assembled, anything else is
+// read as a memory image
+fn load_program(path: &str) -> Option<Vec<u8>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) => {
+            println!("ERROR: Failed to read {}: {}", path, error);
+            return None;
+        }
+    };
+
+    if path.ends_with(".asm") {
+        let assembler = assemble_source(path, &contents)?;
+        return Some(assembler.assembled.iter()
+            .map(|word| u8::from_str_radix(word, 16).unwrap_or_default())
+            .collect());
+    }
+
+    match parse_image(&contents) {
+        Ok(image) => Some(image),
+        Err(error) => {
+            println!("ERROR: {}: {}", path, error);
+            None
+        }
+    }
+}
+
+// Runs a program on the microcode emulator, printing what it writes to the
+// output registers
+fn run_program(args: &[String]) -> i32 {
+    let mut max_cycles = 10_000;
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "--max-cycles" => number_argument(arg, args.next())
+                .map(|cycles| max_cycles = cycles as u64),
+            _ => {
+                paths.push(arg);
+                Ok(())
+            }
+        };
+
+        if let Err(error) = result {
+            println!("ERROR: {}", error);
+            return 2;
+        }
+    }
+
+    if paths.len() != 1 {
+        println!("Usage: wh02 run [--max-cycles N] <program.asm|program.bin>");
+        return 2;
+    }
+
+    let image = match load_program(paths[0]) {
+        Some(image) => image,
+        None => return 1,
+    };
+
+    let mut emulator = MicrocodeEmulator::new(&image);
+    let result = emulator.run(max_cycles);
+
+    for output in &emulator.machine.outputs {
+        println!("{:>6}  {:?} = {:02X} ({})", output.cycle, output.register, output.value, output.value);
+    }
+
+    match result {
+        Err(error) => {
+            println!("ERROR: {}", error);
+            1
+        },
+        Ok(true) => {
+            println!("Halted after {} cycles", emulator.machine.cycles);
+            0
+        },
+        Ok(false) => {
+            println!("ERROR: Did not halt within {} cycles", max_cycles);
+            1
+        },
+    }
+}
+
+fn front_panel(args: &[String]) -> i32 {
+    if args.len() != 1 {
+        println!("Usage: wh02 tui <program.asm|program.bin>");
+        return 2;
+    }
+
+    let image = match load_program(&args[0]) {
+        Some(image) => image,
+        None => return 1,
+    };
+
+    match tui::run(&image) {
+        Ok(()) => 0,
+        Err(error) => {
+            println!("ERROR: {}", error);
+            1
+        },
+    }
+}
+
+fn diff_emulators(args: &[String]) -> i32 {
+    let mut max_cycles = 10_000;
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "--max-cycles" => number_argument(arg, args.next())
+                .map(|cycles| max_cycles = cycles as u64),
+            _ => {
+                paths.push(arg);
+                Ok(())
+            }
+        };
+
+        if let Err(error) = result {
+            println!("ERROR: {}", error);
+            return 2;
+        }
+    }
+
+    if paths.len() != 1 {
+        println!("Usage: wh02 diff [--max-cycles N] <program.asm|program.bin>");
+        return 2;
+    }
+
+    let image = match load_program(paths[0]) {
+        Some(image) => image,
+        None => return 1,
+    };
+
+    match diff::run(&image, max_cycles) {
+        Err(error) => {
+            println!("ERROR: {}", error);
+            1
+        },
+        Ok(Outcome::Halted { instructions, cycles }) => {
+            println!("Both emulators halted after {} instructions ({} cycles) and agree", instructions, cycles);
+            0
+        },
+        Ok(Outcome::TimedOut { instructions, cycles }) => {
+            println!("Both emulators agree after {} instructions ({} cycles), without halting", instructions, cycles);
+            0
+        },
+        Ok(Outcome::Diverged(divergence)) => {
+            println!(
+                "ERROR: The emulators diverge at instruction {}, opcode {:02X} at ${:02X}",
+                divergence.instruction,
+                divergence.opcode,
+                divergence.address,
+            );
+            println!("Instruction emulator first, microcode emulator second:");
+            for difference in divergence.differences {
+                println!("  {}", difference);
+            }
+            1
+        },
+    }
+}
+
+fn trace_program(args: &[String]) -> i32 {
+    let mut max_cycles = 10_000;
+    let mut vcd_path = None;
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "--max-cycles" => number_argument(arg, args.next())
+                .map(|cycles| max_cycles = cycles as u64),
+            "--vcd" => match args.next() {
+                Some(path) => {
+                    vcd_path = Some(path);
+                    Ok(())
+                },
+                None => Err("--vcd expects a path".to_string()),
+            },
+            _ => {
+                paths.push(arg);
+                Ok(())
+            }
+        };
+
+        if let Err(error) = result {
+            println!("ERROR: {}", error);
+            return 2;
+        }
+    }
+
+    if paths.is_empty() || paths.len() > 2 {
+        println!("Usage: wh02 trace [--max-cycles N] [--vcd <trace.vcd>] <program.asm|program.bin> [<trace>]");
+        return 2;
+    }
+
+    let image = match load_program(paths[0]) {
+        Some(image) => image,
+        None => return 1,
+    };
+
+    let mut emulator = MicrocodeEmulator::new(&image);
+    emulator.trace = Some(Trace::new(&emulator.machine));
+
+    // A failed run still leaves a trace up to where it went wrong
+    let result = emulator.run(max_cycles);
+    let trace = emulator.trace.take().unwrap_or_else(|| Trace::new(&emulator.machine));
+
+    match paths.get(1) {
+        Some(path) => {
+            fs::write(path, trace.to_text()).expect("Failed to write to output file.");
+            println!("Wrote {} cycles to {}", trace.steps.len(), path);
+        },
+        None => print!("{}", trace.to_text()),
+    }
+
+    if let Some(path) = vcd_path {
+        fs::write(path, trace.to_vcd()).expect("Failed to write to output file.");
+        println!("Wrote a waveform of {} cycles to {}", trace.steps.len(), path);
+    }
+
+    match result {
+        Err(error) => {
+            println!("ERROR: {}", error);
+            1
+        },
+        Ok(_) => 0,
+    }
+}
+
+// Writes a program, and optionally the control ROM, into the memories of
+// a circuit, so the project opens with them loaded
+fn inject_program(args: &[String]) -> i32 {
+    let usage = "Usage: wh02 inject --circ <WH02.circ> --component NAME [--rom NAME] [--output <out.circ>] <program.asm>";
+    let mut circ_path = None;
+    let mut component = None;
+    let mut rom_component = None;
+    let mut output_path = None;
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = match arg.as_str() {
+            "--circ" => &mut circ_path,
+            "--component" => &mut component,
+            "--rom" => &mut rom_component,
+            "--output" | "-o" => &mut output_path,
+            _ => {
+                paths.push(arg);
+                continue;
+            }
+        };
+
+        match args.next() {
+            Some(next) => *value = Some(next.clone()),
+            None => {
+                println!("ERROR: {} expects a value", arg);
+                return 2;
+            }
+        }
+    }
+
+    let (circ_path, component, program_path) = match (circ_path, component, paths.as_slice()) {
+        (Some(circ_path), Some(component), [program_path]) => (circ_path, component, *program_path),
+        _ => {
+            println!("{}", usage);
+            return 2;
+        }
+    };
+
+    let text = match fs::read_to_string(&circ_path) {
+        Ok(text) => text,
+        Err(error) => {
+            println!("ERROR: Failed to read {}: {}", circ_path, error);
+            return 2;
+        }
+    };
+
+    let image = match load_program(program_path) {
+        Some(image) => image,
+        None => return 1,
+    };
+
+    let mut memories = vec![(component, image.iter().map(|byte| *byte as u32).collect::<Vec<u32>>())];
+    if let Some(rom_component) = rom_component {
+        let mut rom = RomBuilder::default();
+        rom.build_rom();
+        memories.push((rom_component, rom.rom.clone()));
+    }
+
+    // Each memory is found again in the edited file, since earlier edits
+    // move everything after them
+    let mut text = text;
+    for (name, words) in memories {
+        let circ = match Circ::parse(&text) {
+            Ok(circ) => circ,
+            Err(error) => {
+                println!("ERROR: {}: {}", circ_path, error);
+                return 1;
+            }
+        };
+
+        let result = circ.find_memory(&name).and_then(|found| {
+            let address_width = found.width("addrWidth")?;
+            let data_width = found.width("dataWidth")?;
+            let largest = words.iter().max().copied().unwrap_or_default();
+            if words.len() > 1 << address_width || (data_width < 32 && largest >> data_width != 0) {
+                return Err(CircError {
+                    message: format!(
+                        "{} holds {} words of {} bits, which is too small for {} words of up to {:x}",
+                        found.describe(),
+                        1u64 << address_width,
+                        data_width,
+                        words.len(),
+                        largest,
+                    ),
+                });
+            }
+
+            println!("Writing {} words to {}", words.len(), found.describe());
+            Ok(circ.with_contents(found, &encode_contents(address_width, data_width, &words)))
+        });
+
+        match result {
+            Ok(edited) => text = edited,
+            Err(error) => {
+                println!("ERROR: {}", error);
+                return 1;
+            }
+        }
+    }
+
+    let output_path = output_path.unwrap_or_else(|| {
+        let circ = Path::new(&circ_path);
+        let stem = |path: &Path| path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+        circ.with_file_name(format!("{}-{}.circ", stem(circ), stem(Path::new(program_path))))
+            .to_string_lossy()
+            .to_string()
+    });
+
+    fs::write(&output_path, text).expect("Failed to write to output file.");
+    println!("Wrote {}", output_path);
+
+    0
+}
+
+// Lists the memories in a circuit, or writes out what one holds
+fn extract_memory(args: &[String]) -> i32 {
+    let usage = "Usage: wh02 extract --circ <WH02.circ> [--component NAME [--format hex|bin|asm|microcode] [<output>]]";
+    let mut circ_path = None;
+    let mut component = None;
+    let mut format = "hex".to_string();
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "--circ" => args.next().map(|path| circ_path = Some(path)),
+            "--component" => args.next().map(|name| component = Some(name)),
+            "--format" => args.next().map(|name| format = name.clone()),
+            _ => {
+                paths.push(arg);
+                Some(())
+            }
+        };
+
+        if result.is_none() {
+            println!("ERROR: {} expects a value", arg);
+            return 2;
+        }
+    }
+
+    let circ_path = match circ_path {
+        Some(path) if paths.len() <= 1 => path,
+        _ => {
+            println!("{}", usage);
+            return 2;
+        }
+    };
+
+    let circ = match fs::read_to_string(circ_path).map(|text| Circ::parse(&text)) {
+        Ok(Ok(circ)) => circ,
+        Ok(Err(error)) => {
+            println!("ERROR: {}: {}", circ_path, error);
+            return 1;
+        },
+        Err(error) => {
+            println!("ERROR: Failed to read {}: {}", circ_path, error);
+            return 2;
+        }
+    };
+
+    let component = match component {
+        Some(component) => component,
+        None => {
+            for memory in circ.components.iter().filter(|memory| MEMORIES.contains(&memory.name.as_str())) {
+                let used = memory.attribute("contents")
+                    .and_then(|contents| decode_contents(contents).ok())
+                    .map(|(_, _, words)| words.iter().filter(|word| **word != 0).count())
+                    .unwrap_or_default();
+                println!(
+                    "{}: {} address bits, {} data bits, {} words set",
+                    memory.describe(),
+                    memory.width("addrWidth").unwrap_or_default(),
+                    memory.width("dataWidth").unwrap_or_default(),
+                    used,
+                );
+            }
+            return 0;
+        }
+    };
+
+    let result = circ.find_memory(component).and_then(|memory| {
+        let address_width = memory.width("addrWidth")?;
+        let data_width = memory.width("dataWidth")?;
+        let words = match memory.attribute("contents") {
+            Some(contents) => decode_contents(contents)?.2,
+            None => vec![0; 1 << address_width],
+        };
+
+        let wrong_shape = |needs: &str| CircError {
+            message: format!("{} does not hold {}", memory.describe(), needs),
+        };
+
+        match format.as_str() {
+            "hex" => Ok(memory_image(address_width, data_width, &words).into_bytes()),
+            "bin" => Ok(words.iter()
+                .flat_map(|word| word.to_le_bytes().into_iter().take(data_width.div_ceil(8) as usize))
+                .collect()),
+            "asm" if data_width == 8 => {
+                let bytes: Vec<u8> = words.iter().map(|word| *word as u8).collect();
+                Ok(disassembly(&bytes).into_bytes())
+            },
+            "asm" => Err(wrong_shape("bytes to disassemble")),
+            "microcode" if address_width == 11 => Ok(microcode_listing(&words).into_bytes()),
+            "microcode" => Err(wrong_shape("microcode, which needs 11 address bits")),
+            _ => Err(CircError {
+                message: format!("Unknown format {}. Use hex, bin, asm or microcode", format),
+            }),
+        }
+    });
+
+    match (result, paths.first()) {
+        (Err(error), _) => {
+            println!("ERROR: {}", error);
+            1
+        },
+        (Ok(output), Some(path)) => {
+            fs::write(path, output).expect("Failed to write to output file.");
+            println!("Wrote {} to {}", component, path);
+            0
+        },
+        (Ok(output), None) => {
+            std::io::stdout().write_all(&output).expect("Failed to write output.");
+            0
+        },
+    }
+}
+
+// Checks that the control ROM in a circuit drives the tunnels and
+// decoders the way rom_builder expects
+fn check_wiring(args: &[String]) -> i32 {
+    let usage = "Usage: wh02 wiring [--rom NAME] <WH02.circ>";
+    let mut rom_component = "ROM".to_string();
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rom" => match args.next() {
+                Some(name) => rom_component = name.clone(),
+                None => {
+                    println!("ERROR: {} expects a value", arg);
+                    return 2;
+                }
+            },
+            _ => paths.push(arg),
+        }
+    }
+
+    let circ_path = match paths.as_slice() {
+        [path] => *path,
+        _ => {
+            println!("{}", usage);
+            return 2;
+        }
+    };
+
+    let circ = match fs::read_to_string(circ_path).map(|text| Circ::parse(&text)) {
+        Ok(Ok(circ)) => circ,
+        Ok(Err(error)) => {
+            println!("ERROR: {}: {}", circ_path, error);
+            return 1;
+        },
+        Err(error) => {
+            println!("ERROR: Failed to read {}: {}", circ_path, error);
+            return 2;
+        }
+    };
+
+    match check_control_wiring(&circ, &rom_component) {
+        Err(error) => {
+            println!("ERROR: {}: {}", circ_path, error);
+            1
+        },
+        Ok(mismatches) if mismatches.is_empty() => {
+            println!("{} is wired the way rom_builder expects", circ_path);
+            0
+        },
+        Ok(mismatches) => {
+            for mismatch in &mismatches {
+                println!("ERROR: {}", mismatch);
+            }
+            println!("{} mismatches with rom_builder", mismatches.len());
+            1
+        },
+    }
+}
+
+// A listing of the instructions in memory, up to the last byte that is set
+fn disassembly(memory: &[u8]) -> String {
+    let end = memory.iter().rposition(|byte| *byte != 0).map(|last| last + 1).unwrap_or_default();
+
+    let mut output = String::new();
+    for line in listing(memory, 0).iter().take_while(|line| line.address < end) {
+        let bytes: Vec<String> = line.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        output += &format!("${:02X}  {:<9} {}\n", line.address, bytes.join(" "), line.text);
+    }
+
+    output
+}
+
+// Every word of a control ROM decoded, one instruction at a time, with
+// each word that differs from what rom_builder builds marked
+fn microcode_listing(words: &[u32]) -> String {
+    let mut rom = RomBuilder::default();
+    rom.build_rom();
+
+    let decoded = |bits: u32| match ControlWord::from_bits(bits) {
+        Ok(word) => word.to_string(),
+        Err(error) => format!("0x{:05X} ({})", bits, error),
+    };
+
+    // The fetch cycle is the same for every opcode, so it is only shown
+    // where it differs
+    let mut output = format!("Fetch\n  0: {}\n  1: {}\n", decoded(words[0]), decoded(words[0x100]));
+    let mut differences = 0;
+    for opcode in 0..0x100u16 {
+        let steps: Vec<(u16, u32, u32)> = (0..STEPS)
+            .map(|step| {
+                let address = (step << 8 | opcode) as usize;
+                (step, words[address], rom.rom[address])
+            })
+            .collect();
+
+        let differ = steps.iter().filter(|(_, word, expected)| word != expected).count();
+        differences += differ;
+
+        // Opcodes that only run the fetch cycle, as rom_builder expects,
+        // are left out
+        let name = INSTRUCTIONS.iter().find(|(code, _, _)| *code as u16 == opcode).map(|(_, name, _)| *name);
+        if name.is_none() && differ == 0 {
+            continue;
+        }
+
+        output += &format!("{:02X} {}\n", opcode, name.unwrap_or("(no instruction)"));
+        for (step, word, expected) in steps {
+            if (word == 0 || step < 2) && word == expected {
+                continue;
+            }
+
+            output += &format!("  {}: {}", step, decoded(word));
+            if word != expected {
+                output += &format!("  <- rom_builder has {}", decoded(expected));
+            }
+            output += "\n";
+        }
+    }
+
+    output += &format!("{} words differ from rom_builder\n", differences);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A file in the temp directory, removed when dropped
+    struct TempFile {
+        path: PathBuf,
+    }
+
+    impl TempFile {
+        fn new(name: &str, contents: &str) -> TempFile {
+            let path = env::temp_dir().join(format!("wh02-{}-{}", process::id(), name));
+            fs::write(&path, contents).unwrap();
+            TempFile {
+                path,
+            }
+        }
+
+        fn arg(&self) -> String {
+            self.path.to_string_lossy().to_string()
+        }
+
+        fn contents(&self) -> String {
+            fs::read_to_string(&self.path).unwrap()
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    #[test]
+    fn memory_size_is_at_most_256() {
+        let program = TempFile::new("large.asm", "HLT\n");
+        let output = TempFile::new("large.bin", "");
+
+        let args = |size: &str| vec!["--memory-size".to_string(), size.to_string(), program.arg(), output.arg()];
+        assert_eq!(assemble_file(&args("0x200")), 2);
+        assert_eq!(output.contents(), "");
+        assert_eq!(assemble_file(&args("0x100")), 0);
+        assert!(output.contents().starts_with("v3.0 hex words addressed"));
+    }
+
+    #[test]
+    fn format_check_fails_on_unformatted_input() {
+        let file = TempFile::new("unformatted.asm", "mov #01,@a\nhlt\n");
+
+        assert_eq!(format_files(&["--check".to_string(), file.arg()]), 1);
+        // Checking leaves the file alone
+        assert_eq!(file.contents(), "mov #01,@a\nhlt\n");
+    }
+
+    #[test]
+    fn format_check_passes_on_formatted_input() {
+        let file = TempFile::new("formatted.asm", "MOV #01, @A\nHLT\n");
+        assert_eq!(format_files(&["--check".to_string(), file.arg()]), 0);
+    }
+
+    #[test]
+    fn format_rewrites_unformatted_input() {
+        let file = TempFile::new("rewritten.asm", "mov #01,@a\nhlt\n");
+
+        assert_eq!(format_files(&[file.arg()]), 0);
+        assert_eq!(file.contents(), "MOV #01, @A\nHLT\n");
+        assert_eq!(format_files(&["--check".to_string(), file.arg()]), 0);
+    }
 }
\ No newline at end of file
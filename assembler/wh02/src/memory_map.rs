@@ -0,0 +1,388 @@
+/*
+    Layout of the memory a program is assembled into.
+
+    By default memory is 256 bytes and all of it can hold code. A memory
+    map can set a smaller size and name regions of it:
+
+        rom        code and constants; MOV may not write here
+        ram        anything
+        reserved   never holds code, such as I/O or the stack. MOV may
+                   still read and write here.
+
+    Addresses outside every region can hold anything, as with no map.
+
+    A memory map is read from a `wh02.toml` project file:
+
+        [memory]
+        size = 0x100
+
+        [[region]]
+        name = "io"
+        kind = "reserved"
+        start = 0xF0
+        end = 0xFF
+
+    Only this small part of TOML is understood. Numbers can be decimal,
+    or hex written as `0xF0` or `$F0`. Region ends are inclusive.
+*/
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::memory_map_error::MemoryMapError;
+
+pub const DEFAULT_SIZE: usize = 256;
+// Addresses are a single byte, so memory can be no larger than this
+pub const MAX_SIZE: usize = 256;
+pub const PROJECT_FILE: &str = "wh02.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Rom,
+    Ram,
+    Reserved,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: String,
+    pub kind: RegionKind,
+    pub start: usize,
+    // Last address in the region
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryMap {
+    pub size: usize,
+    pub regions: Vec<Region>,
+}
+
+// Bytes used and free in one region, or in memory outside every region
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionUsage {
+    pub name: String,
+    pub kind: Option<RegionKind>,
+    pub used: usize,
+    pub total: usize,
+}
+
+fn error(message: String, line: Option<usize>) -> MemoryMapError {
+    MemoryMapError {
+        message,
+        line,
+    }
+}
+
+pub fn check_size(size: usize) -> Result<(), MemoryMapError> {
+    if size > MAX_SIZE {
+        return Err(error(
+            format!("Memory size {} is too large. Addresses are one byte, so memory is at most {} bytes", size, MAX_SIZE),
+            None,
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn parse_number(text: &str) -> Option<usize> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).or_else(|| text.strip_prefix('$')) {
+        return usize::from_str_radix(hex, 16).ok();
+    }
+
+    text.parse::<usize>().ok()
+}
+
+impl RegionKind {
+    pub fn from_name(kind: &str) -> Option<RegionKind> {
+        match kind {
+            "rom" => Some(RegionKind::Rom),
+            "ram" => Some(RegionKind::Ram),
+            "reserved" => Some(RegionKind::Reserved),
+            _ => None,
+        }
+    }
+
+    pub fn holds_code(&self) -> bool {
+        *self != RegionKind::Reserved
+    }
+}
+
+impl fmt::Display for RegionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RegionKind::Rom => write!(f, "rom"),
+            RegionKind::Ram => write!(f, "ram"),
+            RegionKind::Reserved => write!(f, "reserved"),
+        }
+    }
+}
+
+impl Region {
+    pub fn from_parts(name: &str, kind: &str, start: &str, end: &str) -> Result<Region, MemoryMapError> {
+        let kind = RegionKind::from_name(kind)
+            .ok_or_else(|| error(format!("Unknown region kind: {}. Expected rom, ram or reserved", kind), None))?;
+        let start = parse_number(start)
+            .ok_or_else(|| error(format!("Invalid region start: {}", start), None))?;
+        let end = parse_number(end)
+            .ok_or_else(|| error(format!("Invalid region end: {}", end), None))?;
+
+        Ok(Region {
+            name: name.to_string(),
+            kind,
+            start,
+            end,
+        })
+    }
+
+    // Reads `name:kind:start-end`, as given on the command line
+    pub fn from_spec(spec: &str) -> Result<Region, MemoryMapError> {
+        let parts: Vec<&str> = spec.split(':').collect();
+        match (parts.as_slice(), parts.get(2).and_then(|range| range.split_once('-'))) {
+            ([name, kind, _], Some((start, end))) => Region::from_parts(name, kind, start, end),
+            _ => Err(error(format!("Invalid region: {}. Expected name:kind:start-end, such as io:reserved:$F0-$FF", spec), None)),
+        }
+    }
+
+    pub fn contains(&self, address: usize) -> bool {
+        self.start <= address && address <= self.end
+    }
+
+    pub fn size(&self) -> usize {
+        self.end + 1 - self.start
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}, ${:02X}-${:02X})", self.name, self.kind, self.start, self.end)
+    }
+}
+
+// The project file for a program, if there is one in its folder
+pub fn project_file(program: &Path) -> Option<PathBuf> {
+    let folder = match program.parent() {
+        Some(folder) if !folder.as_os_str().is_empty() => folder,
+        _ => Path::new("."),
+    };
+
+    let path = folder.join(PROJECT_FILE);
+    if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        MemoryMap::new(DEFAULT_SIZE)
+    }
+}
+
+impl MemoryMap {
+    pub fn new(size: usize) -> MemoryMap {
+        MemoryMap {
+            size,
+            regions: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<MemoryMap, MemoryMapError> {
+        let text = fs::read_to_string(path)
+            .map_err(|io_error| error(format!("Failed to read {}: {}", path.display(), io_error), None))?;
+
+        MemoryMap::from_project(&text).map_err(|mut error| {
+            error.message = format!("{}: {}", path.display(), error.message);
+            error
+        })
+    }
+
+    // A project file, if there is one, with a size and regions from the
+    // command line on top
+    pub fn configure(project: Option<&Path>, size: Option<usize>, regions: Vec<Region>) -> Result<MemoryMap, MemoryMapError> {
+        let mut memory_map = match project {
+            Some(path) => MemoryMap::load(path)?,
+            None => MemoryMap::default(),
+        };
+
+        if let Some(size) = size {
+            memory_map.resize(size)?;
+        }
+
+        for region in regions {
+            memory_map.add_region(region)?;
+        }
+
+        Ok(memory_map)
+    }
+
+    // Changes the size of memory, checking the regions still fit
+    pub fn resize(&mut self, size: usize) -> Result<(), MemoryMapError> {
+        check_size(size)?;
+        self.size = size;
+        for region in std::mem::take(&mut self.regions) {
+            self.add_region(region)?;
+        }
+
+        Ok(())
+    }
+
+    // Reads the `[memory]` and `[[region]]` tables of a project file
+    pub fn from_project(text: &str) -> Result<MemoryMap, MemoryMapError> {
+        let mut map = MemoryMap::default();
+        // Each region with the line its table starts on, for errors
+        let mut regions: Vec<(usize, Vec<(String, String)>)> = Vec::new();
+        let mut table = String::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let number = number + 1;
+            let line = match line.split_once('#') {
+                Some((code, _)) => code.trim(),
+                None => line.trim(),
+            };
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                table = line.to_string();
+                if table == "[[region]]" {
+                    regions.push((number, Vec::new()));
+                }
+                continue;
+            }
+
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| error(format!("Expected key = value, found {}", line), Some(number)))?;
+            let key = key.trim();
+            let value = value.trim().trim_matches('"').to_string();
+
+            match table.as_str() {
+                "[memory]" => match key {
+                    "size" => {
+                        let size = parse_number(&value)
+                            .ok_or_else(|| error(format!("Invalid memory size: {}", value), Some(number)))?;
+                        map.resize(size).map_err(|mut error| {
+                            error.line = Some(number);
+                            error
+                        })?;
+                    },
+                    _ => return Err(error(format!("Unknown memory setting: {}", key), Some(number))),
+                },
+                "[[region]]" => {
+                    if let Some((_, fields)) = regions.last_mut() {
+                        fields.push((key.to_string(), value));
+                    }
+                },
+                // Other tables belong to other tools
+                _ => {},
+            }
+        }
+
+        for (number, fields) in regions {
+            let field = |name: &str| {
+                fields.iter()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.clone())
+                    .ok_or_else(|| error(format!("Region is missing {}", name), Some(number)))
+            };
+
+            let region = Region::from_parts(&field("name")?, &field("kind")?, &field("start")?, &field("end")?).map_err(|mut error| {
+                error.line = Some(number);
+                error
+            })?;
+            map.add_region(region).map_err(|mut error| {
+                error.line = Some(number);
+                error
+            })?;
+        }
+
+        Ok(map)
+    }
+
+    // Adds a region, as long as it fits in memory and does not overlap
+    // another region
+    pub fn add_region(&mut self, region: Region) -> Result<(), MemoryMapError> {
+        if region.start > region.end {
+            return Err(error(format!("Region {} ends before it starts", region), None));
+        }
+
+        if region.end >= self.size {
+            return Err(error(
+                format!("Region {} does not fit in memory, which ends at ${:02X}", region, self.size.saturating_sub(1)),
+                None,
+            ));
+        }
+
+        if let Some(other) = self.regions.iter().find(|other| other.start <= region.end && region.start <= other.end) {
+            return Err(error(format!("Region {} overlaps region {}", region, other), None));
+        }
+
+        self.regions.push(region);
+        self.regions.sort_by_key(|region| region.start);
+        Ok(())
+    }
+
+    pub fn region(&self, name: &str) -> Option<&Region> {
+        self.regions.iter().find(|region| region.name == name)
+    }
+
+    pub fn region_at(&self, address: usize) -> Option<&Region> {
+        self.regions.iter().find(|region| region.contains(address))
+    }
+
+    // Bytes used in each region, then in memory outside every region.
+    // `used` holds one flag per address.
+    pub fn usage(&self, used: &[bool]) -> Vec<RegionUsage> {
+        let count = |start: usize, end: usize| used[start..=end].iter().filter(|used| **used).count();
+
+        let mut usage: Vec<RegionUsage> = self.regions
+            .iter()
+            .map(|region| RegionUsage {
+                name: region.name.clone(),
+                kind: Some(region.kind),
+                used: count(region.start, region.end),
+                total: region.size(),
+            })
+            .collect();
+
+        let unmapped: Vec<usize> = (0..self.size).filter(|address| self.region_at(*address).is_none()).collect();
+        if !unmapped.is_empty() {
+            usage.push(RegionUsage {
+                name: if self.regions.is_empty() { "memory".to_string() } else { "unmapped".to_string() },
+                kind: None,
+                used: unmapped.iter().filter(|address| used[**address]).count(),
+                total: unmapped.len(),
+            });
+        }
+
+        usage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resize_rejects_more_than_a_byte_of_addresses() {
+        let mut memory_map = MemoryMap::default();
+        assert!(memory_map.resize(MAX_SIZE).is_ok());
+        assert!(memory_map.resize(0x80).is_ok());
+        assert!(memory_map.resize(0x200).is_err());
+        assert_eq!(memory_map.size, 0x80);
+    }
+
+    #[test]
+    fn project_rejects_more_than_a_byte_of_addresses() {
+        assert_eq!(MemoryMap::from_project("[memory]\nsize = 0x100\n").unwrap().size, 256);
+
+        let error = MemoryMap::from_project("[memory]\nsize = 512\n").unwrap_err();
+        assert_eq!(error.line, Some(2));
+        assert!(error.message.starts_with("Memory size 512 is too large"), "{}", error);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,17 @@
+use core::fmt;
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct MemoryMapError {
+    pub message: String,
+    // Line of the project file the problem is on, counting from one
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for MemoryMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "MemoryMapError on line {}: {}", line, self.message),
+            None => write!(f, "MemoryMapError: {}", self.message),
+        }
+    }
+}
\ No newline at end of file
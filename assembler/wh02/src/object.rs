@@ -0,0 +1,466 @@
+/*
+    Relocatable object files, written by `wh02 obj` and combined into a
+    program by `wh02-link`.
+
+    An object file holds the sections of one program with every label
+    reference left for the linker to fill in. Symbols are the labels the
+    program defines, as offsets into their sections. A relocation is a
+    byte to fill in with the address of a symbol, which may be defined
+    by another object file.
+
+    Each section is assembled in one piece, one after another. A section
+    that uses START or ORG stays at the addresses it was assembled at;
+    the linker can place any other section wherever there is room.
+
+    Object files, and libraries of them, are stored as JSON:
+
+        {"format": "wh02-object", "version": 1, "name": "delay.asm",
+         "sections": [{"name": "code", "origin": null, "bytes": "21 05 2D 00"}],
+         "symbols": [{"name": "delay", "section": "code", "offset": 0}],
+         "relocations": [{"section": "code", "offset": 3, "symbol": "delay", "line": 2}]}
+
+        {"format": "wh02-library", "version": 1, "members": [...]}
+*/
+
+use wh02_lexer::position::Position;
+use wh02_parser::expressions::Expressions;
+use wh02_parser::keyword::Keyword;
+
+use crate::assembler::{Assembler, DEFAULT_SECTION};
+use crate::assembler_error::AssemblerError;
+use crate::json::Json;
+use crate::object_error::ObjectError;
+
+pub const OBJECT_FORMAT: &str = "wh02-object";
+pub const LIBRARY_FORMAT: &str = "wh02-library";
+pub const VERSION: usize = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectSection {
+    pub name: String,
+    // Where the section must go, if it uses START or ORG
+    pub origin: Option<usize>,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub section: String,
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    pub section: String,
+    pub offset: usize,
+    pub symbol: String,
+    // Source line of the reference, counting from one
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectFile {
+    // The program the object was assembled from
+    pub name: String,
+    pub sections: Vec<ObjectSection>,
+    pub symbols: Vec<Symbol>,
+    pub relocations: Vec<Relocation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Library {
+    pub members: Vec<ObjectFile>,
+}
+
+fn error(message: String) -> ObjectError {
+    ObjectError {
+        message,
+    }
+}
+
+fn field<'a>(json: &'a Json, key: &str) -> Result<&'a Json, ObjectError> {
+    json.get(key).ok_or_else(|| error(format!("Missing {}", key)))
+}
+
+fn string_field(json: &Json, key: &str) -> Result<String, ObjectError> {
+    field(json, key)?
+        .as_str()
+        .map(|value| value.to_string())
+        .ok_or_else(|| error(format!("Expected {} to be a string", key)))
+}
+
+fn number_field(json: &Json, key: &str) -> Result<usize, ObjectError> {
+    field(json, key)?
+        .as_u64()
+        .map(|value| value as usize)
+        .ok_or_else(|| error(format!("Expected {} to be a whole number", key)))
+}
+
+fn array_field<'a>(json: &'a Json, key: &str) -> Result<&'a Vec<Json>, ObjectError> {
+    field(json, key)?
+        .as_array()
+        .ok_or_else(|| error(format!("Expected {} to be an array", key)))
+}
+
+// Checks the format and version of an object file or library
+fn check_header(json: &Json, format: &str) -> Result<(), ObjectError> {
+    let found = string_field(json, "format")?;
+    if found != format {
+        return Err(error(format!("Expected a {} file, found {}", format, found)));
+    }
+
+    match number_field(json, "version")? {
+        VERSION => Ok(()),
+        version => Err(error(format!("Unsupported {} version {}. Expected {}", format, version, VERSION))),
+    }
+}
+
+// Puts the expressions of each section together, in the order the
+// sections first appear, so that every section is assembled in one piece
+fn group_sections(expressions: Vec<Expressions>, positions: Vec<Position>) -> (Vec<Expressions>, Vec<Position>) {
+    let mut groups: Vec<(String, Vec<(Expressions, Position)>)> = vec![(DEFAULT_SECTION.to_string(), Vec::new())];
+    let mut current = 0;
+
+    for (i, expression) in expressions.into_iter().enumerate() {
+        if let Expressions::UnaryExpression { keyword: Keyword::SECTION, operand } = &expression {
+            current = match groups.iter().position(|(name, _)| *name == operand.value) {
+                Some(group) => group,
+                None => {
+                    groups.push((operand.value.clone(), Vec::new()));
+                    groups.len() - 1
+                }
+            };
+        }
+
+        let position = positions.get(i).copied().unwrap_or_default();
+        groups[current].1.push((expression, position));
+    }
+
+    groups.into_iter().flat_map(|(_, group)| group).unzip()
+}
+
+impl ObjectFile {
+    // Assembles a parsed program into an object file
+    pub fn assemble(name: &str, expressions: Vec<Expressions>, positions: Vec<Position>) -> Result<ObjectFile, AssemblerError> {
+        let (expressions, positions) = group_sections(expressions, positions);
+
+        let mut assembler = Assembler::new(expressions);
+        assembler.positions = positions;
+        assembler.relocatable = true;
+        assembler.assemble()?;
+
+        Ok(ObjectFile::from_assembler(name, &assembler))
+    }
+
+    fn from_assembler(name: &str, assembler: &Assembler) -> ObjectFile {
+        let mut names: Vec<&String> = Vec::new();
+        for section in &assembler.sections {
+            if !names.contains(&section) {
+                names.push(section);
+            }
+        }
+
+        let mut sections = Vec::new();
+        let mut starts = Vec::new();
+        for name in names {
+            let members: Vec<usize> = (0..assembler.expressions.len())
+                .filter(|i| assembler.sections[*i] == *name)
+                .collect();
+            let directive = |i: &usize| matches!(
+                assembler.expressions[*i],
+                Expressions::UnaryExpression { keyword: Keyword::START | Keyword::ORG | Keyword::SECTION, .. }
+            );
+
+            // Directives sit at the address before the one they move to,
+            // so they do not count towards where the section starts
+            let placed: Vec<usize> = members.iter().copied().filter(|i| !directive(i)).collect();
+            let start = placed.iter().map(|i| assembler.addresses[*i]).min().unwrap_or(0);
+            let end = placed.iter().map(|i| assembler.addresses[*i] + assembler.sizes[*i]).max().unwrap_or(start);
+            let fixed = members.iter().any(|i| matches!(
+                assembler.expressions[*i],
+                Expressions::UnaryExpression { keyword: Keyword::START | Keyword::ORG, .. }
+            ));
+
+            sections.push(ObjectSection {
+                name: name.clone(),
+                origin: if fixed { Some(start) } else { None },
+                bytes: assembler.assembled[start..end]
+                    .iter()
+                    .map(|byte| u8::from_str_radix(byte, 16).unwrap_or(0))
+                    .collect(),
+            });
+            starts.push((name, start));
+        }
+
+        let start_of = |section: &String| starts.iter()
+            .find(|(name, _)| *name == section)
+            .map(|(_, start)| *start)
+            .unwrap_or(0);
+
+        let symbols = assembler.expressions
+            .iter()
+            .enumerate()
+            .filter_map(|(i, expression)| match expression {
+                Expressions::UnaryExpression { keyword: Keyword::DEF, operand } => Some(Symbol {
+                    name: operand.value.clone(),
+                    section: assembler.sections[i].clone(),
+                    offset: assembler.addresses[i] - start_of(&assembler.sections[i]),
+                }),
+                _ => None,
+            })
+            .collect();
+
+        let relocations = assembler.references
+            .iter()
+            .map(|reference| {
+                let section = &assembler.sections[reference.expression];
+                Relocation {
+                    section: section.clone(),
+                    offset: reference.address - start_of(section),
+                    symbol: reference.label.clone(),
+                    line: assembler.positions.get(reference.expression).map(|position| position.line as usize + 1).unwrap_or(0),
+                }
+            })
+            .collect();
+
+        ObjectFile {
+            name: name.to_string(),
+            sections,
+            symbols,
+            relocations,
+        }
+    }
+
+    pub fn section(&self, name: &str) -> Option<&ObjectSection> {
+        self.sections.iter().find(|section| section.name == name)
+    }
+
+    // Symbols used by relocations but not defined here
+    pub fn undefined(&self) -> Vec<&str> {
+        let mut undefined: Vec<&str> = Vec::new();
+        for relocation in &self.relocations {
+            let defined = self.symbols.iter().any(|symbol| symbol.name == relocation.symbol);
+            if !defined && !undefined.contains(&relocation.symbol.as_str()) {
+                undefined.push(&relocation.symbol);
+            }
+        }
+
+        undefined
+    }
+
+    pub fn to_json(&self) -> Json {
+        let sections = self.sections
+            .iter()
+            .map(|section| Json::object(vec![
+                ("name", section.name.as_str().into()),
+                ("origin", section.origin.map(Json::from).unwrap_or(Json::Null)),
+                (
+                    "bytes",
+                    section.bytes.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<String>>().join(" ").into(),
+                ),
+            ]))
+            .collect::<Vec<Json>>();
+
+        let symbols = self.symbols
+            .iter()
+            .map(|symbol| Json::object(vec![
+                ("name", symbol.name.as_str().into()),
+                ("section", symbol.section.as_str().into()),
+                ("offset", symbol.offset.into()),
+            ]))
+            .collect::<Vec<Json>>();
+
+        let relocations = self.relocations
+            .iter()
+            .map(|relocation| Json::object(vec![
+                ("section", relocation.section.as_str().into()),
+                ("offset", relocation.offset.into()),
+                ("symbol", relocation.symbol.as_str().into()),
+                ("line", relocation.line.into()),
+            ]))
+            .collect::<Vec<Json>>();
+
+        Json::object(vec![
+            ("format", OBJECT_FORMAT.into()),
+            ("version", VERSION.into()),
+            ("name", self.name.as_str().into()),
+            ("sections", sections.into()),
+            ("symbols", symbols.into()),
+            ("relocations", relocations.into()),
+        ])
+    }
+
+    pub fn from_json(json: &Json) -> Result<ObjectFile, ObjectError> {
+        check_header(json, OBJECT_FORMAT)?;
+        let name = string_field(json, "name")?;
+
+        let mut sections = Vec::new();
+        for section in array_field(json, "sections")? {
+            let origin = match field(section, "origin")? {
+                Json::Null => None,
+                _ => Some(number_field(section, "origin")?),
+            };
+
+            let bytes = string_field(section, "bytes")?
+                .split_whitespace()
+                .map(|byte| u8::from_str_radix(byte, 16).map_err(|_| error(format!("Invalid byte: {}", byte))))
+                .collect::<Result<Vec<u8>, ObjectError>>()?;
+
+            sections.push(ObjectSection {
+                name: string_field(section, "name")?,
+                origin,
+                bytes,
+            });
+        }
+
+        let mut object = ObjectFile {
+            name,
+            sections,
+            symbols: Vec::new(),
+            relocations: Vec::new(),
+        };
+
+        for symbol in array_field(json, "symbols")? {
+            let symbol = Symbol {
+                name: string_field(symbol, "name")?,
+                section: string_field(symbol, "section")?,
+                offset: number_field(symbol, "offset")?,
+            };
+
+            match object.section(&symbol.section) {
+                Some(section) if symbol.offset <= section.bytes.len() => object.symbols.push(symbol),
+                _ => return Err(error(format!("Symbol {} is outside its section {}", symbol.name, symbol.section))),
+            }
+        }
+
+        for relocation in array_field(json, "relocations")? {
+            let relocation = Relocation {
+                section: string_field(relocation, "section")?,
+                offset: number_field(relocation, "offset")?,
+                symbol: string_field(relocation, "symbol")?,
+                line: number_field(relocation, "line")?,
+            };
+
+            match object.section(&relocation.section) {
+                Some(section) if relocation.offset < section.bytes.len() => object.relocations.push(relocation),
+                _ => return Err(error(format!(
+                    "Relocation for {} on line {} is outside its section {}",
+                    relocation.symbol,
+                    relocation.line,
+                    relocation.section,
+                ))),
+            }
+        }
+
+        Ok(object)
+    }
+}
+
+impl Library {
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("format", LIBRARY_FORMAT.into()),
+            ("version", VERSION.into()),
+            ("members", self.members.iter().map(|member| member.to_json()).collect::<Vec<Json>>().into()),
+        ])
+    }
+
+    pub fn from_json(json: &Json) -> Result<Library, ObjectError> {
+        check_header(json, LIBRARY_FORMAT)?;
+
+        let members = array_field(json, "members")?
+            .iter()
+            .map(ObjectFile::from_json)
+            .collect::<Result<Vec<ObjectFile>, ObjectError>>()?;
+
+        Ok(Library {
+            members,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wh02_lexer::Lexer;
+    use wh02_parser::Parser;
+
+    use super::*;
+
+    fn object(source: &str) -> ObjectFile {
+        let mut parser = Parser::new(Lexer::new(source));
+        parser.parse_all();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        ObjectFile::assemble("test.asm", parser.expressions, parser.positions).unwrap()
+    }
+
+    fn round_trip(object: &ObjectFile) -> ObjectFile {
+        let json = Json::parse(&object.to_json().to_string()).unwrap();
+        ObjectFile::from_json(&json).unwrap()
+    }
+
+    #[test]
+    fn records_symbols_and_relocations() {
+        let object = object("DEF main\nMOV #05, @O1\nJMP main\nCALL delay\n");
+
+        assert_eq!(object.sections.len(), 1);
+        assert_eq!(object.sections[0].name, "code");
+        assert_eq!(object.sections[0].origin, None);
+        assert_eq!(object.symbols, vec![Symbol {
+            name: "main".to_string(),
+            section: "code".to_string(),
+            offset: 0,
+        }]);
+
+        // Each operand byte follows its opcode
+        let relocations: Vec<(usize, &str, usize)> = object.relocations
+            .iter()
+            .map(|relocation| (relocation.offset, relocation.symbol.as_str(), relocation.line))
+            .collect();
+        assert_eq!(relocations, vec![(3, "main", 3), (5, "delay", 4)]);
+        assert_eq!(object.undefined(), vec!["delay"]);
+    }
+
+    #[test]
+    fn keeps_origin_of_fixed_sections() {
+        let object = object("MOV #01, @O1\nSECTION data\nORG $F0\nDEF table\nJMP table\n");
+
+        let data = object.section("data").unwrap();
+        assert_eq!(data.origin, Some(0xF0));
+        assert_eq!(data.bytes.len(), 2);
+        assert_eq!(object.section("code").unwrap().origin, None);
+        assert_eq!(object.symbols[0].section, "data");
+        assert_eq!(object.symbols[0].offset, 0);
+    }
+
+    #[test]
+    fn object_round_trips_through_json() {
+        let object = object("DEF main\nMOV #05, @O1\nCALL delay\nSECTION data\nORG $F0\nDEF table\nJMP main\n");
+        assert_eq!(round_trip(&object), object);
+    }
+
+    #[test]
+    fn library_round_trips_through_json() {
+        let library = Library {
+            members: vec![object("DEF one\nRET\n"), object("DEF two\nCALL one\nRET\n")],
+        };
+        let json = Json::parse(&library.to_json().to_string()).unwrap();
+        assert_eq!(Library::from_json(&json).unwrap(), library);
+    }
+
+    #[test]
+    fn rejects_relocation_outside_its_section() {
+        let mut object = object("JMP main\nDEF main\nHLT\n");
+        object.relocations[0].offset = 10;
+        let json = Json::parse(&object.to_json().to_string()).unwrap();
+        assert!(ObjectFile::from_json(&json).is_err());
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        let library = Library {
+            members: Vec::new(),
+        };
+        assert!(ObjectFile::from_json(&library.to_json()).is_err());
+    }
+}
\ No newline at end of file
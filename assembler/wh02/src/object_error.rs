@@ -0,0 +1,12 @@
+use core::fmt;
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct ObjectError {
+    pub message: String,
+}
+
+impl fmt::Display for ObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ObjectError: {}", self.message)
+    }
+}
\ No newline at end of file
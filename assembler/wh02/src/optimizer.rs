@@ -0,0 +1,288 @@
+/*
+    Peephole optimizer, run with `wh02 -O`.
+
+    The optimizer looks at pairs of neighbouring instructions and
+    rewrites them into something smaller that leaves the registers and
+    memory the same:
+
+        NOP                            removed, unless marked `; keep`
+        MOV @A,@B / MOV @B,@A          the second move is removed
+        MOV #05,@A / MOV @A,@O1        MOV #05,@O1, when @A is not read again
+        MOV #05,@A / MOV #05,@B        MOV #05,@A / MOV @A,@B
+
+    A label between two instructions keeps them apart, since code can
+    jump to the second. After each rewrite the program is assembled
+    again, so register liveness and label addresses stay current.
+
+    Removing code moves everything after it, so programs that jump to a
+    fixed address instead of a label are not optimized.
+*/
+
+use wh02_lexer::position::Position;
+use wh02_parser::expressions::Expressions;
+use wh02_parser::keyword::Keyword;
+use wh02_parser::operand::Operand;
+
+use crate::assembler::Assembler;
+use crate::assembler_error::AssemblerError;
+use crate::dataflow::{register, DataFlow, WORKING_REGISTERS};
+
+#[derive(Debug, Clone)]
+pub struct Rewrite {
+    pub position: Position,
+    pub message: String,
+}
+
+// One rewrite to make, starting at an expression: an expression to put
+// in place of another, then one to drop
+struct Change {
+    at: usize,
+    replace: Option<(usize, Expressions)>,
+    remove: Option<usize>,
+    message: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Optimizer {
+    pub rewrites: Vec<Rewrite>,
+}
+
+fn mov(operand1: Operand, operand2: Operand) -> Expressions {
+    Expressions::BinaryExpression {
+        keyword: Keyword::MOV,
+        operand1,
+        comma: ",".to_string(),
+        operand2,
+    }
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Optimizer {
+            rewrites: Vec::new(),
+        }
+    }
+
+    // The first JMP or CALL to a fixed address, which would point
+    // somewhere else once code is removed before it
+    pub fn fixed_jump(assembler: &Assembler) -> Option<usize> {
+        assembler.expressions.iter().position(|expression| matches!(
+            expression,
+            Expressions::UnaryExpression { keyword: Keyword::JMP | Keyword::CALL, operand } if !operand.is_label()
+        ))
+    }
+
+    // Rewrites the program until nothing more can be done. The source is
+    // used to find `; keep` comments.
+    pub fn optimize(&mut self, source: &str, assembler: &mut Assembler) -> Result<(), AssemblerError> {
+        let lines: Vec<&str> = source.lines().collect();
+        assembler.assemble()?;
+
+        while let Some(change) = self.find_change(&lines, assembler) {
+            self.rewrites.push(Rewrite {
+                position: assembler.positions.get(change.at).copied().unwrap_or_default(),
+                message: change.message,
+            });
+
+            if let Some((index, expression)) = change.replace {
+                assembler.expressions[index] = expression;
+            }
+
+            if let Some(index) = change.remove {
+                assembler.expressions.remove(index);
+                if index < assembler.positions.len() {
+                    assembler.positions.remove(index);
+                }
+            }
+
+            assembler.assemble()?;
+        }
+
+        Ok(())
+    }
+
+    fn is_kept(lines: &[&str], position: Option<&Position>) -> bool {
+        let line = position.and_then(|position| lines.get(position.line as usize));
+        match line.and_then(|line| line.split_once(';')) {
+            Some((_, comment)) => comment.split_whitespace().any(|word| word == "keep"),
+            None => false,
+        }
+    }
+
+    fn find_change(&self, lines: &[&str], assembler: &Assembler) -> Option<Change> {
+        let expressions = &assembler.expressions;
+        let flow = DataFlow::new(assembler);
+
+        for i in 0..expressions.len() {
+            if let Expressions::NoOperandExpression { keyword: Keyword::NOP } = &expressions[i] {
+                if !Self::is_kept(lines, assembler.positions.get(i)) {
+                    return Some(Change {
+                        at: i,
+                        replace: None,
+                        remove: Some(i),
+                        message: "Removed NOP".to_string(),
+                    });
+                }
+            }
+
+            let (first, second) = match (&expressions[i], expressions.get(i + 1)) {
+                (
+                    Expressions::BinaryExpression { keyword: Keyword::MOV, operand1: source1, operand2: destination1, .. },
+                    Some(Expressions::BinaryExpression { keyword: Keyword::MOV, operand1: source2, operand2: destination2, .. }),
+                ) => ((source1, destination1), (source2, destination2)),
+                _ => continue,
+            };
+
+            // MOV @A,@B / MOV @B,@A: the second move changes nothing
+            if register(first.0) != 0 && register(first.1) != 0 && first.0 == second.1 && first.1 == second.0 {
+                return Some(Change {
+                    at: i + 1,
+                    replace: None,
+                    remove: Some(i + 1),
+                    message: format!(
+                        "Removed {}, which copies back the value {} just copied",
+                        expressions[i + 1],
+                        expressions[i],
+                    ),
+                });
+            }
+
+            // MOV #05,@A / MOV @A,@O1, with @A not read again: MOV #05,@O1
+            let through = register(first.1);
+            let live = flow.node(i + 1).map(|node| flow.live[node]).unwrap_or(u8::MAX);
+            if first.0.indicator == '#'
+                && through & WORKING_REGISTERS != 0
+                && first.1 == second.0
+                && register(second.1) != 0
+                && live & through == 0
+            {
+                let folded = mov(first.0.clone(), second.1.clone());
+                return Some(Change {
+                    at: i,
+                    message: format!(
+                        "Folded {} and {} into {}",
+                        expressions[i],
+                        expressions[i + 1],
+                        folded,
+                    ),
+                    replace: Some((i, folded)),
+                    remove: Some(i + 1),
+                });
+            }
+
+            // MOV #05,@A / MOV #05,@B: copy the register instead of
+            // loading the value twice, which is a byte shorter
+            if first.0.indicator == '#'
+                && *first.0 == *second.0
+                && register(first.1) != 0
+                && register(second.1) != 0
+                && first.1 != second.1
+            {
+                let copy = mov(first.1.clone(), second.1.clone());
+                return Some(Change {
+                    at: i + 1,
+                    message: format!("Replaced {} with {}", expressions[i + 1], copy),
+                    replace: Some((i + 1, copy)),
+                    remove: None,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wh02_lexer::Lexer;
+    use wh02_parser::Parser;
+
+    use super::*;
+
+    fn assemble(source: &str) -> Assembler {
+        let mut parser = Parser::new(Lexer::new(source));
+        parser.parse_all();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+
+        let mut assembler = Assembler::new(parser.expressions);
+        assembler.positions = parser.positions;
+        assembler
+    }
+
+    // The program after optimizing, one instruction per line
+    fn optimize(source: &str) -> Vec<String> {
+        let mut assembler = assemble(source);
+        Optimizer::new().optimize(source, &mut assembler).unwrap();
+        assembler.expressions.iter().map(|expression| expression.to_string()).collect()
+    }
+
+    fn lines(source: &str) -> Vec<String> {
+        source.lines().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn removes_nop() {
+        assert_eq!(optimize("NOP\nMOV #05, @O1\nHLT\n"), lines("MOV #05, @O1\nHLT"));
+    }
+
+    #[test]
+    fn keeps_marked_nop() {
+        assert_eq!(optimize("NOP ; keep\nHLT\n"), lines("NOP\nHLT"));
+    }
+
+    #[test]
+    fn removes_copy_back() {
+        assert_eq!(
+            optimize("MOV @A, @B\nMOV @B, @A\nMOV @B, @O1\nHLT\n"),
+            lines("MOV @A, @B\nMOV @B, @O1\nHLT"),
+        );
+    }
+
+    // Registers are live when the program halts, so each of these loads
+    // the register again before HLT
+    #[test]
+    fn folds_load_into_copy() {
+        assert_eq!(
+            optimize("MOV #05, @A\nMOV @A, @O1\nMOV #00, @A\nHLT\n"),
+            lines("MOV #05, @O1\nMOV #00, @A\nHLT"),
+        );
+    }
+
+    #[test]
+    fn keeps_load_when_register_is_read_again() {
+        let source = "MOV #05, @C\nMOV @C, @O1\nMOV @C, @O2\nMOV #00, @C\nHLT\n";
+        assert_eq!(optimize(source), lines(source));
+    }
+
+    #[test]
+    fn keeps_load_when_acc_is_read_again() {
+        // Loading A relatches ACC, so a later MOV @ACC reads A
+        let source = "MOV #05, @A\nMOV @A, @O1\nMOV @ACC, @O2\nMOV #00, @A\nHLT\n";
+        assert_eq!(optimize(source), lines(source));
+    }
+
+    #[test]
+    fn copies_repeated_load() {
+        assert_eq!(
+            optimize("MOV #05, @A\nMOV #05, @B\nMOV @ACC, @O1\nHLT\n"),
+            lines("MOV #05, @A\nMOV @A, @B\nMOV @ACC, @O1\nHLT"),
+        );
+    }
+
+    #[test]
+    fn label_keeps_instructions_apart() {
+        let source = "MOV @A, @B\nDEF back\nMOV @B, @A\nMOV @A, @O1\nJMP back\n";
+        assert_eq!(optimize(source), lines(source));
+    }
+
+    #[test]
+    fn refuses_fixed_jump() {
+        let mut assembler = assemble("NOP\nJMP $00\n");
+        assembler.assemble().unwrap();
+        assert_eq!(Optimizer::fixed_jump(&assembler), Some(1));
+
+        let mut assembler = assemble("NOP\nDEF begin\nJMP begin\n");
+        assembler.assemble().unwrap();
+        assert_eq!(Optimizer::fixed_jump(&assembler), None);
+    }
+}
\ No newline at end of file
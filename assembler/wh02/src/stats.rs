@@ -0,0 +1,255 @@
+/*
+    Size and timing report for an assembled program.
+
+    Cycle counts come from the control ROM that `RomBuilder` generates,
+    so they always match the microcode. The report lists every
+    instruction, totals for each label and the cost of one iteration of
+    each loop. A loop is found from a jump back to a block on the way to
+    it from the start; its body is every block that can reach the jump
+    without passing through the block jumped to.
+*/
+
+use std::fmt;
+
+use rom_builder::RomBuilder;
+use wh02_parser::expressions::Expressions;
+use wh02_parser::keyword::Keyword;
+
+use crate::assembler::Assembler;
+use crate::cfg::{CfgInstruction, ControlFlowGraph};
+
+// Instructions from one label up to the next
+#[derive(Debug, Clone)]
+pub struct SectionStats {
+    // Empty for code before the first label
+    pub labels: Vec<String>,
+    pub instructions: usize,
+    pub bytes: usize,
+    pub cycles: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoopStats {
+    // Block that the loop jumps back to, and the block that jumps
+    pub header: usize,
+    pub latch: usize,
+    pub blocks: Vec<usize>,
+    pub cycles: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Stats {
+    pub graph: ControlFlowGraph,
+    pub sections: Vec<SectionStats>,
+    pub loops: Vec<LoopStats>,
+}
+
+impl Stats {
+    pub fn build(assembler: &Assembler, rom: &RomBuilder) -> Stats {
+        let graph = ControlFlowGraph::build(assembler, rom);
+        let sections = Self::sections(assembler, &graph);
+        let loops = Self::loops(&graph);
+
+        Stats {
+            graph,
+            sections,
+            loops,
+        }
+    }
+
+    pub fn instructions(&self) -> impl Iterator<Item = &CfgInstruction> {
+        self.graph.blocks.iter().flat_map(|block| block.instructions.iter())
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.instructions().map(|instruction| instruction.bytes.len()).sum()
+    }
+
+    // Cycles to run every instruction once
+    pub fn cycles(&self) -> usize {
+        self.instructions().map(|instruction| instruction.cycles).sum()
+    }
+
+    fn sections(assembler: &Assembler, graph: &ControlFlowGraph) -> Vec<SectionStats> {
+        let instructions: Vec<&CfgInstruction> = graph.blocks
+            .iter()
+            .flat_map(|block| block.instructions.iter())
+            .collect();
+
+        let mut sections = vec![SectionStats {
+            labels: Vec::new(),
+            instructions: 0,
+            bytes: 0,
+            cycles: 0,
+        }];
+
+        for (i, expression) in assembler.expressions.iter().enumerate() {
+            if let Expressions::UnaryExpression { keyword: Keyword::DEF, operand } = expression {
+                let section = sections.last_mut().expect("There is always a section");
+                // Labels with no code between them share a section
+                if section.instructions == 0 {
+                    section.labels.push(operand.value.clone());
+                } else {
+                    sections.push(SectionStats {
+                        labels: vec![operand.value.clone()],
+                        instructions: 0,
+                        bytes: 0,
+                        cycles: 0,
+                    });
+                }
+                continue;
+            }
+
+            if let Some(instruction) = instructions.iter().find(|instruction| instruction.expression == i) {
+                let section = sections.last_mut().expect("There is always a section");
+                section.instructions += 1;
+                section.bytes += instruction.bytes.len();
+                section.cycles += instruction.cycles;
+            }
+        }
+
+        sections.retain(|section| section.instructions > 0 || !section.labels.is_empty());
+        sections
+    }
+
+    fn loops(graph: &ControlFlowGraph) -> Vec<LoopStats> {
+        let mut predecessors = vec![Vec::new(); graph.blocks.len()];
+        for block in &graph.blocks {
+            for successor in &block.successors {
+                predecessors[*successor].push(block.id);
+            }
+        }
+
+        let mut loops = Vec::new();
+        for (latch, header) in Self::back_edges(graph) {
+            // Walk backwards from the jump, stopping at the header
+            let mut body = vec![header];
+            let mut pending = vec![latch];
+            while let Some(id) = pending.pop() {
+                if body.contains(&id) {
+                    continue;
+                }
+                body.push(id);
+                pending.extend(predecessors[id].iter().copied());
+            }
+            body.sort();
+
+            loops.push(LoopStats {
+                header,
+                latch,
+                cycles: body.iter().map(|id| graph.blocks[*id].cycles).sum(),
+                blocks: body,
+            });
+        }
+
+        loops
+    }
+
+    // Jumps to a block that is still being walked from the first block.
+    // Addresses do not say which way a jump goes once ORG and SECTION
+    // move code around.
+    fn back_edges(graph: &ControlFlowGraph) -> Vec<(usize, usize)> {
+        let mut edges = Vec::new();
+        if graph.blocks.is_empty() {
+            return edges;
+        }
+
+        let mut visited = vec![false; graph.blocks.len()];
+        let mut on_path = vec![false; graph.blocks.len()];
+        // Each block on the path, with the next successor to look at
+        let mut path = vec![(0, 0)];
+        visited[0] = true;
+        on_path[0] = true;
+
+        while let Some((block, next)) = path.last_mut() {
+            let block = *block;
+            match graph.blocks[block].successors.get(*next) {
+                Some(successor) => {
+                    *next += 1;
+                    if on_path[*successor] {
+                        edges.push((block, *successor));
+                    } else if !visited[*successor] {
+                        visited[*successor] = true;
+                        on_path[*successor] = true;
+                        path.push((*successor, 0));
+                    }
+                },
+                None => {
+                    on_path[block] = false;
+                    path.pop();
+                }
+            }
+        }
+
+        edges.sort();
+        edges
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Address  Bytes     Cycles  Line  Source")?;
+        for instruction in self.instructions() {
+            writeln!(
+                f,
+                "${:02X}      {:<8}  {:>6}  {:>4}  {}",
+                instruction.address,
+                instruction.bytes.join(" "),
+                instruction.cycles,
+                instruction.line + 1,
+                instruction.source,
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Labels")?;
+        for section in &self.sections {
+            let name = if section.labels.is_empty() {
+                "(before the first label)".to_string()
+            } else {
+                section.labels.join(", ")
+            };
+            writeln!(
+                f,
+                "  {:<24}  {:>3} instructions  {:>3} bytes  {:>4} cycles",
+                name,
+                section.instructions,
+                section.bytes,
+                section.cycles,
+            )?;
+        }
+
+        if !self.loops.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Loops")?;
+            for program_loop in &self.loops {
+                let header = &self.graph.blocks[program_loop.header];
+                let latch = &self.graph.blocks[program_loop.latch];
+                let last = &latch.instructions[latch.instructions.len() - 1];
+                let name = match header.labels.first() {
+                    Some(label) => format!("{} ", label),
+                    None => String::new(),
+                };
+                writeln!(
+                    f,
+                    "  {}${:02X} to ${:02X} (lines {} to {}): {} cycles per iteration",
+                    name,
+                    header.start(),
+                    latch.end(),
+                    header.instructions[0].line + 1,
+                    last.line + 1,
+                    program_loop.cycles,
+                )?;
+            }
+        }
+
+        writeln!(f)?;
+        write!(
+            f,
+            "Total: {} instructions, {} bytes, {} cycles to run each instruction once",
+            self.instructions().count(),
+            self.bytes(),
+            self.cycles(),
+        )
+    }
+}
\ No newline at end of file
@@ -0,0 +1,576 @@
+/*
+    Checks that the control ROM in WH02.circ is wired the way rom_builder
+    expects.
+
+    rom_builder only knows the control word as fields of bits, and the
+    registers as positions in the select decoders. This follows each bit
+    of the ROM's data output through wires, splitters and tunnels, and
+    compares where it ends up with the fields in `control_word`:
+
+    - the register selects and enables reach the select and enable of a
+      decoder, and each decoder output reaches the tunnel of the register
+      rom_builder gives that position, such as OUTPUT_ACC for `Source::ACC`
+    - every other field reaches the tunnel named after it, bit for bit
+    - something else in the circuit reads each of those tunnels
+    - bits rom_builder does not use are not wired to anything
+
+    Only the components on the way are understood: wires, tunnels,
+    splitters and decoders. Their ports are placed the way Logisim places
+    them, so a component with settings Logisim allows but this does not
+    know is reported rather than guessed at.
+*/
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use rom_builder::control_word::{
+    Field, Source, Target, FIELDS, INPUT_ENABLE, INPUT_SELECT, OUTPUT_ENABLE, OUTPUT_SELECT,
+};
+
+use crate::circ::{Circ, Component};
+use crate::circ_error::CircError;
+
+type Point = (i64, i64);
+
+// Where a bit of the control word ends up
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Sink {
+    Tunnel { label: String, bit: u32 },
+    // Indexes into `Netlist::decoders`
+    Select { decoder: usize, bit: u32 },
+    Enable { decoder: usize },
+}
+
+struct Splitter {
+    combined: Point,
+    ends: Vec<Point>,
+    // The end each bit of the combined side goes to, if any
+    bits: Vec<Option<usize>>,
+}
+
+impl Splitter {
+    fn new(component: &Component) -> Result<Splitter, CircError> {
+        let incoming = number(component, "incoming", 2)? as usize;
+        let fanout = number(component, "fanout", 2)? as i64;
+        let spacing = number(component, "spacing", 1)? as i64;
+
+        let justify = match component.attribute("appear").unwrap_or("left") {
+            "left" => 1,
+            "right" => -1,
+            "center" | "legacy" => 0,
+            appear => return Err(unsupported(component, "appear", appear)),
+        };
+
+        // Offsets of the first end, and from one end to the next
+        let (x, y) = component.location;
+        let (first, step) = match component.attribute("facing").unwrap_or("east") {
+            facing @ ("east" | "west") => {
+                let m = if facing == "west" { -1 } else { 1 };
+                let dy = match justify {
+                    0 => -10 * spacing * (fanout / 2),
+                    _ if m * justify > 0 => -10 * spacing * fanout,
+                    _ => 10,
+                };
+                ((m * 20, dy), (0, 10 * spacing))
+            },
+            facing @ ("north" | "south") => {
+                let m = if facing == "north" { 1 } else { -1 };
+                let dx = match justify {
+                    0 => 10 * spacing * ((fanout + 1) / 2 - 1),
+                    _ if m * justify < 0 => -10,
+                    _ => 10 * spacing * fanout,
+                };
+                ((dx, -m * 20), (-10 * spacing, 0))
+            },
+            facing => return Err(unsupported(component, "facing", facing)),
+        };
+        let ends = (0..fanout)
+            .map(|end| (x + first.0 + step.0 * end, y + first.1 + step.1 * end))
+            .collect();
+
+        // Logisim spreads the bits evenly over the ends, giving the
+        // first ends one more when they do not divide, unless a bit is
+        // set by hand
+        let fanout = fanout as usize;
+        let mut bits = Vec::new();
+        for bit in 0..incoming {
+            let default = if fanout >= incoming {
+                bit
+            } else {
+                let (each, extra) = (incoming / fanout, incoming % fanout);
+                if bit < extra * (each + 1) {
+                    bit / (each + 1)
+                } else {
+                    extra + (bit - extra * (each + 1)) / each
+                }
+            };
+
+            let end = match component.attribute(&format!("bit{}", bit)) {
+                None => Some(default),
+                Some("none") => None,
+                Some(value) => match value.parse::<usize>() {
+                    Ok(end) if end < fanout => Some(end),
+                    _ => return Err(unsupported(component, &format!("bit{}", bit), value)),
+                },
+            };
+            bits.push(end);
+        }
+
+        Ok(Splitter {
+            combined: component.location,
+            ends,
+            bits,
+        })
+    }
+
+    // Where a bit of the combined side sits among the bits of its end
+    fn position(&self, bit: usize) -> Option<(usize, u32)> {
+        let end = self.bits.get(bit).copied().flatten()?;
+        let before = self.bits[..bit].iter().filter(|other| **other == Some(end)).count();
+        Some((end, before as u32))
+    }
+}
+
+struct Decoder<'a> {
+    component: &'a Component,
+    width: u32,
+    select: Point,
+    enable: Option<Point>,
+    outputs: Vec<Point>,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(component: &'a Component) -> Result<Decoder<'a>, CircError> {
+        let width = number(component, "select", 1)?;
+        let count = 1i64 << width;
+        if count == 2 {
+            return Err(unsupported(component, "select", "1"));
+        }
+        if let Some(location) = component.attribute("selloc").filter(|location| *location != "bl") {
+            return Err(unsupported(component, "selloc", location));
+        }
+
+        let m = match component.attribute("facing").unwrap_or("east") {
+            "east" => 1,
+            "west" => -1,
+            facing => return Err(unsupported(component, "facing", facing)),
+        };
+
+        let (x, y) = component.location;
+        Ok(Decoder {
+            component,
+            width,
+            select: (x, y),
+            enable: match component.attribute("enable") {
+                Some("false") => None,
+                _ => Some((x - m * 10, y)),
+            },
+            outputs: (0..count).map(|output| (x + m * 20, y - 10 * count + 10 * output)).collect(),
+        })
+    }
+}
+
+// What is connected to what in one circuit
+struct Netlist<'a> {
+    points: HashMap<Point, usize>,
+    parents: Vec<usize>,
+    tunnels: Vec<&'a Component>,
+    splitters: Vec<Splitter>,
+    decoders: Vec<Decoder<'a>>,
+}
+
+impl<'a> Netlist<'a> {
+    fn new(circ: &'a Circ, circuit: &str) -> Result<Netlist<'a>, CircError> {
+        let mut netlist = Netlist {
+            points: HashMap::new(),
+            parents: Vec::new(),
+            tunnels: Vec::new(),
+            splitters: Vec::new(),
+            decoders: Vec::new(),
+        };
+
+        let wires: Vec<(Point, Point)> = circ.wires
+            .iter()
+            .filter(|(name, _)| name == circuit)
+            .map(|(_, wire)| (wire.from, wire.to))
+            .collect();
+        for (from, to) in &wires {
+            netlist.join(*from, *to);
+        }
+
+        // A wire that ends partway along another joins it
+        for (from, to) in &wires {
+            for end in [from, to] {
+                for (start, stop) in &wires {
+                    if inside(*end, *start, *stop) {
+                        netlist.join(*end, *start);
+                    }
+                }
+            }
+        }
+
+        let mut labels: HashMap<&str, Point> = HashMap::new();
+        for component in circ.components.iter().filter(|component| component.circuit == circuit) {
+            match component.name.as_str() {
+                "Tunnel" => {
+                    if let Some(label) = component.label() {
+                        match labels.get(label) {
+                            Some(other) => netlist.join(*other, component.location),
+                            None => {
+                                labels.insert(label, component.location);
+                                netlist.net(component.location);
+                            },
+                        }
+                        netlist.tunnels.push(component);
+                    }
+                },
+                "Splitter" => netlist.splitters.push(Splitter::new(component)?),
+                "Decoder" => netlist.decoders.push(Decoder::new(component)?),
+                _ => {},
+            }
+        }
+
+        Ok(netlist)
+    }
+
+    fn index(&mut self, point: Point) -> usize {
+        match self.points.get(&point) {
+            Some(index) => *index,
+            None => {
+                let index = self.parents.len();
+                self.parents.push(index);
+                self.points.insert(point, index);
+                index
+            },
+        }
+    }
+
+    fn root(&mut self, mut index: usize) -> usize {
+        while self.parents[index] != index {
+            self.parents[index] = self.parents[self.parents[index]];
+            index = self.parents[index];
+        }
+        index
+    }
+
+    // The net a point is on
+    fn net(&mut self, point: Point) -> usize {
+        let index = self.index(point);
+        self.root(index)
+    }
+
+    fn join(&mut self, first: Point, second: Point) {
+        let first = self.net(first);
+        let second = self.net(second);
+        self.parents[first] = second;
+    }
+
+    // Everything a bit on a net reaches, through splitters and tunnels
+    fn trace(&mut self, start: Point, bit: u32) -> Vec<Sink> {
+        let start = self.net(start);
+        let mut seen = HashSet::from([(start, bit)]);
+        let mut queue = VecDeque::from([(start, bit)]);
+        let mut sinks = Vec::new();
+
+        while let Some((net, bit)) = queue.pop_front() {
+            let mut next = Vec::new();
+            for index in 0..self.splitters.len() {
+                let combined = self.splitters[index].combined;
+                if self.net(combined) == net {
+                    if let Some((end, position)) = self.splitters[index].position(bit as usize) {
+                        next.push((self.splitters[index].ends[end], position));
+                    }
+                }
+                for end in 0..self.splitters[index].ends.len() {
+                    if self.net(self.splitters[index].ends[end]) != net {
+                        continue;
+                    }
+                    let splitter = &self.splitters[index];
+                    let combined_bit = (0..splitter.bits.len())
+                        .find(|combined_bit| splitter.position(*combined_bit) == Some((end, bit)));
+                    if let Some(combined_bit) = combined_bit {
+                        next.push((combined, combined_bit as u32));
+                    }
+                }
+            }
+
+            for index in 0..self.tunnels.len() {
+                let tunnel = self.tunnels[index];
+                if self.net(tunnel.location) == net {
+                    let sink = Sink::Tunnel {
+                        label: tunnel.label().unwrap_or_default().to_string(),
+                        bit,
+                    };
+                    if !sinks.contains(&sink) {
+                        sinks.push(sink);
+                    }
+                }
+            }
+
+            for decoder in 0..self.decoders.len() {
+                if self.net(self.decoders[decoder].select) == net {
+                    sinks.push(Sink::Select { decoder, bit });
+                }
+                if let Some(enable) = self.decoders[decoder].enable {
+                    if bit == 0 && self.net(enable) == net {
+                        sinks.push(Sink::Enable { decoder });
+                    }
+                }
+            }
+
+            for (point, bit) in next {
+                let net = self.net(point);
+                if seen.insert((net, bit)) {
+                    queue.push_back((net, bit));
+                }
+            }
+        }
+
+        sinks
+    }
+
+    // The labels of the tunnels on a net
+    fn labels(&mut self, point: Point) -> Vec<String> {
+        let net = self.net(point);
+        let mut labels = Vec::new();
+        for index in 0..self.tunnels.len() {
+            let tunnel = self.tunnels[index];
+            let label = tunnel.label().unwrap_or_default().to_string();
+            if self.net(tunnel.location) == net && !labels.contains(&label) {
+                labels.push(label);
+            }
+        }
+        labels
+    }
+
+    // How many tunnels carry a label
+    fn uses(&self, label: &str) -> usize {
+        self.tunnels.iter().filter(|tunnel| tunnel.label() == Some(label)).count()
+    }
+}
+
+// Checks the control ROM's wiring against rom_builder. `rom` is a spec
+// for `Circ::find_memory`. Returns each mismatch found, as a sentence.
+pub fn check_wiring(circ: &Circ, rom: &str) -> Result<Vec<String>, CircError> {
+    let rom = circ.find_memory(rom)?;
+    let data_width = rom.width("dataWidth")?;
+    let mut netlist = Netlist::new(circ, &rom.circuit)?;
+
+    // The ROM's data pin moves with its appearance, so the control word
+    // is found as the one splitter as wide as it
+    let splitters: Vec<&Component> = circ.components
+        .iter()
+        .filter(|component| component.circuit == rom.circuit && component.name == "Splitter")
+        .filter(|splitter| number(splitter, "incoming", 2).ok() == Some(data_width))
+        .collect();
+    let control = match splitters.as_slice() {
+        [splitter] => splitter.location,
+        _ => return Err(CircError {
+            message: format!(
+                "Expected one {} bit splitter in {} for the control word of {}, found {}",
+                data_width,
+                rom.circuit,
+                rom.describe(),
+                splitters.len(),
+            ),
+        }),
+    };
+
+    // Where each bit of the control word goes
+    let mut sinks = Vec::new();
+    for bit in 0..data_width {
+        sinks.push(netlist.trace(control, bit));
+    }
+    let mut mismatches = Vec::new();
+
+    let registers: [(Field, Field, Vec<&str>); 2] = [
+        (OUTPUT_SELECT, OUTPUT_ENABLE, Source::all().iter().map(|source| source.tunnel()).collect()),
+        (INPUT_SELECT, INPUT_ENABLE, Target::all().iter().map(|target| target.tunnel()).collect()),
+    ];
+    let mut read = Vec::new();
+
+    for (select, enable, tunnels) in &registers {
+        // The decoder every bit of the select drives, in order
+        let decoder = (0..netlist.decoders.len()).find(|decoder| {
+            (0..select.width).all(|bit| {
+                sinks[(select.offset + bit) as usize].contains(&Sink::Select { decoder: *decoder, bit })
+            })
+        });
+        let decoder = match decoder {
+            Some(decoder) => decoder,
+            None => {
+                mismatches.push(format!("{} ({}) does not drive the select of a decoder", select.name, bits(select)));
+                continue;
+            },
+        };
+
+        let describe = netlist.decoders[decoder].component.describe();
+        if netlist.decoders[decoder].width != select.width {
+            mismatches.push(format!(
+                "{} is {} bits, but {} selects with {}",
+                select.name,
+                select.width,
+                describe,
+                netlist.decoders[decoder].width,
+            ));
+        }
+        if !sinks[enable.offset as usize].contains(&Sink::Enable { decoder }) {
+            mismatches.push(format!("{} ({}) does not drive the enable of {}", enable.name, bits(enable), describe));
+        }
+
+        let outputs = netlist.decoders[decoder].outputs.clone();
+        for (index, output) in outputs.iter().enumerate() {
+            let labels = netlist.labels(*output);
+            match tunnels.get(index) {
+                Some(tunnel) if labels.iter().any(|label| label == tunnel) => read.push(tunnel.to_string()),
+                Some(tunnel) => mismatches.push(format!(
+                    "{} {} is {} in rom_builder, but output {} of {} goes to {}",
+                    select.name,
+                    index,
+                    tunnel,
+                    index,
+                    describe,
+                    list(&labels),
+                )),
+                None if !labels.is_empty() => mismatches.push(format!(
+                    "{} {} is not a register in rom_builder, but output {} of {} goes to {}",
+                    select.name,
+                    index,
+                    index,
+                    describe,
+                    list(&labels),
+                )),
+                None => {},
+            }
+        }
+    }
+
+    // The rest are named after their tunnels
+    for field in FIELDS.iter().filter(|field| registers.iter().all(|(select, enable, _)| *field != select && *field != enable)) {
+        let mut wired = true;
+        for bit in 0..field.width {
+            let reached = &sinks[(field.offset + bit) as usize];
+            let expected = Sink::Tunnel { label: field.name.to_string(), bit };
+            if !reached.contains(&expected) {
+                wired = false;
+                mismatches.push(format!(
+                    "Control bit {} is {} bit {} in rom_builder, but goes to {}",
+                    field.offset + bit,
+                    field.name,
+                    bit,
+                    list(&describe_sinks(&netlist, reached)),
+                ));
+            }
+        }
+
+        let widths: Vec<u32> = netlist.tunnels
+            .iter()
+            .filter(|tunnel| tunnel.label() == Some(field.name))
+            .map(|tunnel| number(tunnel, "width", 1))
+            .collect::<Result<Vec<u32>, CircError>>()?;
+        if wired && widths.iter().any(|width| *width != field.width) {
+            mismatches.push(format!("{} is {} bits in rom_builder, but its tunnels are {:?} bits", field.name, field.width, widths));
+        }
+        if wired {
+            read.push(field.name.to_string());
+        }
+    }
+
+    // A tunnel only the control word uses is read by nothing. A tunnel of
+    // the same width that is also used only once is likely meant to be it.
+    for label in read {
+        if netlist.uses(&label) > 1 {
+            continue;
+        }
+
+        let width = netlist.tunnels
+            .iter()
+            .find(|tunnel| tunnel.label() == Some(label.as_str()))
+            .map(|tunnel| number(tunnel, "width", 1))
+            .transpose()?
+            .unwrap_or(1);
+        let mut alone = Vec::new();
+        for tunnel in &netlist.tunnels {
+            let other = tunnel.label().unwrap_or_default();
+            if other != label && netlist.uses(other) == 1 && number(tunnel, "width", 1)? == width {
+                alone.push(other.to_string());
+            }
+        }
+
+        if alone.is_empty() {
+            mismatches.push(format!("Nothing in {} reads {}", rom.circuit, label));
+        } else {
+            mismatches.push(format!(
+                "Nothing in {} reads {}; tunnels of the same width used only once: {}",
+                rom.circuit,
+                label,
+                alone.join(", "),
+            ));
+        }
+    }
+
+    // Bits rom_builder never sets
+    let allocated = FIELDS.iter().fold(0u64, |mask, field| mask | field.mask() as u64);
+    for (bit, reached) in sinks.iter().enumerate() {
+        if allocated & 1 << bit == 0 && !reached.is_empty() {
+            mismatches.push(format!(
+                "Control bit {} is not used by rom_builder, but goes to {}",
+                bit,
+                list(&describe_sinks(&netlist, reached)),
+            ));
+        }
+    }
+
+    Ok(mismatches)
+}
+
+fn describe_sinks(netlist: &Netlist, sinks: &[Sink]) -> Vec<String> {
+    sinks
+        .iter()
+        .map(|sink| match sink {
+            Sink::Tunnel { label, bit } => format!("{} bit {}", label, bit),
+            Sink::Select { decoder, bit } => {
+                format!("select bit {} of {}", bit, netlist.decoders[*decoder].component.describe())
+            },
+            Sink::Enable { decoder } => format!("the enable of {}", netlist.decoders[*decoder].component.describe()),
+        })
+        .collect()
+}
+
+fn list(items: &[String]) -> String {
+    if items.is_empty() {
+        "nothing".to_string()
+    } else {
+        items.join(", ")
+    }
+}
+
+fn bits(field: &Field) -> String {
+    if field.width == 1 {
+        format!("bit {}", field.offset)
+    } else {
+        format!("bits {}-{}", field.offset, field.offset + field.width - 1)
+    }
+}
+
+// Whether a point lies on a wire, between its ends
+fn inside(point: Point, from: Point, to: Point) -> bool {
+    let between = |value: i64, a: i64, b: i64| a.min(b) < value && value < a.max(b);
+    (point.0 == from.0 && point.0 == to.0 && between(point.1, from.1, to.1))
+        || (point.1 == from.1 && point.1 == to.1 && between(point.0, from.0, to.0))
+}
+
+// A numeric attribute, or Logisim's default when it is not set
+fn number(component: &Component, name: &str, default: u32) -> Result<u32, CircError> {
+    match component.attribute(name) {
+        None => Ok(default),
+        Some(value) => value.parse().map_err(|_| CircError {
+            message: format!("{} has an invalid {}: {}", component.describe(), name, value),
+        }),
+    }
+}
+
+fn unsupported(component: &Component, name: &str, value: &str) -> CircError {
+    CircError {
+        message: format!("{} has {} set to {}, which the wiring check does not handle", component.describe(), name, value),
+    }
+}
\ No newline at end of file
@@ -0,0 +1,53 @@
+/*
+    Runs the example programs through both emulators, as `wh02 diff`
+    does, to check the microcode does what each instruction should.
+*/
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use wh02::assembler::Assembler;
+use wh02_emu::diff::{self, Outcome};
+use wh02_lexer::Lexer;
+use wh02_parser::Parser;
+
+fn example(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../programs").join(name)
+}
+
+fn assemble(name: &str) -> Vec<u8> {
+    let source = fs::read_to_string(example(name)).unwrap();
+    let mut parser = Parser::new(Lexer::new(&source));
+    parser.parse_all();
+    assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+
+    let mut assembler = Assembler::new(parser.expressions);
+    assembler.assemble().unwrap();
+    assembler.assembled
+        .iter()
+        .map(|word| u8::from_str_radix(word, 16).unwrap())
+        .collect()
+}
+
+#[test]
+fn emulators_agree_on_fib() {
+    // fib loops forever, so both run out of cycles in the same state
+    match diff::run(&assemble("fib.asm"), 10_000).unwrap() {
+        Outcome::TimedOut { instructions, .. } => assert!(instructions > 100),
+        outcome => panic!("Expected both emulators to run fib until they time out, got {:?}", outcome),
+    }
+}
+
+#[test]
+fn diff_mode_passes_on_fib() {
+    let output = Command::new(env!("CARGO_BIN_EXE_wh02"))
+        .arg("diff")
+        .arg(example("fib.asm"))
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("Both emulators agree"), "{}", stdout);
+}
\ No newline at end of file
@@ -0,0 +1,66 @@
+/*
+    Runs both emulators on the same program, one instruction at a time,
+    and stops at the first instruction after which their machines differ.
+
+    The instruction emulator says what each opcode should do, so a
+    divergence points at microcode that does something else.
+*/
+
+use crate::emulator::Emulator;
+use crate::emulator_error::EmulatorError;
+use crate::instruction_set::InstructionEmulator;
+use crate::microcode::MicrocodeEmulator;
+
+// The first instruction the emulators disagree on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    // How many instructions ran before it, counting from 0
+    pub instruction: u64,
+    pub address: u8,
+    pub opcode: u8,
+    // Each difference, with the instruction emulator's value first
+    pub differences: Vec<String>,
+}
+
+// How a differential run ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    // Both halted in the same state
+    Halted { instructions: u64, cycles: u64 },
+    // Both ran out of cycles in the same state
+    TimedOut { instructions: u64, cycles: u64 },
+    Diverged(Divergence),
+}
+
+pub fn run(image: &[u8], max_cycles: u64) -> Result<Outcome, EmulatorError> {
+    let mut expected = InstructionEmulator::new(image);
+    let mut actual = MicrocodeEmulator::new(image);
+
+    let mut instructions = 0;
+    loop {
+        let machine = expected.machine();
+        if machine.halted {
+            return Ok(Outcome::Halted { instructions, cycles: machine.cycles });
+        }
+        if machine.cycles >= max_cycles {
+            return Ok(Outcome::TimedOut { instructions, cycles: machine.cycles });
+        }
+
+        let address = machine.prgc;
+        let opcode = machine.ram[address as usize];
+        expected.step_instruction()?;
+        actual.step_instruction()?;
+
+        let differences = expected.machine().differences(actual.machine());
+        if !differences.is_empty() {
+            return Ok(Outcome::Diverged(Divergence {
+                instruction: instructions,
+                address,
+                opcode,
+                differences,
+            }));
+        }
+
+        instructions += 1;
+    }
+}
\ No newline at end of file
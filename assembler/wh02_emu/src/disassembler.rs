@@ -0,0 +1,68 @@
+/*
+    Turns bytes of memory back into assembly, from the opcode table that
+    rom_builder generates. Operand bytes fill in the `xx` and `yy` of each
+    instruction, so `21 05` reads as `MOV #05, @A`.
+
+    Bytes that are not an opcode are shown as `???`, one at a time.
+*/
+
+use rom_builder::opcodes::INSTRUCTIONS;
+
+// One instruction, or one byte that is not an instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub address: usize,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+// The instruction at an address. Operands past the end of memory read 0.
+pub fn disassemble(memory: &[u8], address: usize) -> Line {
+    let opcode = memory.get(address).copied().unwrap_or_default();
+    let (mnemonic, size) = match INSTRUCTIONS.iter().find(|(code, _, _)| *code == opcode) {
+        Some((_, mnemonic, size)) => (*mnemonic, *size),
+        None => return Line {
+            address,
+            bytes: vec![opcode],
+            text: format!("??? {:02X}", opcode),
+        },
+    };
+
+    let bytes: Vec<u8> = (address..address + size)
+        .map(|address| memory.get(address).copied().unwrap_or_default())
+        .collect();
+
+    let mut text = mnemonic.to_string();
+    for (placeholder, operand) in ["xx", "yy"].iter().zip(bytes.iter().skip(1)) {
+        text = text.replacen(placeholder, &format!("{:02X}", operand), 1);
+    }
+
+    Line {
+        address,
+        bytes,
+        text,
+    }
+}
+
+// Every instruction in memory, read from the start. An instruction that
+// would run over `boundary` is cut short, so the listing always has a
+// line starting there, such as the next instruction to run.
+pub fn listing(memory: &[u8], boundary: usize) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < memory.len() {
+        let mut line = disassemble(memory, address);
+        if address < boundary && boundary < address + line.bytes.len() {
+            line = Line {
+                address,
+                bytes: vec![memory[address]],
+                text: format!("??? {:02X}", memory[address]),
+            };
+        }
+
+        address += line.bytes.len();
+        lines.push(line);
+    }
+
+    lines
+}
\ No newline at end of file
@@ -0,0 +1,24 @@
+use crate::emulator_error::EmulatorError;
+use crate::machine::Machine;
+
+// What the two emulators share, so tools can run either
+pub trait Emulator {
+    fn machine(&self) -> &Machine;
+
+    // Runs one whole instruction, fetch cycle included. Does nothing once
+    // the machine has halted.
+    fn step_instruction(&mut self) -> Result<(), EmulatorError>;
+
+    // Puts the machine back as it was when the program was loaded
+    fn reset(&mut self);
+
+    // Runs until the machine halts or has run `max_cycles` clock cycles.
+    // Returns whether it halted.
+    fn run(&mut self, max_cycles: u64) -> Result<bool, EmulatorError> {
+        while !self.machine().halted && self.machine().cycles < max_cycles {
+            self.step_instruction()?;
+        }
+
+        Ok(self.machine().halted)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,12 @@
+use core::fmt;
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct EmulatorError {
+    pub message: String,
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EmulatorError: {}", self.message)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,100 @@
+/*
+    Reads memory images in the format Logisim and the assembler write:
+
+    v3.0 hex words addressed
+    00: 21 00 22 01 07 28 0B 03 2D 04 20 00 00 00 00 00
+
+    Each line starts with the address of its first word, so lines can be
+    left out or come in any order. Addresses that are not given are 0.
+    Logisim writes a run of the same word as `count*word`, such as
+    `12*0` for twelve zeros, with the count in decimal.
+*/
+
+use crate::emulator_error::EmulatorError;
+
+const HEADER: &str = "v3.0 hex words addressed";
+
+pub fn parse_image(text: &str) -> Result<Vec<u8>, EmulatorError> {
+    let mut lines = text.lines().enumerate();
+    match lines.next() {
+        Some((_, header)) if header.trim() == HEADER => {},
+        _ => return Err(EmulatorError {
+            message: format!("Expected an image starting with \"{}\"", HEADER),
+        }),
+    }
+
+    let mut image = Vec::new();
+    for (number, line) in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let error = |message: String| EmulatorError {
+            message: format!("Line {}: {}", number + 1, message),
+        };
+
+        let (address, words) = match line.split_once(':') {
+            Some(parts) => parts,
+            None => return Err(error("Expected an address before the words".to_string())),
+        };
+        let start = match usize::from_str_radix(address.trim(), 16) {
+            Ok(address) => address,
+            Err(_) => return Err(error(format!("Invalid address: {}", address.trim()))),
+        };
+
+        let mut address = start;
+        for word in words.split_whitespace() {
+            let (count, byte) = match word.split_once('*') {
+                Some((count, byte)) => match count.parse::<usize>() {
+                    Ok(count) => (count, byte),
+                    Err(_) => return Err(error(format!("Invalid run length: {}", word))),
+                },
+                None => (1, word),
+            };
+            let value = match u8::from_str_radix(byte, 16) {
+                Ok(value) => value,
+                Err(_) => return Err(error(format!("Invalid byte: {}", word))),
+            };
+
+            if image.len() < address + count {
+                image.resize(address + count, 0);
+            }
+            image[address..address + count].fill(value);
+            address += count;
+        }
+    }
+
+    Ok(image)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_addressed_words() {
+        let image = parse_image("v3.0 hex words addressed\n04: 2D 04\n00: 21 05\n").unwrap();
+        assert_eq!(image, vec![0x21, 0x05, 0x00, 0x00, 0x2D, 0x04]);
+    }
+
+    #[test]
+    fn expands_runs_of_words() {
+        let image = parse_image("v3.0 hex words addressed\n00: 21 3*0 2d 2*ff\n").unwrap();
+        assert_eq!(image, vec![0x21, 0x00, 0x00, 0x00, 0x2D, 0xFF, 0xFF]);
+
+        // Runs longer than a line, as Logisim writes the rest of memory
+        let image = parse_image("v3.0 hex words addressed\n00: 20 255*0\n").unwrap();
+        assert_eq!(image.len(), 256);
+        assert!(image[1..].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn rejects_bad_runs() {
+        let error = parse_image("v3.0 hex words addressed\n00: x*00\n").unwrap_err();
+        assert_eq!(error.message, "Line 2: Invalid run length: x*00");
+
+        let error = parse_image("v3.0 hex words addressed\n00: 2*100\n").unwrap_err();
+        assert_eq!(error.message, "Line 2: Invalid byte: 2*100");
+    }
+}
\ No newline at end of file
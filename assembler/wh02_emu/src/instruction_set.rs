@@ -0,0 +1,200 @@
+/*
+    Runs a program one instruction at a time, from what each opcode is
+    meant to do rather than from its microcode. It is much faster than
+    the microcode emulator, and is what the microcode is checked against.
+
+    Each instruction still takes as many clock cycles as its microcode,
+    so cycle counts match the hardware.
+*/
+
+use rom_builder::control_word::AluOp;
+use rom_builder::opcodes::*;
+use rom_builder::RomBuilder;
+
+use crate::emulator::Emulator;
+use crate::emulator_error::EmulatorError;
+use crate::machine::{Machine, Register};
+
+pub struct InstructionEmulator {
+    pub machine: Machine,
+    // Clock cycles each opcode takes, from the microcode
+    cycles: Vec<u64>,
+    image: Vec<u8>,
+}
+
+impl InstructionEmulator {
+    pub fn new(image: &[u8]) -> InstructionEmulator {
+        let mut rom = RomBuilder::default();
+        rom.build_rom();
+
+        InstructionEmulator {
+            machine: Machine::new(image),
+            cycles: (0..0x100).map(|opcode| rom.cycles(opcode) as u64).collect(),
+            image: image.to_vec(),
+        }
+    }
+
+    // Reads the byte at PRGC and steps past it
+    fn fetch(&mut self) -> u8 {
+        let byte = self.machine.ram[self.machine.prgc as usize];
+        self.machine.prgc = self.machine.prgc.wrapping_add(1);
+        byte
+    }
+
+    // Loads a register. ACC adds A and B, so it is latched again whenever
+    // either of them changes.
+    fn load(&mut self, register: Register, value: u8) {
+        self.machine.set(register, value);
+        if register == Register::A || register == Register::B {
+            self.machine.acc = self.machine.alu(AluOp::Add, 0).0;
+        }
+    }
+
+    fn mov(&mut self, from: Register, to: Register) {
+        let value = self.machine.get(from);
+        self.load(to, value);
+    }
+
+    fn store(&mut self, from: Register) {
+        let address = self.fetch();
+        self.machine.ram[address as usize] = self.machine.get(from);
+    }
+
+    fn load_ram(&mut self, to: Register) {
+        let address = self.fetch();
+        let value = self.machine.ram[address as usize];
+        self.load(to, value);
+    }
+
+    fn load_immediate(&mut self, to: Register) {
+        let value = self.fetch();
+        self.load(to, value);
+    }
+
+    fn push(&mut self, value: u8) {
+        self.machine.stack[self.machine.sp as usize] = value;
+        self.machine.sp = self.machine.sp.wrapping_add(1);
+    }
+
+    fn pop(&mut self) -> u8 {
+        self.machine.sp = self.machine.sp.wrapping_sub(1);
+        self.machine.stack[self.machine.sp as usize]
+    }
+
+    fn pop_into(&mut self, to: Register) {
+        let value = self.pop();
+        self.load(to, value);
+    }
+
+    fn latch(&mut self, op: AluOp, places: u32) {
+        self.machine.acc = self.machine.alu(op, places).0;
+    }
+}
+
+impl Emulator for InstructionEmulator {
+    fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    fn step_instruction(&mut self) -> Result<(), EmulatorError> {
+        if self.machine.halted {
+            return Ok(());
+        }
+
+        let address = self.machine.prgc;
+        let opcode = self.fetch();
+        self.machine.inst = opcode;
+        self.machine.cycles += self.cycles[opcode as usize];
+
+        match opcode {
+            NOP => {},
+            MOV_A_B => self.mov(Register::A, Register::B),
+            MOV_A_C => self.mov(Register::A, Register::C),
+            MOV_A_O1 => self.mov(Register::A, Register::O1),
+            MOV_A_O2 => self.mov(Register::A, Register::O2),
+            MOV_A_RAM => self.store(Register::A),
+            MOV_B_A => self.mov(Register::B, Register::A),
+            MOV_B_C => self.mov(Register::B, Register::C),
+            MOV_B_O1 => self.mov(Register::B, Register::O1),
+            MOV_B_O2 => self.mov(Register::B, Register::O2),
+            MOV_B_RAM => self.store(Register::B),
+            MOV_C_A => self.mov(Register::C, Register::A),
+            MOV_C_B => self.mov(Register::C, Register::B),
+            MOV_C_O1 => self.mov(Register::C, Register::O1),
+            MOV_C_O2 => self.mov(Register::C, Register::O2),
+            MOV_C_RAM => self.store(Register::C),
+            MOV_O1_A => self.mov(Register::O1, Register::A),
+            MOV_O1_B => self.mov(Register::O1, Register::B),
+            MOV_O1_C => self.mov(Register::O1, Register::C),
+            MOV_O1_O2 => self.mov(Register::O1, Register::O2),
+            MOV_O1_RAM => self.store(Register::O1),
+            MOV_O2_A => self.mov(Register::O2, Register::A),
+            MOV_O2_B => self.mov(Register::O2, Register::B),
+            MOV_O2_C => self.mov(Register::O2, Register::C),
+            MOV_O2_O1 => self.mov(Register::O2, Register::O1),
+            MOV_O2_RAM => self.store(Register::O2),
+            MOV_RAM_A => self.load_ram(Register::A),
+            MOV_RAM_B => self.load_ram(Register::B),
+            MOV_RAM_C => self.load_ram(Register::C),
+            MOV_RAM_O1 => self.load_ram(Register::O1),
+            MOV_RAM_O2 => self.load_ram(Register::O2),
+            MOV_RAM_RAM => {
+                let from = self.fetch();
+                let to = self.fetch();
+                self.machine.ram[to as usize] = self.machine.ram[from as usize];
+            },
+            HLT => self.machine.halted = true,
+            MOV_BUS_A => self.load_immediate(Register::A),
+            MOV_BUS_B => self.load_immediate(Register::B),
+            MOV_BUS_C => self.load_immediate(Register::C),
+            MOV_BUS_O1 => self.load_immediate(Register::O1),
+            MOV_BUS_O2 => self.load_immediate(Register::O2),
+            MOV_BUS_RAM => {
+                let value = self.fetch();
+                let to = self.fetch();
+                self.machine.ram[to as usize] = value;
+            },
+            MOV_ACC_A => self.mov(Register::ACC, Register::A),
+            MOV_ACC_B => self.mov(Register::ACC, Register::B),
+            MOV_ACC_C => self.mov(Register::ACC, Register::C),
+            MOV_ACC_O1 => self.mov(Register::ACC, Register::O1),
+            MOV_ACC_O2 => self.mov(Register::ACC, Register::O2),
+            MOV_ACC_RAM => self.store(Register::ACC),
+            JMP => self.machine.prgc = self.fetch(),
+            CALL => {
+                let target = self.fetch();
+                self.push(self.machine.prgc);
+                self.machine.prgc = target;
+            },
+            RET => self.machine.prgc = self.pop(),
+            PUSH_A => self.push(self.machine.a),
+            PUSH_B => self.push(self.machine.b),
+            PUSH_C => self.push(self.machine.c),
+            PUSH_O1 => self.push(self.machine.o1),
+            PUSH_O2 => self.push(self.machine.o2),
+            PUSH_ACC => self.push(self.machine.acc),
+            POP_A => self.pop_into(Register::A),
+            POP_B => self.pop_into(Register::B),
+            POP_C => self.pop_into(Register::C),
+            POP_O1 => self.pop_into(Register::O1),
+            POP_O2 => self.pop_into(Register::O2),
+            JMP_A => self.machine.prgc = self.machine.a,
+            JMP_B => self.machine.prgc = self.machine.b,
+            JMP_C => self.machine.prgc = self.machine.c,
+            JMP_ACC => self.machine.prgc = self.machine.acc,
+            ADD => self.latch(AluOp::Add, 0),
+            SUB => self.latch(AluOp::Sub, 0),
+            SHL_1..=SHL_7 => self.latch(AluOp::Shl, (opcode - SHL_1 + 1) as u32),
+            SHR_1..=SHR_7 => self.latch(AluOp::Shr, (opcode - SHR_1 + 1) as u32),
+            _ => return Err(EmulatorError {
+                message: format!("Unknown opcode {:02X} at ${:02X}", opcode, address),
+            }),
+        }
+
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.machine = Machine::new(&self.image);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,10 @@
+pub mod diff;
+pub mod disassembler;
+pub mod emulator;
+pub mod emulator_error;
+pub mod image;
+pub mod instruction_set;
+pub mod machine;
+pub mod microcode;
+pub mod trace;
+pub mod tui;
\ No newline at end of file
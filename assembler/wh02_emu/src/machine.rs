@@ -0,0 +1,216 @@
+/*
+    The state of a WH-02: its registers, RAM and stack.
+
+    Both emulators run on a `Machine`, so their states can be compared.
+    MAR, INST and the step counter only matter to the microcode, but are
+    kept here so a front panel can show them.
+*/
+
+use rom_builder::control_word::AluOp;
+
+// Bytes of RAM, and of stack memory
+pub const MEMORY_SIZE: usize = 256;
+
+// The registers an instruction can name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    B,
+    C,
+    ACC,
+    O1,
+    O2,
+}
+
+impl Register {
+    pub fn all() -> Vec<Register> {
+        vec![Register::A, Register::B, Register::C, Register::ACC, Register::O1, Register::O2]
+    }
+}
+
+// ALU flags, latched by writing FLG
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Flags {
+    pub eq: bool,
+    pub gt: bool,
+    pub lt: bool,
+    pub carry: bool,
+}
+
+// A value written to an output register, which the board shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Output {
+    // The clock cycle of the write, counting from one
+    pub cycle: u64,
+    pub register: Register,
+    pub value: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Machine {
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub acc: u8,
+    pub o1: u8,
+    pub o2: u8,
+    pub prgc: u8,
+    pub mar: u8,
+    pub inst: u8,
+    pub flags: Flags,
+    pub ram: Vec<u8>,
+    pub stack: Vec<u8>,
+    // The stack slot that is written next
+    pub sp: u8,
+    // The microcode step that runs next
+    pub step: u16,
+    pub halted: bool,
+    // Clock cycles run since reset
+    pub cycles: u64,
+    pub outputs: Vec<Output>,
+}
+
+impl Machine {
+    // A machine just after reset, with a program loaded at address 0
+    pub fn new(image: &[u8]) -> Machine {
+        let mut ram = vec![0; MEMORY_SIZE];
+        for (byte, value) in ram.iter_mut().zip(image) {
+            *byte = *value;
+        }
+
+        Machine {
+            a: 0,
+            b: 0,
+            c: 0,
+            acc: 0,
+            o1: 0,
+            o2: 0,
+            prgc: 0,
+            mar: 0,
+            inst: 0,
+            flags: Flags::default(),
+            ram,
+            stack: vec![0; MEMORY_SIZE],
+            sp: 0,
+            step: 0,
+            halted: false,
+            cycles: 0,
+            outputs: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, register: Register) -> u8 {
+        match register {
+            Register::A => self.a,
+            Register::B => self.b,
+            Register::C => self.c,
+            Register::ACC => self.acc,
+            Register::O1 => self.o1,
+            Register::O2 => self.o2,
+        }
+    }
+
+    // Writes a register, recording writes to the outputs. ACC only
+    // changes by latching the ALU, which is up to the caller.
+    pub fn set(&mut self, register: Register, value: u8) {
+        match register {
+            Register::A => self.a = value,
+            Register::B => self.b = value,
+            Register::C => self.c = value,
+            Register::ACC => self.acc = value,
+            Register::O1 => self.o1 = value,
+            Register::O2 => self.o2 = value,
+        }
+
+        if register == Register::O1 || register == Register::O2 {
+            self.outputs.push(Output {
+                cycle: self.cycles,
+                register,
+                value,
+            });
+        }
+    }
+
+    // What the ALU gives for A and B, with its carry
+    pub fn alu(&self, op: AluOp, places: u32) -> (u8, bool) {
+        match op {
+            AluOp::Add => self.a.overflowing_add(self.b),
+            AluOp::Sub => self.a.overflowing_sub(self.b),
+            AluOp::Shl => (((self.a as u32) << places) as u8, (self.a as u32) << places > 0xFF),
+            AluOp::Shr => (self.a >> places, false),
+        }
+    }
+
+    // The flags FLG latches from A and B
+    pub fn compare(&self, op: AluOp, places: u32) -> Flags {
+        Flags {
+            eq: self.a == self.b,
+            gt: self.a > self.b,
+            lt: self.a < self.b,
+            carry: self.alu(op, places).1,
+        }
+    }
+
+    // Ways this machine differs from another, as one line each. Stack
+    // slots at or above the stack pointer hold nothing, so only the
+    // slots below it are compared.
+    pub fn differences(&self, other: &Machine) -> Vec<String> {
+        let mut differences = Vec::new();
+
+        for register in Register::all() {
+            if self.get(register) != other.get(register) {
+                differences.push(format!("{:?}: {:02X} and {:02X}", register, self.get(register), other.get(register)));
+            }
+        }
+
+        if self.prgc != other.prgc {
+            differences.push(format!("PRGC: {:02X} and {:02X}", self.prgc, other.prgc));
+        }
+
+        if self.sp != other.sp {
+            differences.push(format!("SP: {:02X} and {:02X}", self.sp, other.sp));
+        }
+
+        if self.flags != other.flags {
+            differences.push(format!("Flags: {:?} and {:?}", self.flags, other.flags));
+        }
+
+        if self.halted != other.halted {
+            differences.push(format!("Halted: {} and {}", self.halted, other.halted));
+        }
+
+        for (address, (mine, theirs)) in self.ram.iter().zip(&other.ram).enumerate() {
+            if mine != theirs {
+                differences.push(format!("RAM ${:02X}: {:02X} and {:02X}", address, mine, theirs));
+            }
+        }
+
+        let depth = self.sp.min(other.sp) as usize;
+        for (slot, (mine, theirs)) in self.stack.iter().zip(&other.stack).enumerate().take(depth) {
+            if mine != theirs {
+                differences.push(format!("Stack slot {:02X}: {:02X} and {:02X}", slot, mine, theirs));
+            }
+        }
+
+        // Outputs are compared by value; only the microcode knows which
+        // step of an instruction writes them
+        let mine: Vec<(Register, u8)> = self.outputs.iter().map(|output| (output.register, output.value)).collect();
+        let theirs: Vec<(Register, u8)> = other.outputs.iter().map(|output| (output.register, output.value)).collect();
+        if mine != theirs {
+            differences.push(format!("Outputs: {} and {}", outputs_text(&mine), outputs_text(&theirs)));
+        }
+
+        differences
+    }
+}
+
+fn outputs_text(outputs: &[(Register, u8)]) -> String {
+    if outputs.is_empty() {
+        return "none".to_string();
+    }
+
+    outputs.iter()
+        .map(|(register, value)| format!("{:?}={:02X}", register, value))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
\ No newline at end of file
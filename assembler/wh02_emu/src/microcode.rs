@@ -0,0 +1,185 @@
+/*
+    Runs a program the way the WH-02 does: one clock cycle at a time,
+    driven by the control words in the ROM that rom_builder builds.
+
+    On each cycle the selected source drives the bus, and on the clock
+    edge the selected target loads it. Everything else the word asks for
+    (counting PRGC up, moving the stack pointer, halting) happens on the
+    same edge, from the state before it. A bus nothing drives reads 0.
+
+    Set `trace` to record every cycle.
+*/
+
+use rom_builder::control_word::{ControlWord, Source, Target};
+use rom_builder::{RomBuilder, STEPS};
+
+use crate::emulator::Emulator;
+use crate::emulator_error::EmulatorError;
+use crate::machine::{Machine, Register};
+use crate::trace::{signals, Trace, TraceStep};
+
+pub struct MicrocodeEmulator {
+    pub machine: Machine,
+    pub rom: RomBuilder,
+    pub trace: Option<Trace>,
+    image: Vec<u8>,
+}
+
+impl MicrocodeEmulator {
+    pub fn new(image: &[u8]) -> MicrocodeEmulator {
+        let mut rom = RomBuilder::default();
+        rom.build_rom();
+
+        MicrocodeEmulator {
+            machine: Machine::new(image),
+            rom,
+            trace: None,
+            image: image.to_vec(),
+        }
+    }
+
+    // Runs one clock cycle
+    pub fn step(&mut self) -> Result<(), EmulatorError> {
+        if self.machine.halted {
+            return Ok(());
+        }
+
+        let step = self.machine.step;
+        let opcode = self.machine.inst;
+        let word = match ControlWord::from_bits(self.rom.control_word(step, opcode as u16)) {
+            Ok(word) => word,
+            Err(error) => return Err(EmulatorError {
+                message: format!("Step {} of opcode {:02X}: {}", step, opcode, error),
+            }),
+        };
+
+        let driven = word.source().map(|source| self.read(source));
+        let bus = driven.unwrap_or_default();
+
+        self.machine.cycles += 1;
+
+        // ACC and FLG latch from A and B as they were before the edge
+        let before = self.machine.clone();
+        match word.target() {
+            Some(Target::A) => self.machine.set(Register::A, bus),
+            Some(Target::B) => self.machine.set(Register::B, bus),
+            Some(Target::C) => self.machine.set(Register::C, bus),
+            Some(Target::O1) => self.machine.set(Register::O1, bus),
+            Some(Target::O2) => self.machine.set(Register::O2, bus),
+            Some(Target::FLG) => self.machine.flags = before.compare(word.alu_op(), word.shift_places()),
+            Some(Target::PRGC) => self.machine.prgc = bus,
+            Some(Target::MAR) => self.machine.mar = bus,
+            Some(Target::INST) => self.machine.inst = bus,
+            Some(Target::RAM) => self.machine.ram[before.mar as usize] = bus,
+            Some(Target::STK) => self.machine.stack[before.sp as usize] = bus,
+            Some(Target::ACC) => self.machine.acc = before.alu(word.alu_op(), word.shift_places()).0,
+            None => {},
+        }
+
+        // Loading PRGC wins over counting it up
+        if word.enables_program_counter() && word.target() != Some(Target::PRGC) {
+            self.machine.prgc = before.prgc.wrapping_add(1);
+        }
+        if word.stack_incs() {
+            self.machine.sp = before.sp.wrapping_add(1);
+        }
+        if word.stack_decs() {
+            self.machine.sp = before.sp.wrapping_sub(1);
+        }
+        if word.halts() {
+            self.machine.halted = true;
+        }
+
+        self.machine.step = if word.resets_step_counter() || step + 1 == STEPS {
+            0
+        } else {
+            step + 1
+        };
+
+        if let Some(trace) = &mut self.trace {
+            trace.steps.push(TraceStep {
+                cycle: self.machine.cycles,
+                step,
+                opcode,
+                word,
+                bus: driven,
+                writes: writes(&word, &before, &self.machine),
+                after: signals(&self.machine),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn read(&self, source: Source) -> u8 {
+        match source {
+            Source::A => self.machine.a,
+            Source::B => self.machine.b,
+            Source::C => self.machine.c,
+            Source::ACC => self.machine.acc,
+            Source::O1 => self.machine.o1,
+            Source::O2 => self.machine.o2,
+            Source::PRGC => self.machine.prgc,
+            Source::MAR => self.machine.mar,
+            Source::INST => self.machine.inst,
+            Source::RAM => self.machine.ram[self.machine.mar as usize],
+            Source::STK => self.machine.stack[self.machine.sp as usize],
+        }
+    }
+}
+
+impl Emulator for MicrocodeEmulator {
+    fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    // Steps until the step counter comes back round to the fetch cycle
+    fn step_instruction(&mut self) -> Result<(), EmulatorError> {
+        self.step()?;
+        while self.machine.step != 0 && !self.machine.halted {
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.machine = Machine::new(&self.image);
+        if self.trace.is_some() {
+            self.trace = Some(Trace::new(&self.machine));
+        }
+    }
+}
+
+// What a cycle wrote, as `NAME=value`: the target of the bus, then
+// anything the control word counted up or down
+fn writes(word: &ControlWord, before: &Machine, after: &Machine) -> Vec<String> {
+    let mut writes = Vec::new();
+    match word.target() {
+        Some(Target::A) => writes.push(format!("A={:02X}", after.a)),
+        Some(Target::B) => writes.push(format!("B={:02X}", after.b)),
+        Some(Target::C) => writes.push(format!("C={:02X}", after.c)),
+        Some(Target::O1) => writes.push(format!("O1={:02X}", after.o1)),
+        Some(Target::O2) => writes.push(format!("O2={:02X}", after.o2)),
+        Some(Target::FLG) => writes.push(format!("FLG={:?}", after.flags)),
+        Some(Target::PRGC) => writes.push(format!("PRGC={:02X}", after.prgc)),
+        Some(Target::MAR) => writes.push(format!("MAR={:02X}", after.mar)),
+        Some(Target::INST) => writes.push(format!("INST={:02X}", after.inst)),
+        Some(Target::RAM) => writes.push(format!("RAM[${:02X}]={:02X}", before.mar, after.ram[before.mar as usize])),
+        Some(Target::STK) => writes.push(format!("STK[{:02X}]={:02X}", before.sp, after.stack[before.sp as usize])),
+        Some(Target::ACC) => writes.push(format!("ACC={:02X}", after.acc)),
+        None => {},
+    }
+
+    if word.enables_program_counter() && word.target() != Some(Target::PRGC) {
+        writes.push(format!("PRGC={:02X}", after.prgc));
+    }
+    if word.stack_incs() || word.stack_decs() {
+        writes.push(format!("SP={:02X}", after.sp));
+    }
+    if word.halts() {
+        writes.push("HALTED".to_string());
+    }
+
+    writes
+}
\ No newline at end of file
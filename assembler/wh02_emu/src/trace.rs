@@ -0,0 +1,152 @@
+/*
+    A record of every clock cycle the microcode emulator runs, for
+    working out what went wrong in a program.
+
+    Each cycle keeps the microcode step and opcode, the control word and
+    what it decodes to, the value on the bus and what was written. The
+    trace can be written as text, one cycle per line, or as a VCD
+    waveform to view in GTKWave.
+
+    In the waveform each cycle is two time units: the clock is low for
+    the first, while the bus is driven, and rises at the second, when the
+    registers load.
+*/
+
+use rom_builder::control_word::ControlWord;
+
+use crate::machine::Machine;
+
+// The registers the waveform shows, with their widths in bits
+pub const SIGNALS: [(&str, u32); 11] = [
+    ("PRGC", 8),
+    ("MAR", 8),
+    ("INST", 8),
+    ("STEP", 3),
+    ("A", 8),
+    ("B", 8),
+    ("C", 8),
+    ("ACC", 8),
+    ("O1", 8),
+    ("O2", 8),
+    ("SP", 8),
+];
+
+// The values of `SIGNALS` in a machine
+pub fn signals(machine: &Machine) -> [u8; 11] {
+    [
+        machine.prgc,
+        machine.mar,
+        machine.inst,
+        machine.step as u8,
+        machine.a,
+        machine.b,
+        machine.c,
+        machine.acc,
+        machine.o1,
+        machine.o2,
+        machine.sp,
+    ]
+}
+
+// One clock cycle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    // Counting from one
+    pub cycle: u64,
+    pub step: u16,
+    pub opcode: u8,
+    pub word: ControlWord,
+    // The value on the bus, if anything drove it
+    pub bus: Option<u8>,
+    // What changed on the clock edge, such as `B=05` or `RAM[$80]=05`
+    pub writes: Vec<String>,
+    // `SIGNALS` after the clock edge
+    pub after: [u8; 11],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    // `SIGNALS` before the first cycle
+    pub initial: [u8; 11],
+    pub steps: Vec<TraceStep>,
+}
+
+impl Trace {
+    pub fn new(machine: &Machine) -> Trace {
+        Trace {
+            initial: signals(machine),
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut output = String::new();
+        output += "# cycle step opcode word bus writes ; microcode\n";
+        for step in &self.steps {
+            output += &format!(
+                "{:>6} {} {:02X} {:05X} {} {} ; {}\n",
+                step.cycle,
+                step.step,
+                step.opcode,
+                step.word.bits(),
+                step.bus.map(|bus| format!("{:02X}", bus)).unwrap_or("--".to_string()),
+                if step.writes.is_empty() { "-".to_string() } else { step.writes.join(",") },
+                step.word,
+            );
+        }
+
+        output
+    }
+
+    pub fn to_vcd(&self) -> String {
+        // Identifiers: the clock, the bus, then each of `SIGNALS`
+        let id = |index: usize| ((b'!' + index as u8) as char).to_string();
+
+        let mut output = String::new();
+        output += "$version wh02_emu $end\n";
+        output += "$timescale 1us $end\n";
+        output += "$scope module wh02 $end\n";
+        output += &format!("$var wire 1 {} CLK $end\n", id(0));
+        output += &format!("$var wire 8 {} BUS $end\n", id(1));
+        for (index, (name, width)) in SIGNALS.iter().enumerate() {
+            output += &format!("$var reg {} {} {} $end\n", width, id(index + 2), name);
+        }
+        output += "$upscope $end\n";
+        output += "$enddefinitions $end\n";
+
+        output += "#0\n$dumpvars\n";
+        output += &format!("0{}\n", id(0));
+        output += &format!("bz {}\n", id(1));
+        for (index, value) in self.initial.iter().enumerate() {
+            output += &format!("b{:b} {}\n", value, id(index + 2));
+        }
+        output += "$end\n";
+
+        let mut previous = self.initial;
+        let mut previous_bus = None;
+        for (index, step) in self.steps.iter().enumerate() {
+            let time = index as u64 * 2;
+            if index > 0 {
+                output += &format!("#{}\n0{}\n", time, id(0));
+            }
+            if step.bus != previous_bus {
+                match step.bus {
+                    Some(bus) => output += &format!("b{:b} {}\n", bus, id(1)),
+                    None => output += &format!("bz {}\n", id(1)),
+                }
+                previous_bus = step.bus;
+            }
+
+            output += &format!("#{}\n1{}\n", time + 1, id(0));
+            for (signal, value) in step.after.iter().enumerate() {
+                if *value != previous[signal] {
+                    output += &format!("b{:b} {}\n", value, id(signal + 2));
+                }
+            }
+            previous = step.after;
+        }
+        output += &format!("#{}\n0{}\n", self.steps.len() * 2, id(0));
+
+        output
+    }
+}
\ No newline at end of file
@@ -0,0 +1,356 @@
+/*
+    A front panel for the microcode emulator in the terminal, laid out
+    like the one in WH02.circ: the output registers on seven segment hex
+    displays, the other registers and flags, the memory and a listing of
+    the program with the running instruction highlighted.
+
+    It only needs std. The terminal is put into non-canonical mode with
+    `stty`, keys are read on their own thread, and the panel is redrawn
+    with ANSI escape codes. It needs a terminal of at least 80 by 24.
+
+    Keys:
+    space  run or pause
+    s      step one instruction
+    c      step one clock cycle
+    r      reset
+    + -    run faster or slower
+    q      quit
+*/
+
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::disassembler::listing;
+use crate::emulator::Emulator;
+use crate::emulator_error::EmulatorError;
+use crate::machine::Machine;
+use crate::microcode::MicrocodeEmulator;
+
+// Clock speeds to pick from, in cycles per second
+const SPEEDS: [u64; 10] = [1, 2, 5, 10, 20, 50, 100, 1_000, 10_000, 100_000];
+const DEFAULT_SPEED: usize = 3;
+
+// How long to wait for a key between redraws
+const FRAME: Duration = Duration::from_millis(33);
+
+// Lines of memory and listing shown
+const ROWS: usize = 16;
+
+// Segments lit for each hex digit, as bits a to g
+const DIGITS: [u8; 16] = [
+    0x3F, 0x06, 0x5B, 0x4F, 0x66, 0x6D, 0x7D, 0x07,
+    0x7F, 0x6F, 0x77, 0x7C, 0x39, 0x5E, 0x79, 0x71,
+];
+
+const REVERSE: &str = "\x1b[7m";
+const NORMAL: &str = "\x1b[0m";
+
+pub struct FrontPanel {
+    pub emulator: MicrocodeEmulator,
+    // Where the running instruction starts, or the next one if the
+    // machine is between instructions
+    pub instruction: u8,
+    pub running: bool,
+    // Index into `SPEEDS`
+    pub speed: usize,
+    // The last problem, shown until the next key
+    pub message: String,
+}
+
+impl FrontPanel {
+    pub fn new(image: &[u8]) -> FrontPanel {
+        FrontPanel {
+            emulator: MicrocodeEmulator::new(image),
+            instruction: 0,
+            running: false,
+            speed: DEFAULT_SPEED,
+            message: String::new(),
+        }
+    }
+
+    // Runs one clock cycle, pausing on a halt or a bad control word
+    fn clock(&mut self) {
+        if self.emulator.machine.halted {
+            self.running = false;
+            return;
+        }
+
+        if let Err(error) = self.emulator.step() {
+            self.message = error.to_string();
+            self.running = false;
+        }
+
+        if self.emulator.machine.step == 0 {
+            self.instruction = self.emulator.machine.prgc;
+        }
+    }
+
+    fn step_instruction(&mut self) {
+        self.clock();
+        while self.emulator.machine.step != 0 && !self.emulator.machine.halted && self.message.is_empty() {
+            self.clock();
+        }
+    }
+
+    // Handles a key, returning false to quit
+    fn key(&mut self, key: u8) -> bool {
+        self.message.clear();
+        match key {
+            b' ' => self.running = !self.running && !self.emulator.machine.halted,
+            b's' => {
+                self.running = false;
+                self.step_instruction();
+            },
+            b'c' => {
+                self.running = false;
+                self.clock();
+            },
+            b'r' => {
+                self.emulator.reset();
+                self.instruction = 0;
+                self.running = false;
+            },
+            b'+' | b'=' => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+            b'-' => self.speed = self.speed.saturating_sub(1),
+            // Ctrl-C comes through as a key, since signals are off
+            b'q' | 3 => return false,
+            _ => {},
+        }
+
+        true
+    }
+
+    fn event_loop(&mut self, keys: &Receiver<u8>) -> io::Result<()> {
+        let mut last = Instant::now();
+        let mut owed = 0.0;
+        loop {
+            self.draw()?;
+
+            match keys.recv_timeout(FRAME) {
+                Ok(key) => {
+                    if !self.key(key) {
+                        return Ok(());
+                    }
+                },
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+
+            // Runs the cycles due since the last frame, at the set speed
+            let now = Instant::now();
+            if self.running {
+                owed += now.duration_since(last).as_secs_f64() * SPEEDS[self.speed] as f64;
+                let cycles = (owed as u64).min(SPEEDS[SPEEDS.len() - 1]);
+                owed -= cycles as f64;
+                for _ in 0..cycles {
+                    if !self.running {
+                        break;
+                    }
+                    self.clock();
+                }
+            } else {
+                owed = 0.0;
+            }
+            last = now;
+        }
+    }
+
+    fn draw(&self) -> io::Result<()> {
+        let mut output = String::from("\x1b[H");
+        for line in self.render() {
+            output += &line;
+            output += "\x1b[K\n";
+        }
+
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(output.trim_end_matches('\n').as_bytes())?;
+        stdout.flush()
+    }
+
+    // The panel, one string per line of the terminal
+    pub fn render(&self) -> Vec<String> {
+        let machine = &self.emulator.machine;
+        let mut lines = Vec::new();
+
+        let status = if machine.halted {
+            "HALTED".to_string()
+        } else if self.running {
+            format!("RUNNING {} Hz", SPEEDS[self.speed])
+        } else {
+            format!("PAUSED ({} Hz)", SPEEDS[self.speed])
+        };
+        lines.push(format!("WH-02  {:<22} cycle {:<10} step {}", status, machine.cycles, machine.step));
+
+        // Output displays on the left, registers on the right
+        let registers = [
+            format!("A    {:02X}   B    {:02X}   C    {:02X}   ACC  {:02X}", machine.a, machine.b, machine.c, machine.acc),
+            format!(
+                "PRGC {:02X}   MAR  {:02X}   INST {:02X}   STK  {:02X}",
+                machine.prgc,
+                machine.mar,
+                machine.inst,
+                machine.stack[machine.sp as usize],
+            ),
+            format!("SP   {:02X}", machine.sp),
+            format!(
+                "EQ {}  GT {}  LT {}  C {}",
+                led(machine.flags.eq),
+                led(machine.flags.gt),
+                led(machine.flags.lt),
+                led(machine.flags.carry),
+            ),
+        ];
+        let o1 = segments(machine.o1);
+        let o2 = segments(machine.o2);
+        lines.push(format!("{:<13}{:<14}{}", " O1", "O2", registers[0]));
+        for row in 0..3 {
+            lines.push(format!(" {}    {}      {}", o1[row], o2[row], registers[row + 1]));
+        }
+        lines.push(String::new());
+
+        // Memory on the left, the listing on the right
+        let mut header = "   ".to_string();
+        for column in 0..16 {
+            header += &format!(" {:X} ", column);
+        }
+        lines.push(format!("{}   Program", header));
+
+        let memory = self.memory_rows(machine);
+        let program = self.program_rows(machine);
+        for (row, memory) in memory.iter().enumerate() {
+            lines.push(format!("{}   {}", memory, program.get(row).cloned().unwrap_or_default()));
+        }
+
+        lines.push(String::new());
+        if self.message.is_empty() {
+            lines.push("space run/pause  s step  c cycle  r reset  +/- speed  q quit".to_string());
+        } else {
+            lines.push(format!("ERROR: {}", self.message));
+        }
+
+        lines
+    }
+
+    // The bytes of the running instruction are highlighted
+    fn memory_rows(&self, machine: &Machine) -> Vec<String> {
+        let current = listing(&machine.ram, self.instruction as usize)
+            .into_iter()
+            .find(|line| line.address == self.instruction as usize)
+            .map(|line| line.address..line.address + line.bytes.len())
+            .unwrap_or_default();
+
+        (0..ROWS)
+            .map(|row| {
+                let mut text = format!("{:X}0 ", row);
+                for column in 0..16 {
+                    let address = row * 16 + column;
+                    if current.contains(&address) {
+                        text += &format!("{}{:02X}{} ", REVERSE, machine.ram[address], NORMAL);
+                    } else {
+                        text += &format!("{:02X} ", machine.ram[address]);
+                    }
+                }
+                text
+            })
+            .collect()
+    }
+
+    // A window of the listing, with a few lines before the running
+    // instruction
+    fn program_rows(&self, machine: &Machine) -> Vec<String> {
+        let lines = listing(&machine.ram, self.instruction as usize);
+        let current = lines.iter().position(|line| line.address == self.instruction as usize).unwrap_or_default();
+        let first = current.saturating_sub(4).min(lines.len().saturating_sub(ROWS));
+
+        lines[first..]
+            .iter()
+            .take(ROWS)
+            .map(|line| {
+                if line.address == self.instruction as usize {
+                    format!("{}> {:02X}  {:<16}{}", REVERSE, line.address, line.text, NORMAL)
+                } else {
+                    format!("  {:02X}  {}", line.address, line.text)
+                }
+            })
+            .collect()
+    }
+}
+
+fn led(on: bool) -> &'static str {
+    if on { "●" } else { "○" }
+}
+
+// A byte on two seven segment digits, as three rows of text
+fn segments(value: u8) -> [String; 3] {
+    let mut rows = [String::new(), String::new(), String::new()];
+    for digit in [value >> 4, value & 0xF] {
+        let lit = DIGITS[digit as usize];
+        let segment = |bit: u8, text: char| if lit & 1 << bit != 0 { text } else { ' ' };
+
+        rows[0] += &format!(" {} ", segment(0, '_'));
+        rows[1] += &format!("{}{}{}", segment(5, '|'), segment(6, '_'), segment(1, '|'));
+        rows[2] += &format!("{}{}{}", segment(4, '|'), segment(3, '_'), segment(2, '|'));
+        for row in rows.iter_mut() {
+            row.push(' ');
+        }
+    }
+
+    rows
+}
+
+// Runs `stty` on the terminal, returning what it prints
+fn stty(args: &[&str]) -> Result<String, EmulatorError> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .map_err(|error| EmulatorError {
+            message: format!("Failed to run stty: {}", error),
+        })?;
+
+    if !output.status.success() {
+        return Err(EmulatorError {
+            message: "The front panel needs a terminal".to_string(),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Shows the front panel until the user quits, putting the terminal back
+// as it was afterwards
+pub fn run(image: &[u8]) -> Result<(), EmulatorError> {
+    let saved = stty(&["-g"])?;
+    stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+
+    // The reader thread is left blocked on stdin when the panel closes,
+    // and ends with the process
+    let (sender, keys) = mpsc::channel();
+    thread::spawn(move || {
+        for byte in io::stdin().lock().bytes() {
+            match byte {
+                Ok(byte) => {
+                    if sender.send(byte).is_err() {
+                        return;
+                    }
+                },
+                Err(_) => return,
+            }
+        }
+    });
+
+    // The alternate screen keeps the panel out of the scrollback
+    print!("\x1b[?1049h\x1b[?25l\x1b[2J");
+    let mut panel = FrontPanel::new(image);
+    let result = panel.event_loop(&keys);
+    print!("\x1b[?25h\x1b[?1049l");
+    let _ = io::stdout().flush();
+
+    stty(&[&saved])?;
+    result.map_err(|error| EmulatorError {
+        message: format!("Failed to draw the front panel: {}", error),
+    })
+}
\ No newline at end of file
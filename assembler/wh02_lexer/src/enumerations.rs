@@ -7,6 +7,7 @@ pub enum TokenType {
     Address,
     Location,
     Comma,
+    Colon,
     Comment,
     Whitespace,
     Newline,
@@ -24,6 +25,7 @@ impl fmt::Display for TokenType {
             TokenType::Address => "Address",
             TokenType::Location => "Location",
             TokenType::Comma => "Comma",
+            TokenType::Colon => "Colon",
             TokenType::Comment => "Comment",
             TokenType::Whitespace => "Whitespace",
             TokenType::Newline => "Newline",
@@ -2,6 +2,7 @@ use std::iter::Peekable;
 use std::str::Chars;
 
 pub mod position;
+pub mod span;
 pub mod token;
 pub mod enumerations;
 pub mod lexer_error;
@@ -9,45 +10,96 @@ pub mod lexer_error;
 use enumerations::TokenType;
 use lexer_error::LexerError;
 use position::Position;
+use span::Span;
 use token::Token;
 
+// Lexes the whole source. Text that fails to lex becomes an `Unknown`
+// token, so joining the token values always gives back the source.
+pub fn tokenize(source: &str) -> Vec<Token> {
+    let mut lexer = Lexer::new(source);
+    let mut tokens = Vec::new();
+
+    loop {
+        let start = lexer.position;
+        match lexer.next() {
+            Some(Ok(token)) => {
+                tokens.push(token);
+            },
+            Some(Err(_)) => {
+                let end = lexer.position;
+                tokens.push(Token::new(
+                    source[start.offset..end.offset].to_string(),
+                    TokenType::Unknown,
+                    Span::new(start, end),
+                ));
+            },
+            None => {
+                break;
+            }
+        }
+    }
+
+    tokens
+}
+
 fn is_newline(c: char) -> bool {
-    return c == '\n' || c == '\r';
+    c == '\n' || c == '\r'
 }
 
 fn is_special(c: char) -> bool {
-    return
-        c == '#'
+    c == '#'
         || c == '$'
         || c == '@'
         || c == ','
         || c == ';'
         || c == '.'
-        || c.is_ascii_whitespace();
+        || c == ':'
+        || c.is_ascii_whitespace()
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.'
 }
 
 #[derive(Debug, Clone)]
 pub struct Lexer<'a> {
     pub position: Position,
     pub characters: Peekable<Chars<'a>>,
+    finished: bool,
 }
 
 impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str) -> Lexer<'a> {
+        Lexer {
+            position: Position::default(),
+            characters: source.chars().peekable(),
+            finished: false,
+        }
+    }
 
     fn next_char(&mut self) -> Option<char> {
         let c = self.characters.next();
-        self.position.col += 1;
-        match c {
-            Some(c) => {
-                if is_newline(c) {
-                    self.position.line += 1;
-                    self.position.col = 0;
-                }
+        if let Some(c) = c {
+            self.position.offset += c.len_utf8();
+            if c == '\n' {
+                self.position.line += 1;
+                self.position.col = 0;
+            } else {
+                self.position.col += 1;
             }
-            _ => {}
         }
 
-        return c;
+        c
+    }
+
+    // After an error, skip the rest of the bad token so lexing can resume
+    fn skip_unknown(&mut self) {
+        while let Some(next) = self.characters.peek() {
+            if is_special(*next) {
+                break;
+            }
+            self.next_char();
+        }
     }
 
     fn parse_hex(&mut self, val: &mut String) -> Result<(), LexerError>{
@@ -96,9 +148,6 @@ impl<'a> Lexer<'a> {
             }
         }
 
-        // Normalize hex values to uppercase
-        *val = val.to_ascii_uppercase();
-
         Ok(())
     }
 
@@ -156,13 +205,13 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    fn parse_newline(&mut self, val: &mut String) {
+    fn parse_identifier(&mut self, val: &mut String) {
         let mut end = false;
         while !end {
             let next = self.characters.peek();
             match next {
                 Some(next) => {
-                    if !is_newline(*next) {
+                    if !is_identifier(*next) {
                         break;
                     }
                 },
@@ -183,13 +232,23 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    // A line break is `\n`, `\r\n` or a lone `\r`. Each one is its own
+    // token, so blank lines and the indentation after them stay separate.
+    fn parse_newline(&mut self, first: char, val: &mut String) {
+        if first == '\r' && self.characters.peek() == Some(&'\n') {
+            if let Some(c) = self.next_char() {
+                val.push(c);
+            }
+        }
+    }
+
     fn parse_whitespace(&mut self, val: &mut String) {
         let mut end = false;
         while !end {
             let next = self.characters.peek();
             match next {
                 Some(next) => {
-                    if !(*next).is_ascii_whitespace() {
+                    if !(*next).is_ascii_whitespace() || is_newline(*next) {
                         break;
                     }
                 },
@@ -213,19 +272,21 @@ impl<'a> Lexer<'a> {
     pub fn lex(&mut self) -> Result<Token, LexerError> {
         let mut val = String::new();
         let token_type;
+        let start_position = self.position;
         let c = self.next_char();
 
-        let start_position = self.position.clone();
-
         match c {
             Some(c) => {
                 if c == '.' {
                     token_type = TokenType::Word;
                     val.push(c);
-                    self.parse_alnum(&mut val);
+                    self.parse_identifier(&mut val);
                 } else if c == ',' {
                     token_type = TokenType::Comma;
                     val.push(c);
+                } else if c == ':' {
+                    token_type = TokenType::Colon;
+                    val.push(c);
                 } else if c == ';' {
                     token_type = TokenType::Comment;
                     val.push(c);
@@ -242,14 +303,23 @@ impl<'a> Lexer<'a> {
                     token_type = TokenType::Location;
                     val.push(c);
                     self.parse_alnum(&mut val);
-                } else if c.is_alphabetic() {
+                } else if c.is_alphabetic() || c == '_' {
+                    // Mnemonics and global labels share a grammar; the parser
+                    // decides which one an identifier is from where it appears
                     token_type = TokenType::Operation;
                     val.push(c);
-                    self.parse_alnum(&mut val);
+                    self.parse_identifier(&mut val);
                 } else if is_newline(c) {
-                    self.parse_newline(&mut val);
-                    token_type = TokenType::Newline;
                     val.push(c);
+                    self.parse_newline(c, &mut val);
+                    // The span ends on the line it breaks, rather than at the
+                    // start of the next one
+                    let end = Position {
+                        line: start_position.line,
+                        col: start_position.col + val.chars().count() as u32,
+                        offset: self.position.offset,
+                    };
+                    return Ok(Token::new(val, TokenType::Newline, Span::new(start_position, end)));
                 } else if c.is_ascii_whitespace() {
                     token_type = TokenType::Whitespace;
                     val.push(c);
@@ -257,7 +327,7 @@ impl<'a> Lexer<'a> {
                 } else {
                     return Err(LexerError {
                         message: format!("Unknown character: {}", c),
-                        position: self.position,
+                        position: start_position,
                     });
                 }
             }
@@ -266,6 +336,133 @@ impl<'a> Lexer<'a> {
             }
         }
 
-        return Ok(Token::new(val, token_type, start_position));
+        Ok(Token::new(val, token_type, Span::new(start_position, self.position)))
+    }
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token, LexerError>;
+
+    // Yields every token up to and including `EndOfFile`, then `None`
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let token = self.lex();
+        match &token {
+            Ok(token) => {
+                if token.token_type == TokenType::EndOfFile {
+                    self.finished = true;
+                }
+            },
+            Err(_) => {
+                self.skip_unknown();
+            }
+        }
+
+        Some(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(source: &str) -> Vec<(TokenType, usize, usize)> {
+        tokenize(source)
+            .iter()
+            .map(|token| (token.token_type.clone(), token.span.start.offset, token.span.end.offset))
+            .collect()
+    }
+
+    #[test]
+    fn spans_cover_the_source() {
+        let source = "loop: MOV #01, @A ; go\n";
+        let tokens = tokenize(source);
+
+        let mut offset = 0;
+        for token in &tokens {
+            assert_eq!(token.span.start.offset, offset, "{}", token);
+            assert_eq!(&source[token.span.start.offset..token.span.end.offset], token.value);
+            offset = token.span.end.offset;
+        }
+        assert_eq!(offset, source.len());
+
+        assert_eq!(spans("MOV #01, @A\n"), vec![
+            (TokenType::Operation, 0, 3),
+            (TokenType::Whitespace, 3, 4),
+            (TokenType::Hex, 4, 7),
+            (TokenType::Comma, 7, 8),
+            (TokenType::Whitespace, 8, 9),
+            (TokenType::Location, 9, 11),
+            (TokenType::Newline, 11, 12),
+            (TokenType::EndOfFile, 12, 12),
+        ]);
+    }
+
+    #[test]
+    fn newline_ends_at_the_line_break() {
+        let tokens = tokenize("HLT\r\n\n    NOP\n");
+        let types: Vec<TokenType> = tokens.iter().map(|token| token.token_type.clone()).collect();
+        assert_eq!(types, vec![
+            TokenType::Operation,
+            TokenType::Newline,
+            TokenType::Newline,
+            TokenType::Whitespace,
+            TokenType::Operation,
+            TokenType::Newline,
+            TokenType::EndOfFile,
+        ]);
+
+        assert_eq!(tokens[1].value, "\r\n");
+        assert_eq!(tokens[1].span.start, Position { line: 0, col: 3, offset: 3 });
+        assert_eq!(tokens[1].span.end, Position { line: 0, col: 5, offset: 5 });
+        assert_eq!(tokens[2].span.end.line, 1);
+
+        // The indentation is whitespace on the line it belongs to
+        assert_eq!(tokens[3].value, "    ");
+        assert_eq!(tokens[3].span.start, Position { line: 2, col: 0, offset: 6 });
+        assert_eq!(tokens[4].span.start, Position { line: 2, col: 4, offset: 10 });
+    }
+
+    #[test]
+    fn tokenize_matches_the_lexer() {
+        let source = "main:\n    MOV #01, @A ; one\n    JMP .main\n\nHLT\n";
+        let lexed: Vec<Token> = Lexer::new(source).filter_map(Result::ok).collect();
+        assert_eq!(tokenize(source), lexed);
+    }
+
+    #[test]
+    fn tokenize_keeps_text_the_lexer_rejects() {
+        let source = "MOV #1G, @A\n";
+        let tokens = tokenize(source);
+        assert!(tokens.iter().any(|token| token.token_type == TokenType::Unknown));
+        let text: String = tokens.iter().map(|token| token.value.as_str()).collect();
+        assert_eq!(text, source);
+
+        // Apart from the bad token, both agree
+        let lexed: Vec<Token> = Lexer::new(source).filter_map(Result::ok).collect();
+        let known: Vec<Token> = tokens.into_iter().filter(|token| token.token_type != TokenType::Unknown).collect();
+        assert_eq!(known, lexed);
+    }
+
+    #[test]
+    fn multi_byte_characters() {
+        let source = "größe: HLT ; très\nNOP\n";
+        let tokens = tokenize(source);
+
+        // Offsets count bytes, columns count characters
+        assert_eq!(tokens[0].value, "größe");
+        assert_eq!(tokens[0].span.end, Position { line: 0, col: 5, offset: 7 });
+        assert_eq!(tokens[1].span.start, Position { line: 0, col: 5, offset: 7 });
+
+        let comment = tokens.iter().find(|token| token.token_type == TokenType::Comment).unwrap();
+        assert_eq!(comment.value, "; très");
+        assert_eq!(comment.span.len(), "; très".len());
+        assert_eq!(comment.span.end.col, comment.span.start.col + 6);
+
+        let nop = tokens.iter().find(|token| token.value == "NOP").unwrap();
+        assert_eq!(nop.span.start, Position { line: 1, col: 0, offset: source.find("NOP").unwrap() });
     }
 }
\ No newline at end of file
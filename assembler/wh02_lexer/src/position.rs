@@ -1,13 +1,16 @@
 use core::fmt;
 
+// Lines and columns count from zero, and are shown counting from one.
+// The offset is in bytes from the start of the source.
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Position {
     pub line: u32,
     pub col: u32,
+    pub offset: usize,
 }
 
 impl fmt::Display for Position {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "(line {}, col {})", self.line, self.col + 1)
+        write!(f, "(line {}, col {})", self.line + 1, self.col + 1)
     }
 }
\ No newline at end of file
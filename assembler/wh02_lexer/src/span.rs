@@ -0,0 +1,36 @@
+use core::fmt;
+
+use crate::position::Position;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Span {
+        Span {
+            start,
+            end,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end.offset - self.start.offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start.offset <= offset && offset < self.end.offset
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} to {}", self.start, self.end)
+    }
+}
\ No newline at end of file
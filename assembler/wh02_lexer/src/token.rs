@@ -1,27 +1,27 @@
 use std::fmt;
 
 use crate::enumerations::TokenType;
-use crate::position::Position;
+use crate::span::Span;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Token {
     pub value: String,
     pub token_type: TokenType,
-    pub start_position: Position,
+    pub span: Span,
 }
 
 impl Token {
-    pub fn new(value: String, token_type: TokenType, start_position: Position) -> Token {
-        return Token {
+    pub fn new(value: String, token_type: TokenType, span: Span) -> Token {
+        Token {
             token_type,
             value,
-            start_position,
-        };
+            span,
+        }
     }
 }
 
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Token at {}: {} ({})", self.start_position, self.value, self.token_type)
+        write!(f, "Token at {}: {} ({})", self.span.start, self.value, self.token_type)
     }
 }
\ No newline at end of file
@@ -0,0 +1,493 @@
+/*
+    Combines object files into a program image.
+
+    Library members are only linked in when they define a symbol that
+    is still needed. Sections that use START or ORG go where they were
+    assembled. Every other section goes in the first free space of the
+    memory map region with its name, or anywhere outside a reserved
+    region if there is no such region, in the order the objects were
+    given. Then each relocation is filled in with the final address of
+    its symbol.
+
+    An object's references go to its own labels first, so labels such as
+    `loop` or `.done` can be reused from file to file. Only a label that
+    another object refers to has to be defined in just one.
+*/
+
+use std::collections::HashMap;
+
+use wh02::memory_map::MemoryMap;
+use wh02::object::{Library, ObjectFile, ObjectSection};
+
+use crate::link_error::LinkError;
+
+// Where one section of one object file went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    pub object: usize,
+    pub section: usize,
+    pub address: usize,
+}
+
+// Where a symbol is defined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Definition {
+    object: usize,
+    section: usize,
+    offset: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Linker {
+    pub memory_map: MemoryMap,
+    pub objects: Vec<ObjectFile>,
+    pub placements: Vec<Placement>,
+}
+
+fn error(message: String) -> LinkError {
+    LinkError {
+        message,
+    }
+}
+
+impl Linker {
+    pub fn new(memory_map: MemoryMap) -> Linker {
+        Linker {
+            memory_map,
+            objects: Vec::new(),
+            placements: Vec::new(),
+        }
+    }
+
+    pub fn add_object(&mut self, object: ObjectFile) {
+        self.objects.push(object);
+    }
+
+    // Adds library members that define symbols still needed, until no
+    // more are needed. Members can need symbols from other members, or
+    // from other libraries.
+    pub fn add_libraries(&mut self, libraries: &[Library]) {
+        let mut added: Vec<(usize, usize)> = Vec::new();
+
+        loop {
+            let undefined = self.undefined();
+            let needed = libraries.iter().enumerate().find_map(|(i, library)| {
+                library.members.iter().enumerate().find_map(|(j, member)| {
+                    let defines = member.symbols.iter().any(|symbol| undefined.contains(&symbol.name));
+                    if defines && !added.contains(&(i, j)) {
+                        Some((i, j))
+                    } else {
+                        None
+                    }
+                })
+            });
+
+            match needed {
+                Some((i, j)) => {
+                    added.push((i, j));
+                    self.add_object(libraries[i].members[j].clone());
+                },
+                None => break,
+            }
+        }
+    }
+
+    // Symbols used by an object file but not defined by any
+    pub fn undefined(&self) -> Vec<String> {
+        let mut undefined = Vec::new();
+        for object in &self.objects {
+            for relocation in &object.relocations {
+                let defined = self.objects
+                    .iter()
+                    .any(|other| other.symbols.iter().any(|symbol| symbol.name == relocation.symbol));
+                if !defined && !undefined.contains(&relocation.symbol) {
+                    undefined.push(relocation.symbol.clone());
+                }
+            }
+        }
+
+        undefined
+    }
+
+    // Places every section and fills in every relocation, returning the
+    // image as hex words
+    pub fn link(&mut self) -> Result<Vec<String>, Vec<LinkError>> {
+        let definitions = self.resolve()?;
+        self.place()?;
+
+        let mut image = vec![0u8; self.memory_map.size];
+        for placement in &self.placements {
+            let section = &self.objects[placement.object].sections[placement.section];
+            image[placement.address..placement.address + section.bytes.len()].copy_from_slice(&section.bytes);
+        }
+
+        let mut errors = Vec::new();
+        for (i, object) in self.objects.iter().enumerate() {
+            for (relocation, definition) in object.relocations.iter().zip(&definitions[i]) {
+                let address = self.address_of(definition.object, definition.section) + definition.offset;
+                if address > 0xFF {
+                    errors.push(error(format!(
+                        "Symbol {} is at ${:02X}, which does not fit in the byte on line {} of {}",
+                        relocation.symbol,
+                        address,
+                        relocation.line,
+                        object.name,
+                    )));
+                    continue;
+                }
+
+                let section = object.sections
+                    .iter()
+                    .position(|section| section.name == relocation.section)
+                    .expect("Relocations are checked against their sections when loaded");
+                image[self.address_of(i, section) + relocation.offset] = address as u8;
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(image.iter().map(|byte| format!("{:02X}", byte)).collect())
+    }
+
+    // Whether each address holds part of a section
+    pub fn used(&self) -> Vec<bool> {
+        let mut used = vec![false; self.memory_map.size];
+        for placement in &self.placements {
+            let length = self.objects[placement.object].sections[placement.section].bytes.len();
+            for flag in used.iter_mut().skip(placement.address).take(length) {
+                *flag = true;
+            }
+        }
+
+        used
+    }
+
+    fn address_of(&self, object: usize, section: usize) -> usize {
+        self.placements
+            .iter()
+            .find(|placement| placement.object == object && placement.section == section)
+            .map(|placement| placement.address)
+            .expect("Every section is placed before relocations are filled in")
+    }
+
+    // Every definition of each symbol
+    fn definitions(&self) -> HashMap<&str, Vec<Definition>> {
+        let mut definitions: HashMap<&str, Vec<Definition>> = HashMap::new();
+
+        for (i, object) in self.objects.iter().enumerate() {
+            for symbol in &object.symbols {
+                let section = object.sections
+                    .iter()
+                    .position(|section| section.name == symbol.section)
+                    .expect("Symbols are checked against their sections when loaded");
+
+                definitions.entry(&symbol.name).or_default().push(Definition {
+                    object: i,
+                    section,
+                    offset: symbol.offset,
+                });
+            }
+        }
+
+        definitions
+    }
+
+    // The definition each relocation refers to, for each object. An
+    // object's own definition comes first; otherwise there must be
+    // exactly one other.
+    fn resolve(&self) -> Result<Vec<Vec<Definition>>, Vec<LinkError>> {
+        let definitions = self.definitions();
+        let mut resolved = Vec::new();
+        let mut errors = Vec::new();
+
+        for (i, object) in self.objects.iter().enumerate() {
+            let mut object_resolved = Vec::new();
+            for relocation in &object.relocations {
+                let found = definitions.get(relocation.symbol.as_str()).map(Vec::as_slice).unwrap_or_default();
+                let own = found.iter().find(|definition| definition.object == i);
+
+                match (own, found) {
+                    (Some(definition), _) => object_resolved.push(*definition),
+                    (None, [definition]) => object_resolved.push(*definition),
+                    (None, []) => errors.push(error(format!(
+                        "Undefined symbol {}, used on line {} of {}",
+                        relocation.symbol,
+                        relocation.line,
+                        object.name,
+                    ))),
+                    (None, [first, second, ..]) => errors.push(error(format!(
+                        "Symbol {}, used on line {} of {}, is defined in both {} and {}",
+                        relocation.symbol,
+                        relocation.line,
+                        object.name,
+                        self.objects[first.object].name,
+                        self.objects[second.object].name,
+                    ))),
+                }
+            }
+            resolved.push(object_resolved);
+        }
+
+        if errors.is_empty() {
+            Ok(resolved)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn place(&mut self) -> Result<(), Vec<LinkError>> {
+        self.placements.clear();
+        let mut owners: Vec<Option<(usize, usize)>> = vec![None; self.memory_map.size];
+        let mut errors = Vec::new();
+
+        // Fixed sections first, so the others can fit around them
+        let mut sections: Vec<(usize, usize)> = self.objects
+            .iter()
+            .enumerate()
+            .flat_map(|(i, object)| (0..object.sections.len()).map(move |j| (i, j)))
+            .collect();
+        sections.sort_by_key(|(i, j)| self.objects[*i].sections[*j].origin.is_none());
+
+        for (i, j) in sections {
+            let section = &self.objects[i].sections[j];
+            let address = match section.origin {
+                Some(origin) => match self.conflict(section, origin, &owners) {
+                    Some(message) => {
+                        errors.push(error(format!(
+                            "Section {} of {} cannot go at ${:02X}: {}",
+                            section.name,
+                            self.objects[i].name,
+                            origin,
+                            message,
+                        )));
+                        continue;
+                    },
+                    None => origin,
+                },
+                None => match self.free_space(section, &owners) {
+                    Ok(address) => address,
+                    Err(message) => {
+                        errors.push(error(format!("No room for section {} of {}: {}", section.name, self.objects[i].name, message)));
+                        continue;
+                    }
+                },
+            };
+
+            for owner in owners.iter_mut().skip(address).take(section.bytes.len()) {
+                *owner = Some((i, j));
+            }
+
+            self.placements.push(Placement {
+                object: i,
+                section: j,
+                address,
+            });
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Why a section cannot go at an address, if it cannot
+    fn conflict(&self, section: &ObjectSection, address: usize, owners: &[Option<(usize, usize)>]) -> Option<String> {
+        if address + section.bytes.len() > self.memory_map.size {
+            return Some(format!("memory ends at ${:02X}", self.memory_map.size.saturating_sub(1)));
+        }
+
+        for (at, owner) in owners.iter().enumerate().skip(address).take(section.bytes.len()) {
+            if let Some((i, j)) = owner {
+                return Some(format!(
+                    "${:02X} is already used by section {} of {}",
+                    at,
+                    self.objects[*i].sections[*j].name,
+                    self.objects[*i].name,
+                ));
+            }
+
+            if let Some(region) = self.memory_map.region_at(at) {
+                if !region.kind.holds_code() {
+                    return Some(format!("${:02X} is in region {}", at, region));
+                }
+            }
+        }
+
+        None
+    }
+
+    fn free_space(&self, section: &ObjectSection, owners: &[Option<(usize, usize)>]) -> Result<usize, String> {
+        let (start, end, place) = match self.memory_map.region(&section.name) {
+            Some(region) => (region.start, region.end + 1, format!("region {}", region)),
+            None => (0, self.memory_map.size, "memory".to_string()),
+        };
+
+        (start..end)
+            .find(|address| address + section.bytes.len() <= end && self.conflict(section, *address, owners).is_none())
+            .ok_or_else(|| format!("{} bytes do not fit in {}", section.bytes.len(), place))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wh02::object::{Relocation, Symbol};
+    use wh02_lexer::Lexer;
+    use wh02_parser::Parser;
+
+    use super::*;
+
+    fn section(name: &str, origin: Option<usize>, bytes: &[u8]) -> ObjectSection {
+        ObjectSection {
+            name: name.to_string(),
+            origin,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    // An object with one symbol per name, at offset 0 of its first section
+    fn object(name: &str, sections: Vec<ObjectSection>, defines: &[&str], uses: &[(usize, &str)]) -> ObjectFile {
+        let first = sections[0].name.clone();
+        ObjectFile {
+            name: name.to_string(),
+            symbols: defines
+                .iter()
+                .map(|symbol| Symbol {
+                    name: symbol.to_string(),
+                    section: first.clone(),
+                    offset: 0,
+                })
+                .collect(),
+            relocations: uses
+                .iter()
+                .map(|(offset, symbol)| Relocation {
+                    section: first.clone(),
+                    offset: *offset,
+                    symbol: symbol.to_string(),
+                    line: 1,
+                })
+                .collect(),
+            sections,
+        }
+    }
+
+    fn linker(objects: Vec<ObjectFile>) -> Linker {
+        let mut linker = Linker::new(MemoryMap::default());
+        for object in objects {
+            linker.add_object(object);
+        }
+        linker
+    }
+
+    fn assemble(name: &str, source: &str) -> ObjectFile {
+        let mut parser = Parser::new(Lexer::new(source));
+        parser.parse_all();
+        assert!(parser.errors.is_empty(), "{:?}", parser.errors);
+        ObjectFile::assemble(name, parser.expressions, parser.positions).unwrap()
+    }
+
+    fn messages(errors: Vec<LinkError>) -> Vec<String> {
+        errors.into_iter().map(|error| error.message).collect()
+    }
+
+    #[test]
+    fn fills_in_relocations() {
+        let mut linker = linker(vec![
+            object("main.o", vec![section("code", None, &[0xAA, 0x00])], &["main"], &[(1, "delay")]),
+            object("delay.o", vec![section("code", None, &[0xBB, 0xCC])], &["delay"], &[]),
+        ]);
+
+        let image = linker.link().unwrap();
+        assert_eq!(image[..4], ["AA", "02", "BB", "CC"]);
+    }
+
+    #[test]
+    fn places_fixed_sections_first() {
+        let mut linker = linker(vec![
+            object("main.o", vec![section("code", None, &[0xAA, 0xAB])], &["main"], &[]),
+            object("table.o", vec![section("data", Some(0x01), &[0xCC])], &["table"], &[]),
+        ]);
+
+        let image = linker.link().unwrap();
+        assert_eq!(image[..4], ["00", "CC", "AA", "AB"]);
+        assert_eq!(linker.placements[0], Placement {
+            object: 1,
+            section: 0,
+            address: 0x01,
+        });
+    }
+
+    #[test]
+    fn rejects_overlapping_fixed_sections() {
+        let mut linker = linker(vec![
+            object("one.o", vec![section("code", Some(0x10), &[0xAA, 0xAB])], &[], &[]),
+            object("two.o", vec![section("data", Some(0x11), &[0xCC])], &[], &[]),
+        ]);
+
+        let errors = messages(linker.link().unwrap_err());
+        assert_eq!(errors, ["Section data of two.o cannot go at $11: $11 is already used by section code of one.o"]);
+    }
+
+    #[test]
+    fn adds_only_needed_library_members() {
+        let library = Library {
+            members: vec![
+                object("unused.o", vec![section("code", None, &[0x01])], &["unused"], &[]),
+                object("delay.o", vec![section("code", None, &[0x02, 0x00])], &["delay"], &[(1, "wait")]),
+                object("wait.o", vec![section("code", None, &[0x03])], &["wait"], &[]),
+            ],
+        };
+
+        let mut linker = linker(vec![object("main.o", vec![section("code", None, &[0xAA, 0x00])], &["main"], &[(1, "delay")])]);
+        linker.add_libraries(&[library]);
+
+        let names: Vec<&str> = linker.objects.iter().map(|object| object.name.as_str()).collect();
+        assert_eq!(names, ["main.o", "delay.o", "wait.o"]);
+        assert!(linker.undefined().is_empty());
+    }
+
+    #[test]
+    fn reports_undefined_symbols() {
+        let mut linker = linker(vec![object("main.o", vec![section("code", None, &[0xAA, 0x00])], &["main"], &[(1, "delay")])]);
+
+        let errors = messages(linker.link().unwrap_err());
+        assert_eq!(errors, ["Undefined symbol delay, used on line 1 of main.o"]);
+    }
+
+    #[test]
+    fn objects_share_label_names() {
+        // Each object has its own `done`, and its own references go to it
+        let mut linker = linker(vec![
+            object("one.o", vec![section("code", None, &[0xAA, 0x00])], &["one", "done"], &[(1, "done")]),
+            object("two.o", vec![section("code", None, &[0xBB, 0x00])], &["two", "done"], &[(1, "done")]),
+        ]);
+
+        let image = linker.link().unwrap();
+        assert_eq!(image[..4], ["AA", "00", "BB", "02"]);
+    }
+
+    #[test]
+    fn objects_share_local_labels() {
+        let mut linker = linker(vec![
+            assemble("one.asm", ".loop: MOV #01, @O1\nJMP .loop\n"),
+            assemble("two.asm", ".loop: MOV #02, @O1\nJMP .loop\n"),
+        ]);
+
+        let image = linker.link().unwrap();
+        assert_eq!(image[..10], ["24", "01", "2D", "00", "24", "02", "2D", "04", "00", "00"]);
+    }
+
+    #[test]
+    fn reports_duplicate_symbols() {
+        let mut linker = linker(vec![
+            object("main.o", vec![section("code", None, &[0xAA, 0x00])], &["main"], &[(1, "delay")]),
+            object("one.o", vec![section("code", None, &[0xBB])], &["delay"], &[]),
+            object("two.o", vec![section("code", None, &[0xCC])], &["delay"], &[]),
+        ]);
+
+        let errors = messages(linker.link().unwrap_err());
+        assert_eq!(errors, ["Symbol delay, used on line 1 of main.o, is defined in both one.o and two.o"]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,201 @@
+/*
+    wh02-link, the linker for WH-02 object files.
+
+    Combines object files written by `wh02 obj`, and any library members
+    they need, into a program image. Where sections go comes from the
+    memory map: `--memory-map`, or else a `wh02.toml` in the current
+    folder, with `--memory-size` and `--region` on top. `--library`
+    collects object files into a library instead.
+*/
+
+use std::path::PathBuf;
+use std::{env, fs, process};
+
+use wh02::assembler::image_text;
+use wh02::json::Json;
+use wh02::memory_map::{parse_number, MemoryMap, Region, PROJECT_FILE};
+use wh02::object::{Library, ObjectFile, LIBRARY_FORMAT, OBJECT_FORMAT};
+use wh02::object_error::ObjectError;
+
+mod link_error;
+mod linker;
+
+use linker::Linker;
+
+// An input file, which holds an object or a library
+enum Input {
+    Object(ObjectFile),
+    Library(Library),
+}
+
+fn load(path: &str) -> Result<Input, String> {
+    let text = fs::read_to_string(path).map_err(|error| format!("Failed to read {}: {}", path, error))?;
+    let json = Json::parse(&text).map_err(|error| format!("{}: {}", path, error))?;
+    let input = match json.get("format").and_then(Json::as_str) {
+        Some(OBJECT_FORMAT) => ObjectFile::from_json(&json).map(Input::Object),
+        Some(LIBRARY_FORMAT) => Library::from_json(&json).map(Input::Library),
+        _ => Err(ObjectError {
+            message: "Not an object file or library".to_string(),
+        }),
+    };
+
+    input.map_err(|error| format!("{}: {}", path, error))
+}
+
+fn usage() -> i32 {
+    println!("Usage: wh02-link [--memory-map <wh02.toml>] [--memory-size N] [--region name:kind:start-end]...");
+    println!("                 -o <program.bin> <input.o|input.a>...");
+    println!("       wh02-link --library <library.a> <object.o>...");
+    2
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let code = match args.first().map(|arg| arg.as_str()) {
+        Some("--library") => write_library(&args[1..]),
+        _ => link(&args),
+    };
+
+    process::exit(code);
+}
+
+fn write_library(args: &[String]) -> i32 {
+    if args.len() < 2 {
+        return usage();
+    }
+
+    let mut members = Vec::new();
+    for path in &args[1..] {
+        match load(path) {
+            Ok(Input::Object(object)) => members.push(object),
+            Ok(Input::Library(library)) => members.extend(library.members),
+            Err(error) => {
+                println!("ERROR: {}", error);
+                return 1;
+            }
+        }
+    }
+
+    let library = Library {
+        members,
+    };
+    fs::write(&args[0], format!("{}\n", library.to_json())).expect("Failed to write to output file.");
+    println!("Wrote {} members to {}", library.members.len(), args[0]);
+
+    0
+}
+
+fn link(args: &[String]) -> i32 {
+    let mut map_path = None;
+    let mut memory_size = None;
+    let mut regions = Vec::new();
+    let mut output_path = None;
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let result = match arg.as_str() {
+            "-o" => match args.next() {
+                Some(path) => {
+                    output_path = Some(path.clone());
+                    Ok(())
+                },
+                None => Err(format!("{} expects a path", arg)),
+            },
+            "--memory-map" => match args.next() {
+                Some(path) => {
+                    map_path = Some(PathBuf::from(path));
+                    Ok(())
+                },
+                None => Err(format!("{} expects a path", arg)),
+            },
+            "--memory-size" => match args.next().and_then(|size| parse_number(size)) {
+                Some(size) => {
+                    memory_size = Some(size);
+                    Ok(())
+                },
+                None => Err(format!("{} expects a number, such as 256 or 0x100", arg)),
+            },
+            "--region" => match args.next().map(|spec| Region::from_spec(spec)) {
+                Some(Ok(region)) => {
+                    regions.push(region);
+                    Ok(())
+                },
+                Some(Err(error)) => Err(error.to_string()),
+                None => Err(format!("{} expects name:kind:start-end", arg)),
+            },
+            _ => {
+                paths.push(arg);
+                Ok(())
+            }
+        };
+
+        if let Err(error) = result {
+            println!("ERROR: {}", error);
+            return 2;
+        }
+    }
+
+    let output_path = match output_path {
+        Some(path) if !paths.is_empty() => path,
+        _ => return usage(),
+    };
+
+    let project = map_path.or_else(|| Some(PathBuf::from(PROJECT_FILE)).filter(|path| path.is_file()));
+    let memory_map = match MemoryMap::configure(project.as_deref(), memory_size, regions) {
+        Ok(memory_map) => memory_map,
+        Err(error) => {
+            println!("ERROR: {}", error);
+            return 2;
+        }
+    };
+
+    let mut linker = Linker::new(memory_map);
+    let mut libraries = Vec::new();
+    for path in paths {
+        match load(path) {
+            Ok(Input::Object(object)) => linker.add_object(object),
+            Ok(Input::Library(library)) => libraries.push(library),
+            Err(error) => {
+                println!("ERROR: {}", error);
+                return 1;
+            }
+        }
+    }
+    linker.add_libraries(&libraries);
+
+    let image = match linker.link() {
+        Ok(image) => image,
+        Err(errors) => {
+            for error in errors {
+                println!("ERROR: {}", error);
+            }
+            return 1;
+        }
+    };
+
+    for placement in &linker.placements {
+        let object = &linker.objects[placement.object];
+        let section = &object.sections[placement.section];
+        if section.bytes.is_empty() {
+            continue;
+        }
+
+        println!(
+            "Placed {} from {} at ${:02X}-${:02X}",
+            section.name,
+            object.name,
+            placement.address,
+            placement.address + section.bytes.len() - 1,
+        );
+    }
+
+    fs::write(&output_path, image_text(&image)).expect("Failed to write to output file.");
+    println!("Wrote {} hex words to {}", image.len(), output_path);
+
+    let used = linker.used().iter().filter(|used| **used).count();
+    let size = linker.memory_map.size;
+    println!("Used {} of {} bytes, {} free", used, size, size - used);
+
+    0
+}
\ No newline at end of file
@@ -0,0 +1,354 @@
+/*
+    Everything the language server knows about one open document.
+
+    A document is run through the concrete syntax tree, for positions of
+    labels and instructions, and through the same parser and assembler
+    that `wh02` uses, for diagnostics and encodings. Programs that
+    assemble are linted as well. The analysis is rebuilt from scratch on
+    every change; programs are small enough that this is instant.
+*/
+
+use std::collections::HashMap;
+
+use rom_builder::RomBuilder;
+use wh02::assembler::Assembler;
+use wh02::lint::Level;
+use wh02::linter::Linter;
+use wh02_lexer::position::Position;
+use wh02_lexer::span::Span;
+use wh02_lexer::Lexer;
+use wh02_parser::ast::{Instruction, Program};
+use wh02_parser::cst::{self, SyntaxNode};
+use wh02_parser::keyword::Keyword;
+use wh02_parser::{qualify_label, Parser};
+
+pub const REGISTERS: [&str; 6] = ["A", "B", "C", "O1", "O2", "ACC"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub severity: Severity,
+    pub message: String,
+}
+
+// A label definition or a reference to one. Names are qualified the way
+// the parser qualifies them, so `.loop` under `main:` is `main.loop`.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub span: Span,
+    pub is_definition: bool,
+    pub is_local: bool,
+}
+
+// A global label with the local labels under it, and the code it covers
+#[derive(Debug, Clone)]
+pub struct Outline {
+    pub label: Symbol,
+    pub span: Span,
+    pub children: Vec<Symbol>,
+}
+
+// The bytes an instruction on a given source line assembles to
+#[derive(Debug, Clone)]
+pub struct Encoding {
+    pub line: u32,
+    pub address: usize,
+    pub bytes: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Completion {
+    Mnemonic(Keyword),
+    Register {
+        name: String,
+        // Whether the `@` has already been typed
+        prefixed: bool,
+    },
+    Label(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub source: String,
+    pub tree: SyntaxNode,
+    pub diagnostics: Vec<Diagnostic>,
+    pub symbols: Vec<Symbol>,
+    pub encodings: Vec<Encoding>,
+    // Label addresses, once the program assembles
+    pub addresses: HashMap<String, usize>,
+}
+
+impl Analysis {
+    pub fn new(source: &str) -> Analysis {
+        let tree = cst::parse(source);
+        let symbols = Self::collect_symbols(&tree);
+
+        let mut analysis = Analysis {
+            source: source.to_string(),
+            tree,
+            diagnostics: Vec::new(),
+            symbols,
+            encodings: Vec::new(),
+            addresses: HashMap::new(),
+        };
+
+        let mut parser = Parser::new(Lexer::new(source));
+        parser.parse_all();
+
+        for warning in &parser.warnings {
+            analysis.add_diagnostic(warning.position, Severity::Warning, &warning.message);
+        }
+
+        for error in &parser.errors {
+            analysis.add_diagnostic(error.position, Severity::Error, &error.message);
+        }
+
+        if !parser.errors.is_empty() {
+            return analysis;
+        }
+
+        let mut assembler = Assembler::new(parser.expressions);
+        assembler.positions = parser.positions.clone();
+
+        match assembler.assemble() {
+            Err(error) => {
+                let position = error.position.unwrap_or_default();
+                analysis.add_diagnostic(position, Severity::Error, &error.message);
+            },
+            Ok(_) => {
+                for (i, position) in parser.positions.iter().enumerate() {
+                    // Directives and label definitions take no space
+                    if assembler.sizes[i] == 0 {
+                        continue;
+                    }
+
+                    analysis.encodings.push(Encoding {
+                        line: position.line,
+                        address: assembler.addresses[i],
+                        bytes: assembler.expression_bytes(i).to_vec(),
+                    });
+                }
+
+                for warning in Linter::default().lint(source, &assembler) {
+                    let severity = match warning.level {
+                        Level::Deny => Severity::Error,
+                        _ => Severity::Warning,
+                    };
+                    let message = format!("{} ({})", warning.message, warning.lint);
+                    analysis.add_diagnostic(warning.position, severity, &message);
+                }
+
+                analysis.addresses = assembler.words;
+            }
+        }
+
+        analysis
+    }
+
+    fn add_diagnostic(&mut self, position: Position, severity: Severity, message: &str) {
+        // Underline the whole token the message is about, if there is one
+        let span = match self.tree.token_at(position.offset) {
+            Some(token) => token.span,
+            None => Span::new(position, position),
+        };
+
+        // Some messages are laid out for the terminal
+        let message = message.trim().trim_start_matches("==> ").to_string();
+
+        self.diagnostics.push(Diagnostic {
+            span,
+            severity,
+            message,
+        });
+    }
+
+    fn collect_symbols(tree: &SyntaxNode) -> Vec<Symbol> {
+        let program = Program::cast(tree).expect("cst::parse always returns a program");
+        let mut symbols = Vec::new();
+        let mut global_label: Option<String> = None;
+
+        for line in program.lines() {
+            if let Some(label) = line.label() {
+                let name = label.name();
+                symbols.push(Symbol {
+                    name: qualify_label(global_label.as_deref(), &name.value),
+                    span: name.span,
+                    is_definition: true,
+                    is_local: label.is_local(),
+                });
+
+                if !label.is_local() {
+                    global_label = Some(name.value.clone());
+                }
+            }
+
+            if let Some(instruction) = line.instruction() {
+                let defining = instruction.keyword() == Some(Keyword::DEF);
+                // Section names are not labels
+                let naming = instruction.keyword() == Some(Keyword::SECTION);
+                for operand in instruction.operands() {
+                    if !operand.is_label() || naming {
+                        continue;
+                    }
+
+                    let token = operand.token();
+                    symbols.push(Symbol {
+                        name: qualify_label(global_label.as_deref(), &token.value),
+                        span: token.span,
+                        is_definition: defining,
+                        is_local: token.value.starts_with('.'),
+                    });
+
+                    if defining && !symbols[symbols.len() - 1].is_local {
+                        global_label = Some(token.value.clone());
+                    }
+                }
+            }
+        }
+
+        symbols
+    }
+
+    pub fn symbol_at(&self, offset: usize) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.span.contains(offset))
+    }
+
+    pub fn definition(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.is_definition && symbol.name == name)
+    }
+
+    pub fn references(&self, name: &str, include_definition: bool) -> Vec<&Symbol> {
+        self.symbols
+            .iter()
+            .filter(|symbol| symbol.name == name && (include_definition || !symbol.is_definition))
+            .collect()
+    }
+
+    pub fn outline(&self) -> Vec<Outline> {
+        let program = Program::cast(&self.tree).expect("cst::parse always returns a program");
+        let mut outline: Vec<Outline> = Vec::new();
+
+        for line in program.lines() {
+            let span = line.node.span();
+            let definition = self.symbols
+                .iter()
+                .find(|symbol| symbol.is_definition && span.contains(symbol.span.start.offset));
+
+            match (definition, outline.last_mut()) {
+                (Some(label), Some(parent)) if label.is_local => parent.children.push(label.clone()),
+                (Some(label), _) => outline.push(Outline {
+                    label: label.clone(),
+                    span: label.span,
+                    children: Vec::new(),
+                }),
+                (None, _) => {},
+            }
+
+            // A block runs to the end of the last instruction under its label
+            if let (Some(parent), Some(instruction)) = (outline.last_mut(), line.instruction()) {
+                parent.span.end = instruction.node.span().end;
+            }
+        }
+
+        outline
+    }
+
+    fn instruction_at(&self, offset: usize) -> Option<Instruction<'_>> {
+        let program = Program::cast(&self.tree).expect("cst::parse always returns a program");
+        program.lines()
+            .into_iter()
+            .filter_map(|line| line.instruction())
+            .find(|instruction| instruction.node.span().contains(offset))
+    }
+
+    // Markdown describing whatever is at the offset, and the text it covers
+    pub fn hover(&self, offset: usize, rom: &RomBuilder) -> Option<(String, Span)> {
+        if let Some(symbol) = self.symbol_at(offset) {
+            let mut text = format!("label `{}`", symbol.name);
+            if let Some(address) = self.addresses.get(&symbol.name) {
+                text += &format!(" at `${:02X}`", address);
+            }
+            if let Some(definition) = self.definition(&symbol.name) {
+                text += &format!("\n\nDefined on line {}", definition.span.start.line + 1);
+            }
+            return Some((text, symbol.span));
+        }
+
+        let instruction = self.instruction_at(offset)?;
+        let mnemonic = instruction.mnemonic();
+        let encoding = self.encodings.iter().find(|encoding| encoding.line == mnemonic.span.start.line)?;
+        let opcode = u16::from_str_radix(encoding.bytes.first()?, 16).ok()?;
+
+        let operands: Vec<String> = instruction.operands()
+            .iter()
+            .map(|operand| operand.token().value.clone())
+            .collect();
+        let mut form = mnemonic.value.to_ascii_uppercase();
+        if !operands.is_empty() {
+            form += " ";
+            form += &operands.join(",");
+        }
+
+        let size = encoding.bytes.len();
+        let text = format!(
+            "```\n{}\n```\nOpcode `0x{:02X}`, {} byte{}, {} cycles\n\nAt `${:02X}`: `{}`",
+            form,
+            opcode,
+            size,
+            if size == 1 { "" } else { "s" },
+            rom.cycles(opcode),
+            encoding.address,
+            encoding.bytes.join(" "),
+        );
+
+        Some((text, instruction.node.span()))
+    }
+
+    pub fn completions(&self, offset: usize) -> Vec<Completion> {
+        let line_start = self.source[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let mut before = &self.source[line_start..offset];
+
+        if before.contains(';') {
+            return Vec::new();
+        }
+
+        if let Some(colon) = before.find(':') {
+            before = &before[colon + 1..];
+        }
+
+        // Still typing the first word of the line
+        if !before.trim_start().contains(char::is_whitespace) {
+            return Keyword::all().into_iter().map(Completion::Mnemonic).collect();
+        }
+
+        let word = before
+            .rsplit(|c: char| c.is_whitespace() || c == ',')
+            .next()
+            .unwrap_or("");
+
+        let registers = REGISTERS.iter().map(|name| Completion::Register {
+            name: name.to_string(),
+            prefixed: word.starts_with('@'),
+        });
+
+        if word.starts_with('@') {
+            return registers.collect();
+        }
+
+        let mut labels: Vec<String> = self.symbols
+            .iter()
+            .filter(|symbol| symbol.is_definition)
+            .map(|symbol| symbol.name.clone())
+            .collect();
+        labels.dedup();
+
+        registers.chain(labels.into_iter().map(Completion::Label)).collect()
+    }
+}
\ No newline at end of file
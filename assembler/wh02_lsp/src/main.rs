@@ -0,0 +1,101 @@
+/*
+    wh02-lsp, a language server for WH-02 assembly.
+
+    Editors start this binary and talk to it over stdin and stdout using
+    the Language Server Protocol. Each message is a JSON body preceded by
+    a `Content-Length` header. Anything printed for people goes to stderr.
+*/
+
+use std::io::{self, BufRead, Write};
+use std::process;
+
+use wh02::json::Json;
+
+mod analysis;
+mod server;
+
+use server::Server;
+
+// Reads one framed message, or None once the client closes the stream
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = match length {
+        Some(length) => length,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length header")),
+    };
+
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+
+    match String::from_utf8(body) {
+        Ok(body) => Ok(Some(body)),
+        Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "Message is not UTF-8")),
+    }
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+fn main() {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+
+    let mut server = Server::new();
+
+    loop {
+        let body = match read_message(&mut input) {
+            Ok(Some(body)) => body,
+            Ok(None) => break,
+            Err(error) => {
+                eprintln!("ERROR: {}", error);
+                break;
+            }
+        };
+
+        let message = match Json::parse(&body) {
+            Ok(message) => message,
+            Err(error) => {
+                eprintln!("ERROR: {}", error);
+                continue;
+            }
+        };
+
+        for reply in server.handle(&message) {
+            if let Err(error) = write_message(&mut output, &reply) {
+                eprintln!("ERROR: {}", error);
+                process::exit(1);
+            }
+        }
+
+        if let Some(code) = server.exit_code {
+            process::exit(code);
+        }
+    }
+
+    // The client went away without asking us to exit
+    process::exit(1);
+}
\ No newline at end of file
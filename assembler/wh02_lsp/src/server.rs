@@ -0,0 +1,386 @@
+/*
+    Handles Language Server Protocol messages for WH-02 assembly.
+
+    Documents are synced in full on every change, and each change
+    republishes diagnostics. Requests are answered from the document's
+    `Analysis`; see there for what is known about a program.
+*/
+
+use std::collections::HashMap;
+
+use rom_builder::RomBuilder;
+use wh02::json::Json;
+use wh02_lexer::position::Position;
+use wh02_lexer::span::Span;
+use wh02_parser::keyword::Keyword;
+
+use crate::analysis::{Analysis, Completion, Severity, Symbol};
+
+// JSON-RPC error codes
+const INVALID_PARAMS: i32 = -32602;
+const METHOD_NOT_FOUND: i32 = -32601;
+
+// LSP kinds, from the specification
+const COMPLETION_KEYWORD: u32 = 14;
+const COMPLETION_VARIABLE: u32 = 6;
+const COMPLETION_REFERENCE: u32 = 18;
+const SYMBOL_FUNCTION: u32 = 12;
+const SYMBOL_KEY: u32 = 20;
+const SYNC_FULL: u32 = 1;
+
+struct RequestError {
+    code: i32,
+    message: String,
+}
+
+pub struct Server {
+    documents: HashMap<String, Analysis>,
+    rom: RomBuilder,
+    shutting_down: bool,
+    // Set once the client sends `exit`
+    pub exit_code: Option<i32>,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// LSP counts characters in UTF-16 code units, where the lexer counts
+// chars, so the column is counted again from the byte offset
+fn position_json(source: &str, position: Position) -> Json {
+    let line_start = source[..position.offset].rfind('\n').map(|newline| newline + 1).unwrap_or(0);
+    Json::object(vec![
+        ("line", position.line.into()),
+        ("character", source[line_start..position.offset].encode_utf16().count().into()),
+    ])
+}
+
+fn range_json(source: &str, span: Span) -> Json {
+    Json::object(vec![
+        ("start", position_json(source, span.start)),
+        ("end", position_json(source, span.end)),
+    ])
+}
+
+fn location_json(uri: &str, source: &str, span: Span) -> Json {
+    Json::object(vec![
+        ("uri", uri.into()),
+        ("range", range_json(source, span)),
+    ])
+}
+
+fn describe(keyword: &Keyword) -> &'static str {
+    match keyword {
+        Keyword::MOV => "Move a value to a register or memory address",
+        Keyword::HLT => "Halt the processor",
+        Keyword::NOP => "No operation",
+        Keyword::DEF => "Define a label at the current address",
+        Keyword::START => "Set where the program is loaded into memory",
+        Keyword::JMP => "Jump to an address, label or the address in a register",
+        Keyword::ORG => "Place the code that follows at an address",
+        Keyword::SECTION => "Switch to a named section, such as code or data",
+        Keyword::CALL => "Call a subroutine, saving where to return to on the stack",
+        Keyword::RET => "Return from a subroutine to the address on the stack",
+        Keyword::PUSH => "Push a register onto the stack",
+        Keyword::POP => "Pop the top of the stack into a register",
+        Keyword::ADD => "Set ACC to A plus B",
+        Keyword::SUB => "Set ACC to A minus B",
+        Keyword::SHL => "Set ACC to A shifted left by 1 to 7 places",
+        Keyword::SHR => "Set ACC to A shifted right by 1 to 7 places",
+    }
+}
+
+// Byte offset of an LSP line and character, clamped to the line. The
+// character counts UTF-16 code units.
+fn offset_at(source: &str, line: u64, character: u64) -> usize {
+    let mut offset = 0;
+    for (number, text) in source.split('\n').enumerate() {
+        if number as u64 == line {
+            let mut units = 0;
+            for (i, c) in text.char_indices() {
+                if units >= character {
+                    return offset + i;
+                }
+                units += c.len_utf16() as u64;
+            }
+            return offset + text.len();
+        }
+        offset += text.len() + 1;
+    }
+
+    source.len()
+}
+
+impl Server {
+    pub fn new() -> Server {
+        let mut rom = RomBuilder::default();
+        rom.build_rom();
+
+        Server {
+            documents: HashMap::new(),
+            rom,
+            shutting_down: false,
+            exit_code: None,
+        }
+    }
+
+    // Handles one message from the client and returns the messages to
+    // send back, in order
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(|method| method.as_str()).unwrap_or("");
+        let params = message.get("params").unwrap_or(&Json::Null);
+
+        let id = match message.get("id") {
+            Some(id) => id.clone(),
+            None => return self.notify(method, params),
+        };
+
+        let response = match self.request(method, params) {
+            Ok(result) => Json::object(vec![
+                ("jsonrpc", "2.0".into()),
+                ("id", id),
+                ("result", result),
+            ]),
+            Err(error) => Json::object(vec![
+                ("jsonrpc", "2.0".into()),
+                ("id", id),
+                ("error", Json::object(vec![
+                    ("code", Json::Number(error.code as f64)),
+                    ("message", error.message.into()),
+                ])),
+            ]),
+        };
+
+        vec![response]
+    }
+
+    fn notify(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let document = params.get("textDocument");
+        let uri = document
+            .and_then(|document| document.get("uri"))
+            .and_then(|uri| uri.as_str())
+            .unwrap_or("")
+            .to_string();
+
+        match method {
+            "exit" => {
+                self.exit_code = Some(if self.shutting_down { 0 } else { 1 });
+                Vec::new()
+            },
+            "textDocument/didOpen" => {
+                let text = document.and_then(|document| document.get("text")).and_then(|text| text.as_str());
+                self.open(uri, text.unwrap_or(""))
+            },
+            "textDocument/didChange" => {
+                // With full sync the last change holds the whole text
+                let text = params.get("contentChanges")
+                    .and_then(|changes| changes.as_array())
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(|text| text.as_str());
+
+                match text {
+                    Some(text) => self.open(uri, text),
+                    None => Vec::new(),
+                }
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![Self::publish_diagnostics(&uri, Vec::new())]
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    fn open(&mut self, uri: String, text: &str) -> Vec<Json> {
+        let analysis = Analysis::new(text);
+        let diagnostics = analysis.diagnostics
+            .iter()
+            .map(|diagnostic| Json::object(vec![
+                ("range", range_json(text, diagnostic.span)),
+                ("severity", match diagnostic.severity {
+                    Severity::Error => 1u32,
+                    Severity::Warning => 2u32,
+                }.into()),
+                ("source", "wh02".into()),
+                ("message", diagnostic.message.clone().into()),
+            ]))
+            .collect();
+
+        let notification = Self::publish_diagnostics(&uri, diagnostics);
+        self.documents.insert(uri, analysis);
+        vec![notification]
+    }
+
+    fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+        Json::object(vec![
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/publishDiagnostics".into()),
+            ("params", Json::object(vec![
+                ("uri", uri.into()),
+                ("diagnostics", diagnostics.into()),
+            ])),
+        ])
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, RequestError> {
+        match method {
+            "initialize" => Ok(Self::capabilities()),
+            "shutdown" => {
+                self.shutting_down = true;
+                Ok(Json::Null)
+            },
+            "textDocument/hover" => {
+                let (analysis, offset) = self.document_position(params)?;
+                Ok(match analysis.hover(offset, &self.rom) {
+                    Some((text, span)) => Json::object(vec![
+                        ("contents", Json::object(vec![
+                            ("kind", "markdown".into()),
+                            ("value", text.into()),
+                        ])),
+                        ("range", range_json(&analysis.source, span)),
+                    ]),
+                    None => Json::Null,
+                })
+            },
+            "textDocument/definition" => {
+                let (analysis, offset) = self.document_position(params)?;
+                let uri = Self::uri(params)?;
+                Ok(analysis.symbol_at(offset)
+                    .and_then(|symbol| analysis.definition(&symbol.name))
+                    .map(|definition| location_json(uri, &analysis.source, definition.span))
+                    .unwrap_or(Json::Null))
+            },
+            "textDocument/references" => {
+                let (analysis, offset) = self.document_position(params)?;
+                let uri = Self::uri(params)?;
+                let include_declaration = params.get("context")
+                    .and_then(|context| context.get("includeDeclaration"))
+                    .and_then(|include| include.as_bool())
+                    .unwrap_or(true);
+
+                let locations = match analysis.symbol_at(offset) {
+                    Some(symbol) => analysis.references(&symbol.name, include_declaration)
+                        .into_iter()
+                        .map(|reference| location_json(uri, &analysis.source, reference.span))
+                        .collect(),
+                    None => Vec::new(),
+                };
+                Ok(locations.into())
+            },
+            "textDocument/completion" => {
+                let (analysis, offset) = self.document_position(params)?;
+                let items: Vec<Json> = analysis.completions(offset)
+                    .into_iter()
+                    .map(Self::completion_json)
+                    .collect();
+                Ok(items.into())
+            },
+            "textDocument/documentSymbol" => {
+                let uri = Self::uri(params)?;
+                let analysis = self.document(uri)?;
+                let symbols: Vec<Json> = analysis.outline()
+                    .into_iter()
+                    .map(|outline| {
+                        let children: Vec<Json> = outline.children
+                            .iter()
+                            .map(|child| Self::symbol_json(&analysis.source, child, child.span, SYMBOL_KEY, Vec::new()))
+                            .collect();
+                        Self::symbol_json(&analysis.source, &outline.label, outline.span, SYMBOL_FUNCTION, children)
+                    })
+                    .collect();
+                Ok(symbols.into())
+            },
+            _ => Err(RequestError {
+                code: METHOD_NOT_FOUND,
+                message: format!("Unsupported method: {}", method),
+            }),
+        }
+    }
+
+    fn capabilities() -> Json {
+        Json::object(vec![
+            ("capabilities", Json::object(vec![
+                ("textDocumentSync", SYNC_FULL.into()),
+                ("hoverProvider", true.into()),
+                ("definitionProvider", true.into()),
+                ("referencesProvider", true.into()),
+                ("documentSymbolProvider", true.into()),
+                ("completionProvider", Json::object(vec![
+                    ("triggerCharacters", vec![Json::from("@")].into()),
+                ])),
+            ])),
+            ("serverInfo", Json::object(vec![
+                ("name", "wh02-lsp".into()),
+                ("version", env!("CARGO_PKG_VERSION").into()),
+            ])),
+        ])
+    }
+
+    fn completion_json(completion: Completion) -> Json {
+        match completion {
+            Completion::Mnemonic(keyword) => Json::object(vec![
+                ("label", keyword.to_string().into()),
+                ("kind", COMPLETION_KEYWORD.into()),
+                ("detail", describe(&keyword).into()),
+            ]),
+            Completion::Register { name, prefixed } => Json::object(vec![
+                ("label", format!("@{}", name).into()),
+                ("kind", COMPLETION_VARIABLE.into()),
+                ("detail", "Register".into()),
+                ("filterText", name.clone().into()),
+                ("insertText", if prefixed { name } else { format!("@{}", name) }.into()),
+            ]),
+            Completion::Label(name) => Json::object(vec![
+                ("label", name.into()),
+                ("kind", COMPLETION_REFERENCE.into()),
+                ("detail", "Label".into()),
+            ]),
+        }
+    }
+
+    fn symbol_json(source: &str, symbol: &Symbol, span: Span, kind: u32, children: Vec<Json>) -> Json {
+        Json::object(vec![
+            ("name", symbol.name.clone().into()),
+            ("kind", kind.into()),
+            ("range", range_json(source, span)),
+            ("selectionRange", range_json(source, symbol.span)),
+            ("children", children.into()),
+        ])
+    }
+
+    fn uri(params: &Json) -> Result<&str, RequestError> {
+        params.get("textDocument")
+            .and_then(|document| document.get("uri"))
+            .and_then(|uri| uri.as_str())
+            .ok_or(RequestError {
+                code: INVALID_PARAMS,
+                message: "Expected a textDocument with a uri".to_string(),
+            })
+    }
+
+    fn document(&self, uri: &str) -> Result<&Analysis, RequestError> {
+        self.documents.get(uri).ok_or(RequestError {
+            code: INVALID_PARAMS,
+            message: format!("Document is not open: {}", uri),
+        })
+    }
+
+    // The document a request is about, and the byte offset of its position
+    fn document_position(&self, params: &Json) -> Result<(&Analysis, usize), RequestError> {
+        let analysis = self.document(Self::uri(params)?)?;
+        let position = params.get("position");
+        let line = position.and_then(|position| position.get("line")).and_then(|line| line.as_u64());
+        let character = position.and_then(|position| position.get("character")).and_then(|character| character.as_u64());
+
+        match (line, character) {
+            (Some(line), Some(character)) => Ok((analysis, offset_at(&analysis.source, line, character))),
+            _ => Err(RequestError {
+                code: INVALID_PARAMS,
+                message: "Expected a position with a line and character".to_string(),
+            }),
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,174 @@
+/*
+    Typed views over the concrete syntax tree in `cst`.
+
+    Each view wraps a borrowed `SyntaxNode` of the matching kind and
+    exposes the parts of it by name, so tools can ask a line for its
+    label or an instruction for its operands without walking tokens by
+    hand. The views never copy or drop tokens; the tree stays the single
+    source of truth for the text.
+*/
+
+use wh02_lexer::enumerations::TokenType;
+use wh02_lexer::token::Token;
+
+use crate::cst::{SyntaxElement, SyntaxKind, SyntaxNode};
+use crate::keyword::Keyword;
+use crate::operand;
+use crate::parser_error::ParserError;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Program<'a> {
+    pub node: &'a SyntaxNode,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Line<'a> {
+    pub node: &'a SyntaxNode,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Label<'a> {
+    pub node: &'a SyntaxNode,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction<'a> {
+    pub node: &'a SyntaxNode,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Operand<'a> {
+    pub node: &'a SyntaxNode,
+}
+
+fn cast(node: &SyntaxNode, kind: SyntaxKind) -> Option<&SyntaxNode> {
+    if node.kind == kind {
+        Some(node)
+    } else {
+        None
+    }
+}
+
+impl<'a> Program<'a> {
+    pub fn cast(node: &'a SyntaxNode) -> Option<Program<'a>> {
+        cast(node, SyntaxKind::Program).map(|node| Program { node })
+    }
+
+    pub fn lines(&self) -> Vec<Line<'a>> {
+        self.node.child_nodes().filter_map(Line::cast).collect()
+    }
+}
+
+impl<'a> Line<'a> {
+    pub fn cast(node: &'a SyntaxNode) -> Option<Line<'a>> {
+        cast(node, SyntaxKind::Line).map(|node| Line { node })
+    }
+
+    pub fn label(&self) -> Option<Label<'a>> {
+        self.node.child_nodes().find_map(Label::cast)
+    }
+
+    pub fn instruction(&self) -> Option<Instruction<'a>> {
+        self.node.child_nodes().find_map(Instruction::cast)
+    }
+
+    pub fn comment(&self) -> Option<&'a Token> {
+        self.node.child_tokens().find(|token| token.token_type == TokenType::Comment)
+    }
+
+    // Whitespace before the first label, instruction or comment
+    pub fn indent(&self) -> Option<&'a Token> {
+        match self.node.children.first() {
+            Some(SyntaxElement::Token(token)) if token.token_type == TokenType::Whitespace => Some(token),
+            _ => None,
+        }
+    }
+
+    pub fn newline(&self) -> Option<&'a Token> {
+        self.node.child_tokens().find(|token| token.token_type == TokenType::Newline)
+    }
+
+    // Tokens the lexer could not make sense of
+    pub fn unknown(&self) -> Vec<&'a Token> {
+        self.node.tokens()
+            .into_iter()
+            .filter(|token| token.token_type == TokenType::Unknown)
+            .collect()
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.label().is_none() && self.instruction().is_none() && self.comment().is_none()
+    }
+}
+
+impl<'a> Label<'a> {
+    pub fn cast(node: &'a SyntaxNode) -> Option<Label<'a>> {
+        cast(node, SyntaxKind::Label).map(|node| Label { node })
+    }
+
+    pub fn name(&self) -> &'a Token {
+        self.node.child_tokens()
+            .next()
+            .expect("A label node always starts with its name")
+    }
+
+    pub fn is_local(&self) -> bool {
+        self.name().value.starts_with('.')
+    }
+}
+
+impl<'a> Instruction<'a> {
+    pub fn cast(node: &'a SyntaxNode) -> Option<Instruction<'a>> {
+        cast(node, SyntaxKind::Instruction).map(|node| Instruction { node })
+    }
+
+    pub fn mnemonic(&self) -> &'a Token {
+        self.node.child_tokens()
+            .next()
+            .expect("An instruction node always starts with its mnemonic")
+    }
+
+    // Mnemonics are matched regardless of case, as the parser does by default
+    pub fn keyword(&self) -> Option<Keyword> {
+        let mnemonic = self.mnemonic();
+        Keyword::from_str(&mnemonic.value.to_ascii_uppercase(), mnemonic.span.start).ok()
+    }
+
+    pub fn operands(&self) -> Vec<Operand<'a>> {
+        self.node.child_nodes().filter_map(Operand::cast).collect()
+    }
+
+    pub fn commas(&self) -> Vec<&'a Token> {
+        self.node.child_tokens()
+            .filter(|token| token.token_type == TokenType::Comma)
+            .collect()
+    }
+}
+
+impl<'a> Operand<'a> {
+    pub fn cast(node: &'a SyntaxNode) -> Option<Operand<'a>> {
+        cast(node, SyntaxKind::Operand).map(|node| Operand { node })
+    }
+
+    pub fn token(&self) -> &'a Token {
+        self.node.child_tokens()
+            .next()
+            .expect("An operand node always holds one token")
+    }
+
+    // Identifiers after a mnemonic are label references
+    pub fn is_label(&self) -> bool {
+        matches!(self.token().token_type, TokenType::Word | TokenType::Operation)
+    }
+
+    // Label references keep the name as written; local labels are not
+    // qualified with their global label here
+    pub fn to_operand(&self) -> Result<operand::Operand, ParserError> {
+        let token = self.token();
+        if self.is_label() {
+            return Ok(operand::Operand::label(&token.value));
+        }
+
+        operand::Operand::from_str(&token.value, token.span.start)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,33 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaseStyle {
+    Upper,
+    Lower,
+    Mixed,
+}
+
+impl CaseStyle {
+    // Returns None when the text has no letters to judge, e.g. a bare number
+    pub fn of(text: &str) -> Option<CaseStyle> {
+        let has_upper = text.chars().any(|c| c.is_ascii_uppercase());
+        let has_lower = text.chars().any(|c| c.is_ascii_lowercase());
+
+        match (has_upper, has_lower) {
+            (true, true) => Some(CaseStyle::Mixed),
+            (true, false) => Some(CaseStyle::Upper),
+            (false, true) => Some(CaseStyle::Lower),
+            (false, false) => None,
+        }
+    }
+}
+
+impl fmt::Display for CaseStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CaseStyle::Upper => write!(f, "uppercase"),
+            CaseStyle::Lower => write!(f, "lowercase"),
+            CaseStyle::Mixed => write!(f, "mixed case"),
+        }
+    }
+}
\ No newline at end of file
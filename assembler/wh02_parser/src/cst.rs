@@ -0,0 +1,254 @@
+/*
+    A lossless concrete syntax tree for WH-02 assembly.
+
+    Unlike `Parser`, which drops whitespace and comments on the way to
+    `Expressions`, every token from the lexer ends up in the tree exactly
+    once and in source order. Printing a tree therefore gives back the
+    original text, which is what formatters and rewrites build on.
+
+    A program is a list of lines. Each line holds, in order, any indent,
+    an optional `name:` label, an optional instruction, trailing
+    whitespace, an optional comment and the newline:
+
+        Program
+          Line
+            Whitespace "    "
+            Label        ( Operation "loop", Colon ":" )
+            Whitespace " "
+            Instruction  ( Operation "MOV", Whitespace " ",
+                           Operand ( Location "@B" ), Comma ",",
+                           Operand ( Location "@C" ) )
+            Whitespace "   "
+            Comment "; body"
+            Newline "\n"
+*/
+
+use std::fmt;
+
+use wh02_lexer::enumerations::TokenType;
+use wh02_lexer::span::Span;
+use wh02_lexer::token::Token;
+use wh02_lexer::tokenize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    Program,
+    Line,
+    Label,
+    Instruction,
+    Operand,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(Token),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+fn is_trivia(token: &Token) -> bool {
+    matches!(
+        token.token_type,
+        TokenType::Whitespace | TokenType::Comment | TokenType::Newline | TokenType::EndOfFile
+    )
+}
+
+impl SyntaxNode {
+    pub fn new(kind: SyntaxKind, children: Vec<SyntaxElement>) -> SyntaxNode {
+        SyntaxNode {
+            kind,
+            children,
+        }
+    }
+
+    pub fn child_nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    pub fn child_tokens(&self) -> impl Iterator<Item = &Token> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(_) => None,
+            SyntaxElement::Token(token) => Some(token),
+        })
+    }
+
+    // Every token below this node, in source order
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut tokens = Vec::new();
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+
+        tokens
+    }
+
+    pub fn span(&self) -> Span {
+        let tokens = self.tokens();
+        match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => Span::new(first.span.start, last.span.end),
+            _ => Span::default(),
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.tokens().iter().map(|token| token.value.as_str()).collect()
+    }
+
+    // The token covering a byte offset
+    pub fn token_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens().into_iter().find(|token| token.span.contains(offset))
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+pub fn parse(source: &str) -> SyntaxNode {
+    let tokens = tokenize(source);
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+
+    for token in tokens {
+        let ends_line = token.token_type == TokenType::Newline;
+        line.push(token);
+        if ends_line {
+            lines.push(SyntaxElement::Node(build_line(std::mem::take(&mut line))));
+        }
+    }
+
+    if !line.is_empty() {
+        lines.push(SyntaxElement::Node(build_line(line)));
+    }
+
+    SyntaxNode::new(SyntaxKind::Program, lines)
+}
+
+fn build_line(tokens: Vec<Token>) -> SyntaxNode {
+    let significant: Vec<usize> = (0..tokens.len())
+        .filter(|i| !is_trivia(&tokens[*i]))
+        .collect();
+
+    // `name:` at the start of the line is a label definition
+    let has_label = significant.len() >= 2
+        && matches!(tokens[significant[0]].token_type, TokenType::Operation | TokenType::Word)
+        && tokens[significant[1]].token_type == TokenType::Colon;
+
+    let label = if has_label {
+        Some((significant[0], significant[1]))
+    } else {
+        None
+    };
+
+    let rest = if has_label { &significant[2..] } else { &significant[..] };
+    let instruction = match (rest.first(), rest.last()) {
+        (Some(first), Some(last)) => Some((*first, *last)),
+        _ => None,
+    };
+
+    let mut children = Vec::new();
+    let mut label_children = Vec::new();
+    let mut instruction_children = Vec::new();
+
+    for (i, token) in tokens.into_iter().enumerate() {
+        if let Some((first, last)) = label {
+            if first <= i && i <= last {
+                label_children.push(SyntaxElement::Token(token));
+                if i == last {
+                    children.push(SyntaxElement::Node(SyntaxNode::new(
+                        SyntaxKind::Label,
+                        std::mem::take(&mut label_children),
+                    )));
+                }
+                continue;
+            }
+        }
+
+        if let Some((first, last)) = instruction {
+            if first <= i && i <= last {
+                if i == first || is_trivia(&token) || token.token_type == TokenType::Comma {
+                    instruction_children.push(SyntaxElement::Token(token));
+                } else {
+                    instruction_children.push(SyntaxElement::Node(SyntaxNode::new(
+                        SyntaxKind::Operand,
+                        vec![SyntaxElement::Token(token)],
+                    )));
+                }
+
+                if i == last {
+                    children.push(SyntaxElement::Node(SyntaxNode::new(
+                        SyntaxKind::Instruction,
+                        std::mem::take(&mut instruction_children),
+                    )));
+                }
+                continue;
+            }
+        }
+
+        children.push(SyntaxElement::Token(token));
+    }
+
+    SyntaxNode::new(SyntaxKind::Line, children)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trips(source: &str) {
+        assert_eq!(parse(source).text(), source);
+    }
+
+    #[test]
+    fn round_trips_comments_and_blank_lines() {
+        assert_round_trips("; Counts down from 5\n\nmain:\n    MOV #05, @A   ; start\n\n\n.loop: JMP .loop\n    ; done\nHLT\n");
+    }
+
+    #[test]
+    fn round_trips_crlf() {
+        assert_round_trips("MOV #01, @A\r\n\r\n    HLT ; stop\r\n");
+    }
+
+    #[test]
+    fn round_trips_without_final_newline() {
+        assert_round_trips("HLT");
+        assert_round_trips("   ");
+        assert_round_trips("");
+    }
+
+    #[test]
+    fn round_trips_syntax_errors() {
+        assert_round_trips("MOV #1G, @A\nJMP\nMOV @A,, @B\n: HLT\n%% ? !\nNOP ; ünïcode\n");
+    }
+
+    #[test]
+    fn round_trips_example_program() {
+        assert_round_trips(include_str!("../../../programs/fib.asm"));
+    }
+
+    #[test]
+    fn tokens_are_in_source_order() {
+        let source = "loop: MOV @B, @C ; body\n\n  JMP loop\n";
+        let tree = parse(source);
+
+        let mut offset = 0;
+        for token in tree.tokens() {
+            assert_eq!(token.span.start.offset, offset, "{}", token);
+            offset = token.span.end.offset;
+        }
+        assert_eq!(offset, source.len());
+    }
+}
\ No newline at end of file
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use crate::keyword::Keyword;
 use crate::operand::Operand;
 use crate::parser_error::ParserError;
@@ -26,9 +28,16 @@ impl Expressions {
         match keyword {
             Keyword::HLT => Ok(()),
             Keyword::NOP => Ok(()),
+            Keyword::RET => Ok(()),
+            Keyword::ADD => Ok(()),
+            Keyword::SUB => Ok(()),
             _ => Err(ParserError {
                 position: Default::default(),
-                message: format!("\n\t==> Invalid keyword: {}. Expected one of {:#?}", keyword, vec![Keyword::HLT, Keyword::NOP]),
+                message: format!(
+                    "\n\t==> Invalid keyword: {}. Expected one of {:#?}",
+                    keyword,
+                    vec![Keyword::HLT, Keyword::NOP, Keyword::RET, Keyword::ADD, Keyword::SUB],
+                ),
             }),
         }
     }
@@ -38,9 +47,31 @@ impl Expressions {
             Keyword::DEF => Ok(()),
             Keyword::START => Ok(()),
             Keyword::JMP => Ok(()),
+            Keyword::ORG => Ok(()),
+            Keyword::SECTION => Ok(()),
+            Keyword::CALL => Ok(()),
+            Keyword::PUSH => Ok(()),
+            Keyword::POP => Ok(()),
+            Keyword::SHL => Ok(()),
+            Keyword::SHR => Ok(()),
             _ => Err(ParserError {
                 position: Default::default(),
-                message: format!("\n\t==> Invalid keyword: {}. Expected one of {:#?}", keyword, vec![Keyword::DEF, Keyword::START]),
+                message: format!(
+                    "\n\t==> Invalid keyword: {}. Expected one of {:#?}",
+                    keyword,
+                    vec![
+                        Keyword::DEF,
+                        Keyword::START,
+                        Keyword::JMP,
+                        Keyword::ORG,
+                        Keyword::SECTION,
+                        Keyword::CALL,
+                        Keyword::PUSH,
+                        Keyword::POP,
+                        Keyword::SHL,
+                        Keyword::SHR,
+                    ],
+                ),
             }),
         }
     }
@@ -54,4 +85,31 @@ impl Expressions {
             }),
         }
     }
+}
+
+// Label references print as written, without their `.` indicator
+fn write_operand(f: &mut fmt::Formatter, operand: &Operand) -> fmt::Result {
+    if operand.is_label() {
+        write!(f, "{}", operand.value)
+    } else {
+        write!(f, "{}", operand)
+    }
+}
+
+impl fmt::Display for Expressions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expressions::NoOperandExpression { keyword } => write!(f, "{}", keyword),
+            Expressions::UnaryExpression { keyword, operand } => {
+                write!(f, "{} ", keyword)?;
+                write_operand(f, operand)
+            },
+            Expressions::BinaryExpression { keyword, operand1, operand2, .. } => {
+                write!(f, "{} ", keyword)?;
+                write_operand(f, operand1)?;
+                write!(f, ", ")?;
+                write_operand(f, operand2)
+            },
+        }
+    }
 }
\ No newline at end of file
@@ -11,9 +11,40 @@ pub enum Keyword {
     DEF,
     START,
     JMP,
+    ORG,
+    SECTION,
+    CALL,
+    RET,
+    PUSH,
+    POP,
+    ADD,
+    SUB,
+    SHL,
+    SHR,
 }
 
 impl Keyword {
+    pub fn all() -> Vec<Keyword> {
+        vec![
+            Keyword::MOV,
+            Keyword::HLT,
+            Keyword::NOP,
+            Keyword::DEF,
+            Keyword::START,
+            Keyword::JMP,
+            Keyword::ORG,
+            Keyword::SECTION,
+            Keyword::CALL,
+            Keyword::RET,
+            Keyword::PUSH,
+            Keyword::POP,
+            Keyword::ADD,
+            Keyword::SUB,
+            Keyword::SHL,
+            Keyword::SHR,
+        ]
+    }
+
     pub fn from_str(keyword: &str, position: Position) -> Result<Keyword, ParserError> {
         match keyword {
             "MOV" => Ok(Keyword::MOV),
@@ -22,6 +53,16 @@ impl Keyword {
             "DEF" => Ok(Keyword::DEF),
             "START" => Ok(Keyword::START),
             "JMP" => Ok(Keyword::JMP),
+            "ORG" => Ok(Keyword::ORG),
+            "SECTION" => Ok(Keyword::SECTION),
+            "CALL" => Ok(Keyword::CALL),
+            "RET" => Ok(Keyword::RET),
+            "PUSH" => Ok(Keyword::PUSH),
+            "POP" => Ok(Keyword::POP),
+            "ADD" => Ok(Keyword::ADD),
+            "SUB" => Ok(Keyword::SUB),
+            "SHL" => Ok(Keyword::SHL),
+            "SHR" => Ok(Keyword::SHR),
             _ => Err(ParserError {
                 position,
                 message: format!("Invalid keyword: {}", keyword),
@@ -39,6 +80,16 @@ impl fmt::Display for Keyword {
             Keyword::DEF => write!(f, "DEF"),
             Keyword::START => write!(f, "START"),
             Keyword::JMP => write!(f, "JMP"),
+            Keyword::ORG => write!(f, "ORG"),
+            Keyword::SECTION => write!(f, "SECTION"),
+            Keyword::CALL => write!(f, "CALL"),
+            Keyword::RET => write!(f, "RET"),
+            Keyword::PUSH => write!(f, "PUSH"),
+            Keyword::POP => write!(f, "POP"),
+            Keyword::ADD => write!(f, "ADD"),
+            Keyword::SUB => write!(f, "SUB"),
+            Keyword::SHL => write!(f, "SHL"),
+            Keyword::SHR => write!(f, "SHR"),
         }
     }
 }
\ No newline at end of file
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 
 use wh02_lexer::Lexer;
+use wh02_lexer::position::Position;
 use wh02_lexer::token::Token;
 use wh02_lexer::enumerations::TokenType;
 use wh02_lexer::lexer_error::LexerError;
@@ -9,18 +10,46 @@ pub mod keyword;
 pub mod expressions;
 pub mod operand;
 pub mod parser_error;
+pub mod parser_warning;
+pub mod case_style;
+pub mod cst;
+pub mod ast;
 
+use crate::case_style::CaseStyle;
 use crate::expressions::Expressions;
 use crate::parser_error::ParserError;
+use crate::parser_warning::ParserWarning;
 use crate::keyword::Keyword;
 use crate::operand::Operand;
 
+// Local labels start with a `.` and belong to the most recent global
+// label, so `.loop` after `main:` is stored as `main.loop`. Before any
+// global label is defined, `.name` is treated as the global `name`.
+pub fn qualify_label(global_label: Option<&str>, name: &str) -> String {
+    match name.strip_prefix('.') {
+        Some(local) => match global_label {
+            Some(global) => format!("{}.{}", global, local),
+            None => local.to_string(),
+        },
+        None => name.to_string(),
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Parser<'a> {
     pub lexer: Lexer<'a>,
     pub expressions: Vec<Expressions>,
+    // Where each expression starts in the source, in the same order
+    pub positions: Vec<Position>,
     pub errors: Vec<ParserError>,
+    pub warnings: Vec<ParserWarning>,
     pub has_next: bool,
+    // When set, mnemonics, directives and register names match regardless
+    // of case. Labels are always case-sensitive.
+    pub case_insensitive: bool,
+    case_style: Option<CaseStyle>,
+    // The most recent global label, which scopes local `.name` labels
+    global_label: Option<String>,
 }
 
 impl<'a> Parser<'a> {
@@ -28,8 +57,13 @@ impl<'a> Parser<'a> {
         Parser {
             lexer,
             expressions: Vec::new(),
+            positions: Vec::new(),
             errors: Vec::new(),
+            warnings: Vec::new(),
             has_next: true,
+            case_insensitive: true,
+            case_style: None,
+            global_label: None,
         }
     }
 
@@ -44,21 +78,14 @@ impl<'a> Parser<'a> {
                 }
             }
         }
-
-
-        if self.errors.len() > 0 {
-            for error in &self.errors {
-                println!("ERROR: {}", error);
-            }
-        }
     }
 
-    fn validate_token_types(&mut self, tokens: &Vec<Token>, types: Vec<Vec<TokenType>>) {
+    fn validate_token_types(&mut self, tokens: &[Token], types: Vec<Vec<TokenType>>) {
         for i in 0..tokens.len() {
             if !types[i].contains(&tokens[i].token_type) {
                 self.errors.push(ParserError {
                     message: format!("Invalid token type {}, expected one of {:#?}", tokens[i].token_type, types[i]),
-                    position: tokens[i].start_position,
+                    position: tokens[i].span.start,
                 })
             }
         }
@@ -66,7 +93,7 @@ impl<'a> Parser<'a> {
 
     fn parse_no_operand(&mut self, toks: Vec<Token>) -> Result<(), ParserError> {
 
-        let keyword = Keyword::from_str(&toks[0].value, toks[0].start_position)?;
+        let keyword = Keyword::from_str(&toks[0].value, toks[0].span.start)?;
 
         let token_types = vec![
             vec![TokenType::Operation],
@@ -81,7 +108,7 @@ impl<'a> Parser<'a> {
         match result {
             Ok(_) => {},
             Err(mut error) => {
-                error.position = toks[0].start_position;
+                error.position = toks[0].span.start;
                 self.errors.push(error);
             }
         }
@@ -89,17 +116,25 @@ impl<'a> Parser<'a> {
         self.expressions.push(Expressions::NoOperandExpression {
             keyword,
         });
+        self.positions.push(toks[0].span.start);
 
         Ok(())
     }
 
     fn parse_unary(&mut self, toks: Vec<Token>) -> Result<(), ParserError> {
-        let keyword = Keyword::from_str(&toks[0].value, toks[0].start_position)?;
+        let keyword = Keyword::from_str(&toks[0].value, toks[0].span.start)?;
 
         let keyword_operands: HashMap<Keyword, Vec<TokenType>> = HashMap::from([
             (Keyword::DEF, vec![TokenType::Word]),
             (Keyword::START, vec![TokenType::Address]),
-            (Keyword::JMP, vec![TokenType::Address, TokenType::Word]),
+            (Keyword::JMP, vec![TokenType::Address, TokenType::Location, TokenType::Word]),
+            (Keyword::ORG, vec![TokenType::Address]),
+            (Keyword::SECTION, vec![TokenType::Word]),
+            (Keyword::CALL, vec![TokenType::Address, TokenType::Word]),
+            (Keyword::PUSH, vec![TokenType::Location]),
+            (Keyword::POP, vec![TokenType::Location]),
+            (Keyword::SHL, vec![TokenType::Hex]),
+            (Keyword::SHR, vec![TokenType::Hex]),
         ]);
 
         let token_types = vec![
@@ -112,35 +147,66 @@ impl<'a> Parser<'a> {
 
         let result = Expressions::validate_unary_keyword(keyword.clone());
 
-        if keyword == Keyword::START && self.expressions.len() > 0 {
+        if keyword == Keyword::START && !self.expressions.is_empty() {
             self.errors.push(ParserError {
                 message: "START instruction must occur first.".to_string(),
-                position: toks[0].start_position,
+                position: toks[0].span.start,
             })
         } else if keyword == Keyword::START {
 
         }
 
+        if keyword == Keyword::POP && toks[1].value == "@ACC" {
+            self.errors.push(ParserError {
+                message: "Invalid destination provided: @ACC. POP cannot write to @ACC".to_string(),
+                position: toks[1].span.start,
+            });
+        }
+
+        if keyword == Keyword::JMP && (toks[1].value == "@O1" || toks[1].value == "@O2") {
+            self.errors.push(ParserError {
+                message: format!("Invalid source provided: {}. JMP can only use @A, @B, @C or @ACC", toks[1].value),
+                position: toks[1].span.start,
+            });
+        }
+
         match result {
             Ok(_) => {},
             Err(mut error) => {
-                error.position = toks[0].start_position;
+                error.position = toks[0].span.start;
                 self.errors.push(error);
             }
         }
 
-        let operand = toks[1].value.to_string();
+        // Section names are not labels, so local names are not qualified
+        let operand = if keyword == Keyword::SECTION {
+            Operand::label(&toks[1].value)
+        } else {
+            self.parse_operand(&toks[1], keyword == Keyword::DEF)?
+        };
+
+        // The ALU shifts by 3 bits' worth of places, and 0 does nothing
+        if keyword == Keyword::SHL || keyword == Keyword::SHR {
+            let places = usize::from_str_radix(&operand.value, 16).unwrap_or(0);
+            if !(1..=7).contains(&places) {
+                self.errors.push(ParserError {
+                    message: format!("Invalid shift provided: {}. {} shifts by #01 to #07 places", toks[1].value, keyword),
+                    position: toks[1].span.start,
+                });
+            }
+        }
 
         self.expressions.push(Expressions::UnaryExpression {
             keyword,
-            operand: Operand::from_str(&operand, toks[1].start_position)?,
+            operand,
         });
+        self.positions.push(toks[0].span.start);
 
         Ok(())
     }
 
     fn parse_binary(&mut self, toks: Vec<Token>) -> Result<(), ParserError> {
-        let keyword = Keyword::from_str(&toks[0].value, toks[0].start_position)?;
+        let keyword = Keyword::from_str(&toks[0].value, toks[0].span.start)?;
 
         let keyword_operands: HashMap<Keyword, Vec<TokenType>> = HashMap::from([
             (Keyword::MOV, vec![TokenType::Hex, TokenType::Address, TokenType::Location, TokenType::Word]),
@@ -163,27 +229,87 @@ impl<'a> Parser<'a> {
         match result {
             Ok(_) => {},
             Err(mut error) => {
-                error.position = toks[0].start_position;
+                error.position = toks[0].span.start;
                 self.errors.push(error);
             }
         }
 
-        let operand1 = toks[1].value.to_string();
-        let operand2 = toks[3].value.to_string();
+        self.validate_second_operand(&keyword, &toks[1], &toks[3]);
 
-        self.validate_second_operand(&keyword, &operand1, &operand2);
+        let operand1 = self.parse_operand(&toks[1], false)?;
+        let operand2 = self.parse_operand(&toks[3], false)?;
 
         self.expressions.push(Expressions::BinaryExpression {
             keyword,
-            operand1: Operand::from_str(&operand1, toks[1].start_position)?,
+            operand1,
             comma: toks[2].value.to_string(),
-            operand2: Operand::from_str(&operand2, toks[3].start_position)?,
+            operand2,
         });
+        self.positions.push(toks[0].span.start);
 
         Ok(())
     }
 
-    fn validate_second_operand(&mut self, keyword: &Keyword, operand1: &String, operand2: &String) {
+    fn parse_operand(&mut self, token: &Token, defining: bool) -> Result<Operand, ParserError> {
+        if token.token_type == TokenType::Word {
+            let label = self.qualify_label(&token.value, defining, token.span.start)?;
+            return Ok(Operand::label(&label));
+        }
+
+        Operand::from_str(&token.value, token.span.start)
+    }
+
+    fn qualify_label(&mut self, name: &str, defining: bool, position: Position) -> Result<String, ParserError> {
+        if name.starts_with('.') {
+            if name.len() == 1 || name.starts_with("..") {
+                return Err(ParserError {
+                    position,
+                    message: format!("Invalid label: {}. Expected a name after the `.`", name),
+                });
+            }
+
+            return Ok(qualify_label(self.global_label.as_deref(), name));
+        }
+
+        if defining {
+            if Keyword::from_str(&name.to_ascii_uppercase(), position).is_ok() {
+                return Err(ParserError {
+                    position,
+                    message: format!("Invalid label: {}. Labels cannot use an instruction name", name),
+                });
+            }
+
+            self.global_label = Some(name.to_string());
+        }
+
+        Ok(name.to_string())
+    }
+
+    fn parse_label_definition(&mut self, token: &Token) -> Result<(), ParserError> {
+        if token.token_type != TokenType::Operation && token.token_type != TokenType::Word {
+            return Err(ParserError {
+                position: token.span.start,
+                message: format!("Invalid label: {}. Expected an identifier before `:`", token.value),
+            });
+        }
+
+        let mut label = token.clone();
+        label.token_type = TokenType::Word;
+        let operand = self.parse_operand(&label, true)?;
+
+        self.expressions.push(Expressions::UnaryExpression {
+            keyword: Keyword::DEF,
+            operand,
+        });
+        self.positions.push(token.span.start);
+
+        Ok(())
+    }
+
+    fn validate_second_operand(&mut self, keyword: &Keyword, source: &Token, destination: &Token) {
+        let operand1 = &source.value;
+        let operand2 = &destination.value;
+
         if keyword == &Keyword::MOV {
             let valid_destinations = vec![
                 "@A",
@@ -193,17 +319,17 @@ impl<'a> Parser<'a> {
                 "@O2",
             ];
 
-            let is_addr = operand2.starts_with('$');
+            let is_addr = operand2.starts_with('$') || destination.token_type == TokenType::Word;
 
             if !is_addr && !valid_destinations.contains(&operand2.as_str()) {
                 self.errors.push(ParserError {
                     message: format!("Invalid destination provided: {}. Expected one of {:#?}", operand2, valid_destinations),
-                    position: self.lexer.position,
+                    position: destination.span.start,
                 });
             } else if !is_addr && operand1 == operand2 {
                 self.errors.push(ParserError {
                     message: format!("Invalid destination provided: {}. Destination cannot be the same as the source", operand1),
-                    position: self.lexer.position,
+                    position: source.span.start,
                 });
             }
         }
@@ -211,7 +337,7 @@ impl<'a> Parser<'a> {
 
     fn parse_line(&mut self, line: Vec<Token>) -> Result<(), ParserError> {
         let plain_tokens: Vec<Token> = line.clone();
-        let toks: Vec<Token> = plain_tokens
+        let mut toks: Vec<Token> = plain_tokens
             .iter()
             .filter(
                 |x|
@@ -222,6 +348,26 @@ impl<'a> Parser<'a> {
             .cloned()
             .collect();
 
+        // `name:` defines a label, optionally followed by an instruction
+        if toks.len() >= 2 && toks[1].token_type == TokenType::Colon {
+            self.parse_label_definition(&toks[0])?;
+            toks.drain(0..2);
+        }
+
+        if toks.iter().all(|x| x.token_type == TokenType::Newline) {
+            // Blank line, or a line holding only a comment or label
+            return Ok(());
+        }
+
+        // Identifiers after the mnemonic are label references
+        for tok in toks.iter_mut().skip(1) {
+            if tok.token_type == TokenType::Operation {
+                tok.token_type = TokenType::Word;
+            }
+        }
+
+        self.normalize_case(&mut toks[0]);
+
         match toks.len() {
             0 => {
                 // End of File
@@ -268,7 +414,7 @@ impl<'a> Parser<'a> {
             _ => {
                 self.errors.push(ParserError {
                     message: format!("{:#?}\nInvalid expression length. Expected 2, 3, or 5. Got: {}", toks, toks.len()),
-                    position: toks[0].start_position,
+                    position: toks[0].span.start,
                 });
             }
         }
@@ -304,7 +450,7 @@ impl<'a> Parser<'a> {
 
         if !valid_locations.contains(&token.value.as_str()) {
             return Err(ParserError {
-                position: token.start_position,
+                position: token.span.start,
                 message: format!("Invalid location provided: {}. Expected one of {:#?}", token.value, valid_locations),
             })
         }
@@ -312,6 +458,43 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
+    fn normalize_case(&mut self, token: &mut Token) {
+        if !self.case_insensitive {
+            return;
+        }
+
+        if token.token_type != TokenType::Operation && token.token_type != TokenType::Location {
+            return;
+        }
+
+        let style = match CaseStyle::of(&token.value) {
+            Some(style) => style,
+            None => return,
+        };
+
+        if style == CaseStyle::Mixed {
+            self.warnings.push(ParserWarning {
+                position: token.span.start,
+                message: format!("Mixed-case {} found. Prefer {} or {}", token.value, token.value.to_ascii_uppercase(), token.value.to_ascii_lowercase()),
+            });
+        } else {
+            match self.case_style {
+                Some(expected) if expected != style => {
+                    self.warnings.push(ParserWarning {
+                        position: token.span.start,
+                        message: format!("{} is {}, but earlier code is {}", token.value, style, expected),
+                    });
+                },
+                Some(_) => {},
+                None => {
+                    self.case_style = Some(style);
+                }
+            }
+        }
+
+        token.value = token.value.to_ascii_uppercase();
+    }
+
     fn get_line(&mut self) -> Result<Vec<Token>, ParserError> {
         let mut end = false;
         let mut line: Vec<Token> = Vec::new();
@@ -319,7 +502,11 @@ impl<'a> Parser<'a> {
             let token: Result<Token, LexerError> = self.lexer.lex();
 
             match token {
-                Ok (token) => {
+                Ok (mut token) => {
+                    if token.token_type == TokenType::Location {
+                        self.normalize_case(&mut token);
+                    }
+
                     if token.token_type == TokenType::EndOfFile {
                         self.has_next = false;
                         end = true;
@@ -351,4 +538,36 @@ impl<'a> Parser<'a> {
 
         Ok(line)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(source: &str) -> Vec<(String, u32)> {
+        let mut parser = Parser::new(Lexer::new(source));
+        parser.parse_all();
+        parser.errors
+            .into_iter()
+            .map(|error| (error.message, error.position.line))
+            .collect()
+    }
+
+    #[test]
+    fn labels_cannot_use_instruction_names() {
+        assert_eq!(errors("NOP\nsub: HLT\n"), vec![
+            ("Invalid label: sub. Labels cannot use an instruction name".to_string(), 1),
+        ]);
+        assert_eq!(errors("DEF add\nHLT\n"), vec![
+            ("Invalid label: add. Labels cannot use an instruction name".to_string(), 0),
+        ]);
+        assert_eq!(errors("Shl:\nHLT\n"), vec![
+            ("Invalid label: Shl. Labels cannot use an instruction name".to_string(), 0),
+        ]);
+    }
+
+    #[test]
+    fn labels_can_contain_instruction_names() {
+        assert_eq!(errors("subtract: HLT\nmain:\n.sub: JMP .sub\n"), vec![]);
+    }
 }
\ No newline at end of file
@@ -26,13 +26,29 @@ impl Operand {
 
     pub fn from_str(keyword: &str, position: Position) -> Result<Operand, ParserError> {
         let indicator = Self::get_indicator(keyword, position)?;
-        let value = keyword[1..].to_string();
+        let mut value = keyword[1..].to_string();
+
+        // Normalize hex values to uppercase
+        if indicator == '#' || indicator == '$' {
+            value = value.to_ascii_uppercase();
+        }
 
         Ok(Operand {
             indicator,
             value,
         })
     }
+
+    pub fn label(name: &str) -> Operand {
+        Operand {
+            indicator: '.',
+            value: name.to_string(),
+        }
+    }
+
+    pub fn is_label(&self) -> bool {
+        self.indicator == '.'
+    }
 }
 
 impl fmt::Display for Operand {
@@ -0,0 +1,15 @@
+use core::fmt;
+
+use wh02_lexer::position::Position;
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct ParserWarning {
+    pub position: Position,
+    pub message: String,
+}
+
+impl fmt::Display for ParserWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ParserWarning at {} {}", self.position, self.message)
+    }
+}
\ No newline at end of file
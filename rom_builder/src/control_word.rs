@@ -0,0 +1,381 @@
+/*
+    The bits of a control word, as the control ROM drives them.
+
+    Each field is a run of bits wired to one part of the CPU. Fields are
+    named after the tunnels they drive in WH02.circ, apart from the
+    register selects, which go through decoders:
+
+    bits  0-3   output select (which register drives the bus)
+    bits  4-7   input select (which register loads from the bus)
+    bit   8     output enable
+    bit   9     input enable
+    bit  10     ENABLE_PRGC (count the program counter up)
+    bits 11-12  SCT (which ALU result ACC latches)
+    bits 13-15  SFT (how many places the ALU shifts)
+    bit  16     HLT
+    bit  17     RST_STEPCOUNTER
+    bit  18     STACK_INC
+    bit  19     STACK_DEC
+
+    `ControlWord` builds words from these fields, so a word can only set
+    bits that are wired to something.
+*/
+
+use core::fmt;
+
+use crate::control_word_error::ControlWordError;
+
+// A run of bits in the control word
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub offset: u32,
+    pub width: u32,
+}
+
+impl Field {
+    pub const fn mask(&self) -> u32 {
+        ((1 << self.width) - 1) << self.offset
+    }
+
+    pub const fn get(&self, bits: u32) -> u32 {
+        (bits & self.mask()) >> self.offset
+    }
+}
+
+pub const OUTPUT_SELECT: Field = Field { name: "OUTPUT_SELECT", offset: 0, width: 4 };
+pub const INPUT_SELECT: Field = Field { name: "INPUT_SELECT", offset: 4, width: 4 };
+pub const OUTPUT_ENABLE: Field = Field { name: "OUTPUT_ENABLE", offset: 8, width: 1 };
+pub const INPUT_ENABLE: Field = Field { name: "INPUT_ENABLE", offset: 9, width: 1 };
+pub const ENABLE_PRGC: Field = Field { name: "ENABLE_PRGC", offset: 10, width: 1 };
+pub const ALU_SELECT: Field = Field { name: "SCT", offset: 11, width: 2 };
+pub const SHIFT: Field = Field { name: "SFT", offset: 13, width: 3 };
+pub const HLT: Field = Field { name: "HLT", offset: 16, width: 1 };
+pub const RST_STEPCOUNTER: Field = Field { name: "RST_STEPCOUNTER", offset: 17, width: 1 };
+pub const STACK_INC: Field = Field { name: "STACK_INC", offset: 18, width: 1 };
+pub const STACK_DEC: Field = Field { name: "STACK_DEC", offset: 19, width: 1 };
+
+pub const FIELDS: [Field; 11] = [
+    OUTPUT_SELECT,
+    INPUT_SELECT,
+    OUTPUT_ENABLE,
+    INPUT_ENABLE,
+    ENABLE_PRGC,
+    ALU_SELECT,
+    SHIFT,
+    HLT,
+    RST_STEPCOUNTER,
+    STACK_INC,
+    STACK_DEC,
+];
+
+// Registers that can drive the bus, in output select order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    A,
+    B,
+    C,
+    ACC,
+    O1,
+    O2,
+    PRGC,
+    MAR,
+    INST,
+    RAM,
+    STK,
+}
+
+impl Source {
+    pub fn all() -> Vec<Source> {
+        vec![
+            Source::A,
+            Source::B,
+            Source::C,
+            Source::ACC,
+            Source::O1,
+            Source::O2,
+            Source::PRGC,
+            Source::MAR,
+            Source::INST,
+            Source::RAM,
+            Source::STK,
+        ]
+    }
+
+    // The tunnel in WH02.circ that the output select decoder drives
+    pub fn tunnel(&self) -> &'static str {
+        match self {
+            Source::A => "OUTPUT_A",
+            Source::B => "OUTPUT_B",
+            Source::C => "OUTPUT_C",
+            Source::ACC => "OUTPUT_ACC",
+            Source::O1 => "OUTPUT_O1",
+            Source::O2 => "OUTPUT_O2",
+            Source::PRGC => "OUTPUT_PRGC",
+            Source::MAR => "OUTPUT_MEM_ADD",
+            Source::INST => "OUTPUT_INST",
+            Source::RAM => "OUTPUT_RAM",
+            Source::STK => "OUTPUT_STACK",
+        }
+    }
+}
+
+// Registers that can load from the bus, in input select order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    A,
+    B,
+    C,
+    O1,
+    O2,
+    FLG,
+    PRGC,
+    MAR,
+    INST,
+    RAM,
+    STK,
+    // Latches the selected ALU result
+    ACC,
+}
+
+impl Target {
+    pub fn all() -> Vec<Target> {
+        vec![
+            Target::A,
+            Target::B,
+            Target::C,
+            Target::O1,
+            Target::O2,
+            Target::FLG,
+            Target::PRGC,
+            Target::MAR,
+            Target::INST,
+            Target::RAM,
+            Target::STK,
+            Target::ACC,
+        ]
+    }
+
+    // The tunnel in WH02.circ that the input select decoder drives
+    pub fn tunnel(&self) -> &'static str {
+        match self {
+            Target::A => "WRITE_A",
+            Target::B => "WRITE_B",
+            Target::C => "WRITE_C",
+            Target::O1 => "WRITE_O1",
+            Target::O2 => "WRITE_O2",
+            Target::FLG => "WRITE_FLAGS",
+            Target::PRGC => "WRITE_PRGC",
+            Target::MAR => "WRITE_MEM_ADD",
+            Target::INST => "WRITE_INST",
+            Target::RAM => "WRITE_RAM",
+            Target::STK => "WRITE_STACK",
+            Target::ACC => "WRITE_ACC",
+        }
+    }
+}
+
+// The ALU results ACC can latch, in SCT order. Shifts move A by SFT places.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AluOp {
+    Add,
+    Sub,
+    Shl,
+    Shr,
+}
+
+impl AluOp {
+    pub fn all() -> Vec<AluOp> {
+        vec![AluOp::Add, AluOp::Sub, AluOp::Shl, AluOp::Shr]
+    }
+}
+
+// One step of microcode. Every field starts cleared, which reads nothing,
+// writes nothing and selects ADD.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ControlWord {
+    bits: u32,
+}
+
+impl ControlWord {
+    pub const fn new() -> ControlWord {
+        ControlWord {
+            bits: 0,
+        }
+    }
+
+    // Sets a field that has not been set yet
+    const fn set(self, field: Field, value: u32) -> ControlWord {
+        assert!(value < 1 << field.width, "Value does not fit in its control word field");
+        assert!(self.bits & field.mask() == 0, "Control word field set twice");
+
+        ControlWord {
+            bits: self.bits | value << field.offset,
+        }
+    }
+
+    pub const fn read(self, source: Source) -> ControlWord {
+        self.set(OUTPUT_SELECT, source as u32).set(OUTPUT_ENABLE, 1)
+    }
+
+    pub const fn write(self, target: Target) -> ControlWord {
+        self.set(INPUT_SELECT, target as u32).set(INPUT_ENABLE, 1)
+    }
+
+    pub const fn enable_program_counter(self) -> ControlWord {
+        self.set(ENABLE_PRGC, 1)
+    }
+
+    pub const fn alu(self, op: AluOp) -> ControlWord {
+        self.set(ALU_SELECT, op as u32)
+    }
+
+    pub const fn shift(self, places: u32) -> ControlWord {
+        self.set(SHIFT, places)
+    }
+
+    pub const fn halt(self) -> ControlWord {
+        self.set(HLT, 1)
+    }
+
+    pub const fn reset_step_counter(self) -> ControlWord {
+        self.set(RST_STEPCOUNTER, 1)
+    }
+
+    pub const fn stack_inc(self) -> ControlWord {
+        self.set(STACK_INC, 1)
+    }
+
+    pub const fn stack_dec(self) -> ControlWord {
+        self.set(STACK_DEC, 1)
+    }
+
+    pub const fn bits(self) -> u32 {
+        self.bits
+    }
+
+    // Checks a word read back from a ROM against the bit allocation
+    pub fn from_bits(bits: u32) -> Result<ControlWord, ControlWordError> {
+        let word = ControlWord {
+            bits,
+        };
+
+        let allocated = FIELDS.iter().fold(0, |mask, field| mask | field.mask());
+        if bits & !allocated != 0 {
+            return Err(word.error(format!("sets unallocated bits {:#010x}", bits & !allocated)));
+        }
+
+        if OUTPUT_ENABLE.get(bits) == 1 && word.source().is_none() {
+            return Err(word.error(format!("selects output {}, which is not a register", OUTPUT_SELECT.get(bits))));
+        }
+
+        if INPUT_ENABLE.get(bits) == 1 && word.target().is_none() {
+            return Err(word.error(format!("selects input {}, which is not a register", INPUT_SELECT.get(bits))));
+        }
+
+        if OUTPUT_ENABLE.get(bits) == 0 && OUTPUT_SELECT.get(bits) != 0 {
+            return Err(word.error("selects an output without enabling it".to_string()));
+        }
+
+        if INPUT_ENABLE.get(bits) == 0 && INPUT_SELECT.get(bits) != 0 {
+            return Err(word.error("selects an input without enabling it".to_string()));
+        }
+
+        if word.stack_incs() && word.stack_decs() {
+            return Err(word.error("moves the stack pointer up and down at once".to_string()));
+        }
+
+        Ok(word)
+    }
+
+    fn error(&self, message: String) -> ControlWordError {
+        ControlWordError {
+            message: format!("Control word {:#010x} {}", self.bits, message),
+        }
+    }
+
+    pub fn source(&self) -> Option<Source> {
+        if OUTPUT_ENABLE.get(self.bits) == 0 {
+            return None;
+        }
+
+        Source::all().get(OUTPUT_SELECT.get(self.bits) as usize).copied()
+    }
+
+    pub fn target(&self) -> Option<Target> {
+        if INPUT_ENABLE.get(self.bits) == 0 {
+            return None;
+        }
+
+        Target::all().get(INPUT_SELECT.get(self.bits) as usize).copied()
+    }
+
+    pub fn enables_program_counter(&self) -> bool {
+        ENABLE_PRGC.get(self.bits) == 1
+    }
+
+    pub fn alu_op(&self) -> AluOp {
+        AluOp::all()[ALU_SELECT.get(self.bits) as usize]
+    }
+
+    pub fn shift_places(&self) -> u32 {
+        SHIFT.get(self.bits)
+    }
+
+    pub fn halts(&self) -> bool {
+        HLT.get(self.bits) == 1
+    }
+
+    pub fn resets_step_counter(&self) -> bool {
+        RST_STEPCOUNTER.get(self.bits) == 1
+    }
+
+    pub fn stack_incs(&self) -> bool {
+        STACK_INC.get(self.bits) == 1
+    }
+
+    pub fn stack_decs(&self) -> bool {
+        STACK_DEC.get(self.bits) == 1
+    }
+}
+
+// Written like the microcode: `PRGC -> MAR, ENABLE_PRGC`
+impl fmt::Display for ControlWord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        match (self.source(), self.target()) {
+            (None, None) => {},
+            (source, target) => parts.push(format!(
+                "{} -> {}",
+                source.map(|source| format!("{:?}", source)).unwrap_or("NULL".to_string()),
+                target.map(|target| format!("{:?}", target)).unwrap_or("NULL".to_string()),
+            )),
+        }
+
+        if self.enables_program_counter() {
+            parts.push(ENABLE_PRGC.name.to_string());
+        }
+        if self.alu_op() != AluOp::Add {
+            parts.push(format!("{}={:?}", ALU_SELECT.name, self.alu_op()));
+        }
+        if self.shift_places() != 0 {
+            parts.push(format!("{}={}", SHIFT.name, self.shift_places()));
+        }
+        for (set, field) in [
+            (self.halts(), HLT),
+            (self.resets_step_counter(), RST_STEPCOUNTER),
+            (self.stack_incs(), STACK_INC),
+            (self.stack_decs(), STACK_DEC),
+        ] {
+            if set {
+                parts.push(field.name.to_string());
+            }
+        }
+
+        if parts.is_empty() {
+            write!(f, "NOP")
+        } else {
+            write!(f, "{}", parts.join(", "))
+        }
+    }
+}
\ No newline at end of file
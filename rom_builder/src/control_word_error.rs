@@ -0,0 +1,12 @@
+use core::fmt;
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct ControlWordError {
+    pub message: String,
+}
+
+impl fmt::Display for ControlWordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ControlWordError: {}", self.message)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,23 @@
+// An opcode, with how it is written in assembly. Operands are written as
+// `xx` and `yy`, one byte each, in the order they follow the opcode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub opcode: u16,
+    // The name of its constant, such as MOV_BUS_A
+    pub name: String,
+    // How it is written, such as `MOV #xx, @A`
+    pub mnemonic: String,
+    // Bytes in memory, including the opcode
+    pub bytes: usize,
+}
+
+impl Instruction {
+    pub fn new(opcode: u16, name: &str, mnemonic: &str) -> Instruction {
+        Instruction {
+            opcode,
+            name: name.to_string(),
+            mnemonic: mnemonic.to_string(),
+            bytes: 1 + mnemonic.matches("xx").count() + mnemonic.matches("yy").count(),
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,491 @@
+/*
+    This file contains the code to build the control logic
+    ROM for the WH-02 CPU. It creates an array of arrays, all storing
+    the binary representation of the microcode instructions.
+
+    This is indexed by [step][opcode], where step is the current
+    step in the instruction cycle. The opcode is the current opcode
+    corresponding to the instruction being executed.
+
+    All operations begin with a fetch cycle of
+
+    0x00: 0x0376 (PRGC -> MAR)
+    0x01: 0x0789 (RAM -> INST + PRGC++)
+
+    Which fetches the instruction from the RAM and stores it in the
+    instruction register. Then, it increments the address register.
+*/
+
+use std::{fs::File, io::Write};
+
+pub mod control_word;
+pub mod control_word_error;
+pub mod instruction;
+pub mod opcodes;
+pub mod reference;
+
+use control_word::{AluOp, ControlWord, Source, Target};
+use control_word_error::ControlWordError;
+use instruction::Instruction;
+
+// Control signals the step counter reacts to
+pub const RESET_STEP_COUNTER: u32 = ControlWord::new().reset_step_counter().bits();
+pub const HALT: u32 = ControlWord::new().halt().bits();
+
+// The step counter is 3 bits wide, so an instruction that never resets it
+// wraps back to the fetch cycle after 8 steps
+pub const STEPS: u16 = 8;
+
+pub struct RomBuilder {
+    size: usize,
+    pub rom: Vec<u32>,
+    pub instructions: Vec<Instruction>,
+    output: String,
+}
+
+impl RomBuilder {
+    pub fn new(size: usize, output: String) -> RomBuilder {
+        RomBuilder {
+            size,
+            rom: vec![0; size], // Fill with NOP
+            instructions: Vec::new(),
+            output,
+        }
+    }
+
+    pub fn build(&mut self) {
+        self.build_rom();
+        self.write();
+    }
+
+    // The ROM in Logisim's image format
+    pub fn image(&self) -> String {
+        let mut output = String::new();
+        output += "v3.0 hex words addressed\n000: ";
+        let mut counter = 0;
+        let mut address = 0;
+        for byte in &self.rom {
+            output += format!("{:08x} ", byte).as_str();
+            counter += 1;
+            address += 1;
+            if counter % 16 == 0 && address < self.size {
+                output += format!("\n{:03x}: ", address).as_str();
+            }
+        }
+
+        output
+    }
+
+    pub fn write(&self) {
+        let mut outfile = File::create(self.output.clone()).expect("Failed to create output file.");
+        write!(outfile, "{}", self.image()).expect("Faield to write to output file.");
+    }
+
+    pub fn build_rom(&mut self) {
+        // Control signal definitions. Words that move a value between
+        // registers start from `word`, with other signals added on.
+        let word = ControlWord::new();
+        let reset_step_counter = word.reset_step_counter();
+        let enable_program_counter = word.enable_program_counter();
+        let halt = word.halt();
+        let stack_dec = word.stack_dec();
+        let nop = word;
+
+        // Opcode definitions. Each is recorded with its name and how it
+        // is written, for the generated opcode module and reference.
+        self.instructions.clear();
+        self.instruction(0x00, "NOP", "NOP");
+        let mov_a_b = self.instruction(0x01, "MOV_A_B", "MOV @A, @B");
+        let mov_a_c = self.instruction(0x02, "MOV_A_C", "MOV @A, @C");
+        let mov_a_o1 = self.instruction(0x03, "MOV_A_O1", "MOV @A, @O1");
+        let mov_a_o2 = self.instruction(0x04, "MOV_A_O2", "MOV @A, @O2");
+        let mov_a_ram = self.instruction(0x05, "MOV_A_RAM", "MOV @A, $xx");
+        let mov_b_a = self.instruction(0x06, "MOV_B_A", "MOV @B, @A");
+        let mov_b_c = self.instruction(0x07, "MOV_B_C", "MOV @B, @C");
+        let mov_b_o1 = self.instruction(0x08, "MOV_B_O1", "MOV @B, @O1");
+        let mov_b_o2 = self.instruction(0x09, "MOV_B_O2", "MOV @B, @O2");
+        let mov_b_ram = self.instruction(0x0A, "MOV_B_RAM", "MOV @B, $xx");
+        let mov_c_a = self.instruction(0x0B, "MOV_C_A", "MOV @C, @A");
+        let mov_c_b = self.instruction(0x0C, "MOV_C_B", "MOV @C, @B");
+        let mov_c_o1 = self.instruction(0x0D, "MOV_C_O1", "MOV @C, @O1");
+        let mov_c_o2 = self.instruction(0x0E, "MOV_C_O2", "MOV @C, @O2");
+        let mov_c_ram = self.instruction(0x0F, "MOV_C_RAM", "MOV @C, $xx");
+        let mov_o1_a = self.instruction(0x10, "MOV_O1_A", "MOV @O1, @A");
+        let mov_o1_b = self.instruction(0x11, "MOV_O1_B", "MOV @O1, @B");
+        let mov_o1_c = self.instruction(0x12, "MOV_O1_C", "MOV @O1, @C");
+        let mov_o1_o2 = self.instruction(0x13, "MOV_O1_O2", "MOV @O1, @O2");
+        let mov_o1_ram = self.instruction(0x14, "MOV_O1_RAM", "MOV @O1, $xx");
+        let mov_o2_a = self.instruction(0x15, "MOV_O2_A", "MOV @O2, @A");
+        let mov_o2_b = self.instruction(0x16, "MOV_O2_B", "MOV @O2, @B");
+        let mov_o2_c = self.instruction(0x17, "MOV_O2_C", "MOV @O2, @C");
+        let mov_o2_o1 = self.instruction(0x18, "MOV_O2_O1", "MOV @O2, @O1");
+        let mov_o2_ram = self.instruction(0x19, "MOV_O2_RAM", "MOV @O2, $xx");
+        let mov_ram_a = self.instruction(0x1A, "MOV_RAM_A", "MOV $xx, @A");
+        let mov_ram_b = self.instruction(0x1B, "MOV_RAM_B", "MOV $xx, @B");
+        let mov_ram_c = self.instruction(0x1C, "MOV_RAM_C", "MOV $xx, @C");
+        let mov_ram_o1 = self.instruction(0x1D, "MOV_RAM_O1", "MOV $xx, @O1");
+        let mov_ram_o2 = self.instruction(0x1E, "MOV_RAM_O2", "MOV $xx, @O2");
+        let mov_ram_ram = self.instruction(0x1F, "MOV_RAM_RAM", "MOV $xx, $yy");
+        let hlt = self.instruction(0x20, "HLT", "HLT");
+        let mov_bus_a = self.instruction(0x21, "MOV_BUS_A", "MOV #xx, @A");
+        let mov_bus_b = self.instruction(0x22, "MOV_BUS_B", "MOV #xx, @B");
+        let mov_bus_c = self.instruction(0x23, "MOV_BUS_C", "MOV #xx, @C");
+        let mov_bus_o1 = self.instruction(0x24, "MOV_BUS_O1", "MOV #xx, @O1");
+        let mov_bus_o2 = self.instruction(0x25, "MOV_BUS_O2", "MOV #xx, @O2");
+        let mov_bus_ram = self.instruction(0x26, "MOV_BUS_RAM", "MOV #xx, $yy");
+        let mov_acc_a = self.instruction(0x27, "MOV_ACC_A", "MOV @ACC, @A");
+        let mov_acc_b = self.instruction(0x28, "MOV_ACC_B", "MOV @ACC, @B");
+        let mov_acc_c = self.instruction(0x29, "MOV_ACC_C", "MOV @ACC, @C");
+        let mov_acc_o1 = self.instruction(0x2A, "MOV_ACC_O1", "MOV @ACC, @O1");
+        let mov_acc_o2 = self.instruction(0x2B, "MOV_ACC_O2", "MOV @ACC, @O2");
+        let mov_acc_ram = self.instruction(0x2C, "MOV_ACC_RAM", "MOV @ACC, $xx");
+        let jmp = self.instruction(0x2D, "JMP", "JMP $xx");
+        let call = self.instruction(0x2E, "CALL", "CALL $xx");
+        let ret = self.instruction(0x2F, "RET", "RET");
+        let push_a = self.instruction(0x30, "PUSH_A", "PUSH @A");
+        let push_b = self.instruction(0x31, "PUSH_B", "PUSH @B");
+        let push_c = self.instruction(0x32, "PUSH_C", "PUSH @C");
+        let push_o1 = self.instruction(0x33, "PUSH_O1", "PUSH @O1");
+        let push_o2 = self.instruction(0x34, "PUSH_O2", "PUSH @O2");
+        let push_acc = self.instruction(0x35, "PUSH_ACC", "PUSH @ACC");
+        let pop_a = self.instruction(0x36, "POP_A", "POP @A");
+        let pop_b = self.instruction(0x37, "POP_B", "POP @B");
+        let pop_c = self.instruction(0x38, "POP_C", "POP @C");
+        let pop_o1 = self.instruction(0x39, "POP_O1", "POP @O1");
+        let pop_o2 = self.instruction(0x3A, "POP_O2", "POP @O2");
+        let jmp_a = self.instruction(0x3B, "JMP_A", "JMP @A");
+        let jmp_b = self.instruction(0x3C, "JMP_B", "JMP @B");
+        let jmp_c = self.instruction(0x3D, "JMP_C", "JMP @C");
+        let jmp_acc = self.instruction(0x3E, "JMP_ACC", "JMP @ACC");
+        let add = self.instruction(0x3F, "ADD", "ADD");
+        let sub = self.instruction(0x40, "SUB", "SUB");
+        // One opcode for each shift from 1 to 7 places
+        let shl = 0x41;
+        let shr = 0x48;
+
+        // Defining microcode
+        //
+        // All steps begin with a fetch cycle
+        for i in 0..0x100 {
+            self.define(0, i, word.read(Source::PRGC).write(Target::MAR));
+            self.define(1, i, word.read(Source::RAM).write(Target::INST).enable_program_counter());
+        }
+
+        self.define(2, mov_a_b, word.read(Source::A).write(Target::B));
+        self.define(3, mov_a_b, nop);
+        self.define(4, mov_a_b, word.write(Target::ACC));
+        self.define(5, mov_a_b, reset_step_counter);
+
+        self.define(2, mov_a_c, word.read(Source::A).write(Target::C));
+        self.define(3, mov_a_c, reset_step_counter);
+
+        self.define(2, mov_a_o1, word.read(Source::A).write(Target::O1));
+        self.define(3, mov_a_o1, reset_step_counter);
+
+        self.define(2, mov_a_o2, word.read(Source::A).write(Target::O2));
+        self.define(3, mov_a_o2, reset_step_counter);
+
+        self.define(2, mov_a_ram, word.read(Source::INST).write(Target::RAM));
+        self.define(3, mov_a_ram, word.read(Source::A).write(Target::RAM));
+        self.define(4, mov_a_ram, reset_step_counter);
+
+        self.define(2, mov_b_a, word.read(Source::B).write(Target::A));
+        self.define(3, mov_b_a, word.write(Target::ACC));
+        self.define(4, mov_b_a, reset_step_counter);
+
+        self.define(2, mov_b_c, word.read(Source::B).write(Target::C));
+        self.define(3, mov_b_c, reset_step_counter);
+
+        self.define(2, mov_b_o1, word.read(Source::B).write(Target::O1));
+        self.define(3, mov_b_o1, reset_step_counter);
+
+        self.define(2, mov_b_o2, word.read(Source::B).write(Target::O2));
+        self.define(3, mov_b_o2, reset_step_counter);
+
+        self.define(2, mov_b_ram, word.read(Source::INST).write(Target::RAM));
+        self.define(3, mov_b_ram, word.read(Source::B).write(Target::RAM));
+        self.define(4, mov_b_ram, reset_step_counter);
+
+        self.define(2, mov_c_a, word.read(Source::C).write(Target::A));
+        self.define(3, mov_c_a, word.write(Target::ACC));
+        self.define(4, mov_c_a, reset_step_counter);
+
+        self.define(2, mov_c_b, word.read(Source::C).write(Target::B));
+        self.define(3, mov_c_b, word.write(Target::ACC));
+        self.define(4, mov_c_b, reset_step_counter);
+
+        self.define(2, mov_c_o1, word.read(Source::C).write(Target::O1));
+        self.define(3, mov_c_o1, reset_step_counter);
+
+        self.define(2, mov_c_o2, word.read(Source::C).write(Target::O2));
+        self.define(3, mov_c_o2, reset_step_counter);
+
+        self.define(2, mov_c_ram, word.read(Source::INST).write(Target::RAM));
+        self.define(3, mov_c_ram, word.read(Source::C).write(Target::RAM));
+        self.define(4, mov_c_ram, reset_step_counter);
+
+        self.define(2, mov_o1_a, word.read(Source::O1).write(Target::A));
+        self.define(3, mov_o1_a, word.write(Target::ACC));
+        self.define(4, mov_o1_a, reset_step_counter);
+
+        self.define(2, mov_o1_b, word.read(Source::O1).write(Target::B));
+        self.define(3, mov_o1_b, word.write(Target::ACC));
+        self.define(4, mov_o1_b, reset_step_counter);
+
+        self.define(2, mov_o1_c, word.read(Source::O1).write(Target::C));
+        self.define(3, mov_o1_c, reset_step_counter);
+
+        self.define(2, mov_o1_o2, word.read(Source::O1).write(Target::O2));
+        self.define(3, mov_o1_o2, reset_step_counter);
+
+        self.define(2, mov_o1_ram, word.read(Source::INST).write(Target::RAM));
+        self.define(3, mov_o1_ram, word.read(Source::O1).write(Target::RAM));
+
+        self.define(2, mov_o2_a, word.read(Source::O2).write(Target::A));
+        self.define(3, mov_o2_a, word.write(Target::ACC));
+        self.define(4, mov_o2_a, reset_step_counter);
+
+        self.define(2, mov_o2_b, word.read(Source::O2).write(Target::B));
+        self.define(3, mov_o2_b, word.write(Target::ACC));
+        self.define(4, mov_o2_b, reset_step_counter);
+
+        self.define(2, mov_o2_c, word.read(Source::O2).write(Target::C));
+        self.define(3, mov_o2_c, reset_step_counter);
+
+        self.define(2, mov_o2_o1, word.read(Source::O2).write(Target::O1));
+        self.define(3, mov_o2_o1, reset_step_counter);
+
+        self.define(2, mov_o2_ram, word.read(Source::INST).write(Target::RAM));
+        self.define(3, mov_o2_ram, word.read(Source::O2).write(Target::RAM));
+        self.define(4, mov_o2_ram, reset_step_counter);
+
+        self.define(2, mov_ram_a, word.read(Source::RAM).write(Target::MAR));
+        self.define(3, mov_ram_a, word.read(Source::RAM).write(Target::A));
+        self.define(4, mov_ram_a, word.write(Target::ACC));
+        self.define(5, mov_ram_a, reset_step_counter);
+
+        self.define(2, mov_ram_b, word.read(Source::RAM).write(Target::MAR));
+        self.define(3, mov_ram_b, word.read(Source::RAM).write(Target::B));
+        self.define(4, mov_ram_b, word.write(Target::ACC));
+        self.define(5, mov_ram_b, reset_step_counter);
+
+        self.define(2, mov_ram_c, word.read(Source::RAM).write(Target::MAR));
+        self.define(3, mov_ram_c, word.read(Source::RAM).write(Target::C));
+        self.define(4, mov_ram_c, reset_step_counter);
+
+        self.define(2, mov_ram_o1, word.read(Source::RAM).write(Target::MAR));
+        self.define(3, mov_ram_o1, word.read(Source::RAM).write(Target::O1));
+        self.define(4, mov_ram_o1, reset_step_counter);
+
+        self.define(2, mov_ram_o2, word.read(Source::RAM).write(Target::MAR));
+        self.define(3, mov_ram_o2, word.read(Source::RAM).write(Target::O2));
+        self.define(4, mov_ram_o2, reset_step_counter);
+
+        self.define(2, mov_ram_ram, word.read(Source::RAM).write(Target::MAR));
+        self.define(3, mov_ram_ram, word.read(Source::RAM).write(Target::STK).enable_program_counter());
+        self.define(4, mov_ram_ram, word.read(Source::PRGC).write(Target::MAR));
+        self.define(5, mov_ram_ram, word.read(Source::STK).write(Target::RAM).enable_program_counter());
+        self.define(6, mov_ram_ram, reset_step_counter);
+
+        self.define(2, hlt, halt);
+
+        self.define(2, mov_bus_a, word.read(Source::PRGC).write(Target::MAR));
+        self.define(3, mov_bus_a, word.read(Source::RAM).write(Target::A).enable_program_counter());
+        self.define(4, mov_bus_a, word.write(Target::ACC));
+        self.define(5, mov_bus_a, reset_step_counter);
+
+        self.define(2, mov_bus_b, word.read(Source::PRGC).write(Target::MAR));
+        self.define(3, mov_bus_b, word.read(Source::RAM).write(Target::B).enable_program_counter());
+        self.define(4, mov_bus_b, word.write(Target::ACC));
+        self.define(5, mov_bus_b, reset_step_counter);
+
+        self.define(2, mov_bus_c, word.read(Source::PRGC).write(Target::MAR));
+        self.define(3, mov_bus_c, word.read(Source::RAM).write(Target::C).enable_program_counter());
+        self.define(4, mov_bus_c, reset_step_counter);
+
+        self.define(2, mov_bus_o1, word.read(Source::PRGC).write(Target::MAR));
+        self.define(3, mov_bus_o1, word.read(Source::RAM).write(Target::O1).enable_program_counter());
+        self.define(4, mov_bus_o1, reset_step_counter);
+
+        self.define(2, mov_bus_o2, word.read(Source::PRGC).write(Target::MAR));
+        self.define(3, mov_bus_o2, word.read(Source::RAM).write(Target::O2).enable_program_counter());
+        self.define(4, mov_bus_o2, reset_step_counter);
+
+        self.define(2, mov_bus_ram, word.read(Source::PRGC).write(Target::MAR));
+        self.define(3, mov_bus_ram, word.read(Source::RAM).write(Target::STK).enable_program_counter());
+        self.define(4, mov_bus_ram, word.read(Source::PRGC).write(Target::MAR));
+        self.define(5, mov_bus_ram, word.read(Source::STK).write(Target::RAM).enable_program_counter());
+
+        self.define(2, mov_acc_a, word.read(Source::ACC).write(Target::A));
+        self.define(3, mov_acc_a, nop);
+        self.define(4, mov_acc_a, word.write(Target::ACC));
+        self.define(5, mov_acc_a, reset_step_counter);
+
+        self.define(2, mov_acc_b, word.read(Source::ACC).write(Target::B));
+        self.define(3, mov_acc_b, nop);
+        self.define(4, mov_acc_b, word.write(Target::ACC));
+        self.define(5, mov_acc_b, reset_step_counter);
+
+        self.define(2, mov_acc_c, word.read(Source::ACC).write(Target::C));
+        self.define(3, mov_acc_c, reset_step_counter);
+
+        self.define(2, mov_acc_o1, word.read(Source::ACC).write(Target::O1));
+        self.define(3, mov_acc_o1, reset_step_counter);
+
+        self.define(2, mov_acc_o2, word.read(Source::ACC).write(Target::O2));
+        self.define(3, mov_acc_o2, reset_step_counter);
+
+        self.define(2, mov_acc_ram, word.read(Source::PRGC).write(Target::MAR));
+        self.define(3, mov_acc_ram, word.read(Source::RAM).write(Target::STK).enable_program_counter());
+        self.define(4, mov_acc_ram, word.read(Source::PRGC).write(Target::MAR));
+        self.define(5, mov_acc_ram, word.read(Source::STK).write(Target::RAM).enable_program_counter());
+        self.define(6, mov_acc_ram, reset_step_counter);
+
+        self.define(2, jmp, word.read(Source::PRGC).write(Target::MAR));
+        self.define(3, jmp, word.read(Source::RAM).write(Target::STK));
+        self.define(4, jmp, word.read(Source::STK).write(Target::PRGC));
+        self.define(5, jmp, reset_step_counter);
+
+        // The return address is the byte after the target, so step past
+        // the target before pushing. The target then goes through the
+        // free slot above the stack, as JMP does.
+        self.define(2, call, word.read(Source::PRGC).write(Target::MAR));
+        self.define(3, call, enable_program_counter);
+        self.define(4, call, word.read(Source::PRGC).write(Target::STK).stack_inc());
+        self.define(5, call, word.read(Source::RAM).write(Target::STK));
+        self.define(6, call, word.read(Source::STK).write(Target::PRGC));
+        self.define(7, call, reset_step_counter);
+
+        self.define(2, ret, stack_dec);
+        self.define(3, ret, word.read(Source::STK).write(Target::PRGC));
+        self.define(4, ret, reset_step_counter);
+
+        self.define(2, push_a, word.read(Source::A).write(Target::STK).stack_inc());
+        self.define(3, push_a, reset_step_counter);
+
+        self.define(2, push_b, word.read(Source::B).write(Target::STK).stack_inc());
+        self.define(3, push_b, reset_step_counter);
+
+        self.define(2, push_c, word.read(Source::C).write(Target::STK).stack_inc());
+        self.define(3, push_c, reset_step_counter);
+
+        self.define(2, push_o1, word.read(Source::O1).write(Target::STK).stack_inc());
+        self.define(3, push_o1, reset_step_counter);
+
+        self.define(2, push_o2, word.read(Source::O2).write(Target::STK).stack_inc());
+        self.define(3, push_o2, reset_step_counter);
+
+        self.define(2, push_acc, word.read(Source::ACC).write(Target::STK).stack_inc());
+        self.define(3, push_acc, reset_step_counter);
+
+        self.define(2, pop_a, stack_dec);
+        self.define(3, pop_a, word.read(Source::STK).write(Target::A));
+        self.define(4, pop_a, word.write(Target::ACC));
+        self.define(5, pop_a, reset_step_counter);
+
+        self.define(2, pop_b, stack_dec);
+        self.define(3, pop_b, word.read(Source::STK).write(Target::B));
+        self.define(4, pop_b, word.write(Target::ACC));
+        self.define(5, pop_b, reset_step_counter);
+
+        self.define(2, pop_c, stack_dec);
+        self.define(3, pop_c, word.read(Source::STK).write(Target::C));
+        self.define(4, pop_c, reset_step_counter);
+
+        self.define(2, pop_o1, stack_dec);
+        self.define(3, pop_o1, word.read(Source::STK).write(Target::O1));
+        self.define(4, pop_o1, reset_step_counter);
+
+        self.define(2, pop_o2, stack_dec);
+        self.define(3, pop_o2, word.read(Source::STK).write(Target::O2));
+        self.define(4, pop_o2, reset_step_counter);
+
+        self.define(2, jmp_a, word.read(Source::A).write(Target::PRGC));
+        self.define(3, jmp_a, reset_step_counter);
+
+        self.define(2, jmp_b, word.read(Source::B).write(Target::PRGC));
+        self.define(3, jmp_b, reset_step_counter);
+
+        self.define(2, jmp_c, word.read(Source::C).write(Target::PRGC));
+        self.define(3, jmp_c, reset_step_counter);
+
+        self.define(2, jmp_acc, word.read(Source::ACC).write(Target::PRGC));
+        self.define(3, jmp_acc, reset_step_counter);
+
+        self.define(2, add, word.write(Target::ACC).alu(AluOp::Add));
+        self.define(3, add, reset_step_counter);
+
+        self.define(2, sub, word.write(Target::ACC).alu(AluOp::Sub));
+        self.define(3, sub, reset_step_counter);
+
+        for places in 1..8u16 {
+            self.instruction(shl + places - 1, &format!("SHL_{}", places), &format!("SHL #{:02X}", places));
+            self.instruction(shr + places - 1, &format!("SHR_{}", places), &format!("SHR #{:02X}", places));
+
+            self.define(2, shl + places - 1, word.write(Target::ACC).alu(AluOp::Shl).shift(places as u32));
+            self.define(3, shl + places - 1, reset_step_counter);
+
+            self.define(2, shr + places - 1, word.write(Target::ACC).alu(AluOp::Shr).shift(places as u32));
+            self.define(3, shr + places - 1, reset_step_counter);
+        }
+    }
+
+    pub fn control_word(&self, step: u16, opcode: u16) -> u32 {
+        self.rom[self.get_address(step, opcode) as usize]
+    }
+
+    // Clock cycles an instruction takes, including its fetch cycle. The
+    // step that resets the step counter (or halts) is the last one counted.
+    pub fn cycles(&self, opcode: u16) -> usize {
+        for step in 0..STEPS {
+            if self.control_word(step, opcode) & (RESET_STEP_COUNTER | HALT) != 0 {
+                return step as usize + 1;
+            }
+        }
+
+        STEPS as usize
+    }
+
+    // Checks every word in the ROM against the control word bit
+    // allocation, returning an error for each word that does not fit
+    pub fn check(&self) -> Result<(), Vec<ControlWordError>> {
+        let mut errors = Vec::new();
+        for (address, bits) in self.rom.iter().enumerate() {
+            if let Err(mut error) = ControlWord::from_bits(*bits) {
+                error.message = format!("Step {}, opcode {:02X}: {}", address >> 8, address & 0xFF, error.message);
+                errors.push(error);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn instruction(&mut self, opcode: u16, name: &str, mnemonic: &str) -> u16 {
+        self.instructions.push(Instruction::new(opcode, name, mnemonic));
+        opcode
+    }
+
+    fn define(&mut self, step: u16, opcode: u16, word: ControlWord) {
+        let address = self.get_address(step, opcode);
+        self.rom[address as usize] = word.bits();
+    }
+
+    fn get_address(&self, step: u16, opcode: u16) -> u16 {
+        // Addresses are of the form 0b000_0000_0000,
+        // where the 3 MSB are the step and the 8 LSB
+        // are the opcode. When given a step as a number,
+        // We must shift it left 8 bits to make room for
+        // the opcode.
+        (step << 8) | opcode
+    }
+}
+
+impl Default for RomBuilder {
+    fn default() -> Self {
+        RomBuilder::new(0x800, "rom.bin".to_string())
+    }
+}
\ No newline at end of file
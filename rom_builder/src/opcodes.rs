@@ -0,0 +1,186 @@
+/*
+    Opcodes and control signals of the WH-02.
+
+    Generated by rom_builder from the microcode; do not edit. Run
+    `cargo run` in the rom_builder folder to regenerate it.
+*/
+
+// Opcodes
+pub const NOP: u8 = 0x00;
+pub const MOV_A_B: u8 = 0x01;
+pub const MOV_A_C: u8 = 0x02;
+pub const MOV_A_O1: u8 = 0x03;
+pub const MOV_A_O2: u8 = 0x04;
+pub const MOV_A_RAM: u8 = 0x05;
+pub const MOV_B_A: u8 = 0x06;
+pub const MOV_B_C: u8 = 0x07;
+pub const MOV_B_O1: u8 = 0x08;
+pub const MOV_B_O2: u8 = 0x09;
+pub const MOV_B_RAM: u8 = 0x0A;
+pub const MOV_C_A: u8 = 0x0B;
+pub const MOV_C_B: u8 = 0x0C;
+pub const MOV_C_O1: u8 = 0x0D;
+pub const MOV_C_O2: u8 = 0x0E;
+pub const MOV_C_RAM: u8 = 0x0F;
+pub const MOV_O1_A: u8 = 0x10;
+pub const MOV_O1_B: u8 = 0x11;
+pub const MOV_O1_C: u8 = 0x12;
+pub const MOV_O1_O2: u8 = 0x13;
+pub const MOV_O1_RAM: u8 = 0x14;
+pub const MOV_O2_A: u8 = 0x15;
+pub const MOV_O2_B: u8 = 0x16;
+pub const MOV_O2_C: u8 = 0x17;
+pub const MOV_O2_O1: u8 = 0x18;
+pub const MOV_O2_RAM: u8 = 0x19;
+pub const MOV_RAM_A: u8 = 0x1A;
+pub const MOV_RAM_B: u8 = 0x1B;
+pub const MOV_RAM_C: u8 = 0x1C;
+pub const MOV_RAM_O1: u8 = 0x1D;
+pub const MOV_RAM_O2: u8 = 0x1E;
+pub const MOV_RAM_RAM: u8 = 0x1F;
+pub const HLT: u8 = 0x20;
+pub const MOV_BUS_A: u8 = 0x21;
+pub const MOV_BUS_B: u8 = 0x22;
+pub const MOV_BUS_C: u8 = 0x23;
+pub const MOV_BUS_O1: u8 = 0x24;
+pub const MOV_BUS_O2: u8 = 0x25;
+pub const MOV_BUS_RAM: u8 = 0x26;
+pub const MOV_ACC_A: u8 = 0x27;
+pub const MOV_ACC_B: u8 = 0x28;
+pub const MOV_ACC_C: u8 = 0x29;
+pub const MOV_ACC_O1: u8 = 0x2A;
+pub const MOV_ACC_O2: u8 = 0x2B;
+pub const MOV_ACC_RAM: u8 = 0x2C;
+pub const JMP: u8 = 0x2D;
+pub const CALL: u8 = 0x2E;
+pub const RET: u8 = 0x2F;
+pub const PUSH_A: u8 = 0x30;
+pub const PUSH_B: u8 = 0x31;
+pub const PUSH_C: u8 = 0x32;
+pub const PUSH_O1: u8 = 0x33;
+pub const PUSH_O2: u8 = 0x34;
+pub const PUSH_ACC: u8 = 0x35;
+pub const POP_A: u8 = 0x36;
+pub const POP_B: u8 = 0x37;
+pub const POP_C: u8 = 0x38;
+pub const POP_O1: u8 = 0x39;
+pub const POP_O2: u8 = 0x3A;
+pub const JMP_A: u8 = 0x3B;
+pub const JMP_B: u8 = 0x3C;
+pub const JMP_C: u8 = 0x3D;
+pub const JMP_ACC: u8 = 0x3E;
+pub const ADD: u8 = 0x3F;
+pub const SUB: u8 = 0x40;
+pub const SHL_1: u8 = 0x41;
+pub const SHL_2: u8 = 0x42;
+pub const SHL_3: u8 = 0x43;
+pub const SHL_4: u8 = 0x44;
+pub const SHL_5: u8 = 0x45;
+pub const SHL_6: u8 = 0x46;
+pub const SHL_7: u8 = 0x47;
+pub const SHR_1: u8 = 0x48;
+pub const SHR_2: u8 = 0x49;
+pub const SHR_3: u8 = 0x4A;
+pub const SHR_4: u8 = 0x4B;
+pub const SHR_5: u8 = 0x4C;
+pub const SHR_6: u8 = 0x4D;
+pub const SHR_7: u8 = 0x4E;
+
+// Every opcode, as it is written and how many bytes it takes
+pub const INSTRUCTIONS: [(u8, &str, usize); 79] = [
+    (NOP, "NOP", 1),
+    (MOV_A_B, "MOV @A, @B", 1),
+    (MOV_A_C, "MOV @A, @C", 1),
+    (MOV_A_O1, "MOV @A, @O1", 1),
+    (MOV_A_O2, "MOV @A, @O2", 1),
+    (MOV_A_RAM, "MOV @A, $xx", 2),
+    (MOV_B_A, "MOV @B, @A", 1),
+    (MOV_B_C, "MOV @B, @C", 1),
+    (MOV_B_O1, "MOV @B, @O1", 1),
+    (MOV_B_O2, "MOV @B, @O2", 1),
+    (MOV_B_RAM, "MOV @B, $xx", 2),
+    (MOV_C_A, "MOV @C, @A", 1),
+    (MOV_C_B, "MOV @C, @B", 1),
+    (MOV_C_O1, "MOV @C, @O1", 1),
+    (MOV_C_O2, "MOV @C, @O2", 1),
+    (MOV_C_RAM, "MOV @C, $xx", 2),
+    (MOV_O1_A, "MOV @O1, @A", 1),
+    (MOV_O1_B, "MOV @O1, @B", 1),
+    (MOV_O1_C, "MOV @O1, @C", 1),
+    (MOV_O1_O2, "MOV @O1, @O2", 1),
+    (MOV_O1_RAM, "MOV @O1, $xx", 2),
+    (MOV_O2_A, "MOV @O2, @A", 1),
+    (MOV_O2_B, "MOV @O2, @B", 1),
+    (MOV_O2_C, "MOV @O2, @C", 1),
+    (MOV_O2_O1, "MOV @O2, @O1", 1),
+    (MOV_O2_RAM, "MOV @O2, $xx", 2),
+    (MOV_RAM_A, "MOV $xx, @A", 2),
+    (MOV_RAM_B, "MOV $xx, @B", 2),
+    (MOV_RAM_C, "MOV $xx, @C", 2),
+    (MOV_RAM_O1, "MOV $xx, @O1", 2),
+    (MOV_RAM_O2, "MOV $xx, @O2", 2),
+    (MOV_RAM_RAM, "MOV $xx, $yy", 3),
+    (HLT, "HLT", 1),
+    (MOV_BUS_A, "MOV #xx, @A", 2),
+    (MOV_BUS_B, "MOV #xx, @B", 2),
+    (MOV_BUS_C, "MOV #xx, @C", 2),
+    (MOV_BUS_O1, "MOV #xx, @O1", 2),
+    (MOV_BUS_O2, "MOV #xx, @O2", 2),
+    (MOV_BUS_RAM, "MOV #xx, $yy", 3),
+    (MOV_ACC_A, "MOV @ACC, @A", 1),
+    (MOV_ACC_B, "MOV @ACC, @B", 1),
+    (MOV_ACC_C, "MOV @ACC, @C", 1),
+    (MOV_ACC_O1, "MOV @ACC, @O1", 1),
+    (MOV_ACC_O2, "MOV @ACC, @O2", 1),
+    (MOV_ACC_RAM, "MOV @ACC, $xx", 2),
+    (JMP, "JMP $xx", 2),
+    (CALL, "CALL $xx", 2),
+    (RET, "RET", 1),
+    (PUSH_A, "PUSH @A", 1),
+    (PUSH_B, "PUSH @B", 1),
+    (PUSH_C, "PUSH @C", 1),
+    (PUSH_O1, "PUSH @O1", 1),
+    (PUSH_O2, "PUSH @O2", 1),
+    (PUSH_ACC, "PUSH @ACC", 1),
+    (POP_A, "POP @A", 1),
+    (POP_B, "POP @B", 1),
+    (POP_C, "POP @C", 1),
+    (POP_O1, "POP @O1", 1),
+    (POP_O2, "POP @O2", 1),
+    (JMP_A, "JMP @A", 1),
+    (JMP_B, "JMP @B", 1),
+    (JMP_C, "JMP @C", 1),
+    (JMP_ACC, "JMP @ACC", 1),
+    (ADD, "ADD", 1),
+    (SUB, "SUB", 1),
+    (SHL_1, "SHL #01", 1),
+    (SHL_2, "SHL #02", 1),
+    (SHL_3, "SHL #03", 1),
+    (SHL_4, "SHL #04", 1),
+    (SHL_5, "SHL #05", 1),
+    (SHL_6, "SHL #06", 1),
+    (SHL_7, "SHL #07", 1),
+    (SHR_1, "SHR #01", 1),
+    (SHR_2, "SHR #02", 1),
+    (SHR_3, "SHR #03", 1),
+    (SHR_4, "SHR #04", 1),
+    (SHR_5, "SHR #05", 1),
+    (SHR_6, "SHR #06", 1),
+    (SHR_7, "SHR #07", 1),
+];
+
+// Control word fields, as masks. Named after the tunnels they drive
+// in WH02.circ, so HLT here is the signal rather than the opcode.
+pub mod signals {
+    pub const OUTPUT_SELECT: u32 = 0x0000F;
+    pub const INPUT_SELECT: u32 = 0x000F0;
+    pub const OUTPUT_ENABLE: u32 = 0x00100;
+    pub const INPUT_ENABLE: u32 = 0x00200;
+    pub const ENABLE_PRGC: u32 = 0x00400;
+    pub const SCT: u32 = 0x01800;
+    pub const SFT: u32 = 0x0E000;
+    pub const HLT: u32 = 0x10000;
+    pub const RST_STEPCOUNTER: u32 = 0x20000;
+    pub const STACK_INC: u32 = 0x40000;
+    pub const STACK_DEC: u32 = 0x80000;
+}
\ No newline at end of file
@@ -0,0 +1,150 @@
+/*
+    Writes what the microcode defines in forms other tools can read: a
+    Rust module of opcode and control signal constants for the assembler
+    and emulator, and a Markdown reference of every instruction.
+
+    Both are generated from a built ROM, so they always match rom.bin.
+    `cargo run` writes them next to rom.bin, and `cargo run -- --check`
+    reports any that are out of date. `cargo test` fails if they are.
+*/
+
+use crate::control_word::{ControlWord, FIELDS};
+use crate::instruction::Instruction;
+use crate::RomBuilder;
+
+// Where the generated files go, from the rom_builder folder
+pub const ROM: &str = "rom.bin";
+pub const OPCODES_MODULE: &str = "src/opcodes.rs";
+pub const REFERENCE: &str = "Opcodes.md";
+
+impl RomBuilder {
+    // The Rust module of constants, for `rom_builder::opcodes`
+    pub fn opcodes_module(&self) -> String {
+        let instructions = self.sorted_instructions();
+
+        let mut output = String::new();
+        output += "/*\n";
+        output += "    Opcodes and control signals of the WH-02.\n\n";
+        output += "    Generated by rom_builder from the microcode; do not edit. Run\n";
+        output += "    `cargo run` in the rom_builder folder to regenerate it.\n";
+        output += "*/\n\n";
+
+        output += "// Opcodes\n";
+        for instruction in &instructions {
+            output += &format!("pub const {}: u8 = 0x{:02X};\n", instruction.name, instruction.opcode);
+        }
+
+        output += "\n// Every opcode, as it is written and how many bytes it takes\n";
+        output += &format!("pub const INSTRUCTIONS: [(u8, &str, usize); {}] = [\n", instructions.len());
+        for instruction in &instructions {
+            output += &format!("    ({}, \"{}\", {}),\n", instruction.name, instruction.mnemonic, instruction.bytes);
+        }
+        output += "];\n";
+
+        output += "\n// Control word fields, as masks. Named after the tunnels they drive\n";
+        output += "// in WH02.circ, so HLT here is the signal rather than the opcode.\n";
+        output += "pub mod signals {\n";
+        for field in FIELDS {
+            output += &format!("    pub const {}: u32 = 0x{:05X};\n", field.name, field.mask());
+        }
+        output += "}\n";
+
+        output.trim_end().to_string()
+    }
+
+    // The Markdown reference of every opcode and its microcode
+    pub fn reference(&self) -> String {
+        let mut output = String::new();
+        output += "# WH-02 Opcodes\n\n";
+        output += "Generated by rom_builder from the microcode; do not edit. Run `cargo run` in the rom_builder folder to\n";
+        output += "regenerate it.\n\n";
+
+        output += "## Control word\n\n";
+        output += "| Bits | Signal | Mask |\n";
+        output += "| --- | --- | --- |\n";
+        for field in FIELDS {
+            let bits = if field.width == 1 {
+                format!("{}", field.offset)
+            } else {
+                format!("{}-{}", field.offset, field.offset + field.width - 1)
+            };
+            output += &format!("| {} | {} | `0x{:05X}` |\n", bits, field.name, field.mask());
+        }
+
+        output += "\n## Instructions\n\n";
+        output += &format!(
+            "Every instruction starts with the same fetch cycle: step 0 is `{}` and step 1 is `{}`. The steps below\n",
+            self.decoded(0, 0),
+            self.decoded(1, 0),
+        );
+        output += "come after it. Cycles include the fetch cycle.\n\n";
+        output += "| Opcode | Name | Assembly | Bytes | Cycles | Microcode |\n";
+        output += "| --- | --- | --- | --- | --- | --- |\n";
+        for instruction in self.sorted_instructions() {
+            let cycles = self.cycles(instruction.opcode);
+            let steps: Vec<String> = (2..cycles as u16)
+                .map(|step| format!(
+                    "{}: `{}` `0x{:05X}`",
+                    step,
+                    self.decoded(step, instruction.opcode),
+                    self.control_word(step, instruction.opcode),
+                ))
+                .collect();
+
+            output += &format!(
+                "| `{:02X}` | {} | `{}` | {} | {} | {} |\n",
+                instruction.opcode,
+                instruction.name,
+                instruction.mnemonic,
+                instruction.bytes,
+                cycles,
+                if steps.is_empty() { "-".to_string() } else { steps.join("<br>") },
+            );
+        }
+
+        output.trim_end().to_string()
+    }
+
+    fn sorted_instructions(&self) -> Vec<&Instruction> {
+        let mut instructions: Vec<&Instruction> = self.instructions.iter().collect();
+        instructions.sort_by_key(|instruction| instruction.opcode);
+        instructions
+    }
+
+    // A step of the microcode as text, or its bits if they do not fit
+    // the control word
+    fn decoded(&self, step: u16, opcode: u16) -> String {
+        let bits = self.control_word(step, opcode);
+        match ControlWord::from_bits(bits) {
+            Ok(word) => word.to_string(),
+            Err(_) => format!("0x{:05X}", bits),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+
+    // The assembler and emulator use the checked-in files, so editing
+    // the microcode without regenerating them would leave both wrong
+    #[test]
+    fn generated_files_are_up_to_date() {
+        let mut rom = RomBuilder::new(0x800, ROM.to_string());
+        rom.build_rom();
+        assert!(rom.check().is_ok());
+
+        let outputs = [
+            (ROM, rom.image()),
+            (OPCODES_MODULE, rom.opcodes_module()),
+            (REFERENCE, rom.reference()),
+        ];
+        for (path, contents) in outputs {
+            let found = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join(path)).unwrap_or_default();
+            assert!(found == contents, "{} is out of date. Run `cargo run` in rom_builder to regenerate it", path);
+        }
+    }
+}
\ No newline at end of file